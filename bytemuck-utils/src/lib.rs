@@ -3,7 +3,7 @@
 // bytes with unknown alignment
 
 use core::slice;
-use std::{mem::MaybeUninit, ops::Deref};
+use std::{error::Error, fmt::Display, mem::MaybeUninit, ops::Deref};
 
 use bytemuck::{Pod, PodCastError};
 
@@ -13,11 +13,21 @@ pub enum PodData<'data, T: Pod> {
   Owned(T)
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FromBytesError {
   SizeMismatch
 }
 
+impl Display for FromBytesError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      FromBytesError::SizeMismatch => write!(f, "size of bytes does not match the type")
+    }
+  }
+}
+
+impl Error for FromBytesError {}
+
 impl<'data, T: Pod> PodData<'data, T> {
   pub fn as_bytes(this: &Self) -> &[u8] {
     match this {
@@ -0,0 +1,211 @@
+// #[derive(BinderWrite, BinderRead)] for libbinder::packet::encoding
+//
+// Generated impls are generic over the writer/reader so they work
+// with both libbinder's and libbinder-runtime's. Use
+// #[binder(crate = path)] if encoding module is somewhere else
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Fields, GenericParam, Ident, Lifetime, LifetimeParam, Path, Type, TypeParam, parse_macro_input, parse_quote, spanned::Spanned};
+
+#[proc_macro_derive(BinderWrite, attributes(binder))]
+pub fn derive_binder_write(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  expand_write(&input)
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
+
+#[proc_macro_derive(BinderRead, attributes(binder))]
+pub fn derive_binder_read(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  expand_read(&input)
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
+
+fn crate_path(input: &DeriveInput) -> syn::Result<Path> {
+  let mut path = parse_quote!(::libbinder::packet::encoding);
+  for attr in input.attrs.iter().filter(|x| x.path().is_ident("binder")) {
+    attr.parse_nested_meta(|meta| {
+      if meta.path.is_ident("crate") {
+        path = meta.value()?.parse()?;
+        Ok(())
+      } else {
+        Err(meta.error("unknown binder attribute"))
+      }
+    })?;
+  }
+  Ok(path)
+}
+
+fn field_types(data: &Data) -> Vec<&Type> {
+  match data {
+    Data::Struct(x) => x.fields.iter().map(|f| &f.ty).collect(),
+    Data::Enum(x) => x.variants.iter().flat_map(|v| v.fields.iter().map(|f| &f.ty)).collect(),
+    Data::Union(_) => Vec::new()
+  }
+}
+
+// Each variant gets its u32 discriminant as const __D<n>, ones without
+// explicit discriminant are previous plus one like Rust does
+fn discriminants(data: &syn::DataEnum) -> TokenStream2 {
+  let mut consts = TokenStream2::new();
+  for (i, variant) in data.variants.iter().enumerate() {
+    let name = format_ident!("__D{}", i);
+    let value = match (&variant.discriminant, i) {
+      (Some((_, expr)), _) => quote!((#expr) as u32),
+      (None, 0) => quote!(0),
+      (None, _) => {
+        let prev = format_ident!("__D{}", i - 1);
+        quote!(#prev + 1)
+      }
+    };
+    consts.extend(quote!(const #name: u32 = #value;));
+  }
+  consts
+}
+
+// Names to bind the fields as, and pattern which binds them
+fn bind_fields(fields: &Fields) -> (Vec<Ident>, TokenStream2) {
+  let names: Vec<Ident> = (0..fields.len()).map(|i| format_ident!("__f{}", i)).collect();
+  let pattern = match fields {
+    Fields::Named(x) => {
+      let members = x.named.iter().map(|f| f.ident.as_ref().unwrap());
+      quote!({ #(#members: #names),* })
+    },
+    Fields::Unnamed(_) => quote!(( #(#names),* )),
+    Fields::Unit => quote!()
+  };
+  (names, pattern)
+}
+
+fn no_union(input: &DeriveInput) -> syn::Result<()> {
+  match &input.data {
+    Data::Union(_) => Err(syn::Error::new(input.span(), "unions can't be written to or read from packet")),
+    _ => Ok(())
+  }
+}
+
+fn expand_write(input: &DeriveInput) -> syn::Result<TokenStream2> {
+  no_union(input)?;
+  let krate = crate_path(input)?;
+  let name = &input.ident;
+  
+  let packet = Lifetime::new("'__packet", Span::call_site());
+  let mut generics = input.generics.clone();
+  generics.params.insert(0, GenericParam::Lifetime(LifetimeParam::new(packet.clone())));
+  generics.params.push(GenericParam::Type(TypeParam::from(format_ident!("__W"))));
+  let where_clause = generics.make_where_clause();
+  where_clause.predicates.push(parse_quote!(__W: #krate::PacketWriter + ?Sized));
+  for ty in field_types(&input.data) {
+    where_clause.predicates.push(parse_quote!(#ty: #krate::BinderWrite<#packet, __W>));
+  }
+  let (impl_generics, _, where_clause) = generics.split_for_impl();
+  let (_, ty_generics, _) = input.generics.split_for_impl();
+  
+  let body = match &input.data {
+    Data::Struct(data) => {
+      let (names, pattern) = bind_fields(&data.fields);
+      quote! {
+        let Self #pattern = self;
+        #(#krate::BinderWrite::<#packet, __W>::write_to(#names, writer);)*
+      }
+    },
+    Data::Enum(data) => {
+      let consts = discriminants(data);
+      let arms = data.variants.iter().enumerate().map(|(i, variant)| {
+        let ident = &variant.ident;
+        let discriminant = format_ident!("__D{}", i);
+        let (names, pattern) = bind_fields(&variant.fields);
+        quote! {
+          Self::#ident #pattern => {
+            #krate::PacketWriter::write_u32(writer, #discriminant);
+            #(#krate::BinderWrite::<#packet, __W>::write_to(#names, writer);)*
+          }
+        }
+      });
+      quote! {
+        #consts
+        match self {
+          #(#arms)*
+        }
+      }
+    },
+    Data::Union(_) => unreachable!()
+  };
+  
+  Ok(quote! {
+    #[automatically_derived]
+    impl #impl_generics #krate::BinderWrite<#packet, __W> for #name #ty_generics #where_clause {
+      #[allow(unused_variables)]
+      fn write_to(&#packet self, writer: &mut __W) {
+        #body
+      }
+    }
+  })
+}
+
+fn expand_read(input: &DeriveInput) -> syn::Result<TokenStream2> {
+  no_union(input)?;
+  let krate = crate_path(input)?;
+  let name = &input.ident;
+  
+  let packet = Lifetime::new("'__packet", Span::call_site());
+  let mut generics = input.generics.clone();
+  generics.params.insert(0, GenericParam::Lifetime(LifetimeParam::new(packet.clone())));
+  generics.params.push(GenericParam::Type(TypeParam::from(format_ident!("__R"))));
+  let where_clause = generics.make_where_clause();
+  where_clause.predicates.push(parse_quote!(__R: #krate::PacketReader<#packet> + ?Sized));
+  for ty in field_types(&input.data) {
+    where_clause.predicates.push(parse_quote!(#ty: #krate::BinderRead<#packet, __R>));
+  }
+  let (impl_generics, _, where_clause) = generics.split_for_impl();
+  let (_, ty_generics, _) = input.generics.split_for_impl();
+  
+  // Fields are read in order before constructing
+  let construct = |path: TokenStream2, fields: &Fields| {
+    let (names, pattern) = bind_fields(fields);
+    quote! {
+      #(let #names = #krate::BinderRead::<#packet, __R>::read_from(reader)?;)*
+      ::core::result::Result::Ok(#path #pattern)
+    }
+  };
+  
+  let body = match &input.data {
+    Data::Struct(data) => construct(quote!(Self), &data.fields),
+    Data::Enum(data) => {
+      let consts = discriminants(data);
+      let arms = data.variants.iter().enumerate().map(|(i, variant)| {
+        let ident = &variant.ident;
+        let discriminant = format_ident!("__D{}", i);
+        let construct = construct(quote!(Self::#ident), &variant.fields);
+        quote! {
+          #discriminant => {
+            #construct
+          }
+        }
+      });
+      quote! {
+        #consts
+        let offset = #krate::PacketReader::get_current_offset(reader);
+        match #krate::PacketReader::read_u32(reader)? {
+          #(#arms)*
+          _ => ::core::result::Result::Err(#krate::ReadError::InvalidValue { offset })
+        }
+      }
+    },
+    Data::Union(_) => unreachable!()
+  };
+  
+  Ok(quote! {
+    #[automatically_derived]
+    impl #impl_generics #krate::BinderRead<#packet, __R> for #name #ty_generics #where_clause {
+      #[allow(unused_variables)]
+      fn read_from(reader: &mut __R) -> ::core::result::Result<Self, #krate::ReadError> {
+        #body
+      }
+    }
+  })
+}
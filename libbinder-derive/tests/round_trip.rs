@@ -0,0 +1,164 @@
+use std::{ffi::CString, fs::File, os::fd::AsFd, path::Path, sync::Arc};
+
+use libbinder::{formats::{ReadError, dead_simple::{DeadSimpleFormat, DeadSimpleFormatReader}}, packet::{builder::PacketBuilder, encoding::{BinderRead, BinderWrite}}};
+use libbinder_raw::device::BinderDevice;
+use libbinder_runtime::{new_proxy_manager, object::{FromProxy, FromProxyError, Object, TransactionError}, packet::Packet, proxy::{Proxy, SelfMananger}, reference::Reference};
+
+#[derive(Debug, PartialEq, BinderWrite, BinderRead)]
+struct Point {
+  x: i32,
+  y: i32
+}
+
+#[derive(Debug, PartialEq, BinderWrite, BinderRead)]
+struct Wrapper(u8, bool);
+
+#[derive(Debug, PartialEq, BinderWrite, BinderRead)]
+struct Empty;
+
+#[derive(Debug, PartialEq, BinderWrite, BinderRead)]
+#[repr(u32)]
+enum Shape {
+  Nothing,
+  Circle { center: Point, radius: f32 } = 5,
+  Polygon(Vec<Point>),
+  Named(String, Option<CString>) = 0x100
+}
+
+#[derive(Debug, PartialEq, BinderWrite, BinderRead)]
+struct Scene<'a> {
+  name: &'a str,
+  origin: Point,
+  shapes: Vec<Shape>,
+  tag: Option<Wrapper>,
+  missing: Option<Box<Point>>,
+  empty: Empty,
+  data: &'a [u8]
+}
+
+// Writes then reads back, expecting same value and nothing left
+macro_rules! round_trip {
+  ($value:expr) => {{
+    let value = $value;
+    let dev = File::open("/dev/null").unwrap();
+    let mut builder = PacketBuilder::new(dev.as_fd());
+    builder.set_code(1);
+    
+    let mut writer = builder.writer(DeadSimpleFormat::new());
+    value.write_to(&mut writer);
+    drop(writer);
+    
+    let packet = builder.build().unwrap();
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    assert_eq!(BinderRead::read_from(&mut reader), Ok(value));
+    assert!(reader.read_u8().is_err());
+  }};
+}
+
+#[test]
+fn structs() {
+  round_trip!(Point { x: -1, y: 2 });
+  round_trip!(Wrapper(7, true));
+  round_trip!(Empty);
+}
+
+#[test]
+fn enums() {
+  round_trip!(Shape::Nothing);
+  round_trip!(Shape::Circle { center: Point { x: 3, y: 4 }, radius: 1.5 });
+  round_trip!(Shape::Polygon(vec![Point { x: 0, y: 0 }, Point { x: 1, y: 1 }]));
+  round_trip!(Shape::Named("hi".to_string(), Some(CString::new("there").unwrap())));
+  round_trip!(Shape::Named(String::new(), None));
+}
+
+#[test]
+fn enum_discriminants() {
+  let dev = File::open("/dev/null").unwrap();
+  let mut builder = PacketBuilder::new(dev.as_fd());
+  builder.set_code(1);
+  
+  let mut writer = builder.writer(DeadSimpleFormat::new());
+  Shape::Polygon(Vec::new()).write_to(&mut writer);
+  Shape::Named(String::new(), None).write_to(&mut writer);
+  writer.write_u32(2);
+  drop(writer);
+  
+  let packet = builder.build().unwrap();
+  let mut reader = packet.reader(DeadSimpleFormatReader::new());
+  
+  // Polygon comes after explicit 5
+  assert_eq!(reader.read_u32(), Ok(6));
+  assert_eq!(reader.read_u32(), Ok(0));
+  assert_eq!(reader.read_u32(), Ok(0x100));
+  reader.read_str().unwrap();
+  assert_eq!(reader.read_bool(), Ok(false));
+  
+  let offset = reader.get_current_offset();
+  assert_eq!(Shape::read_from(&mut reader), Err(ReadError::InvalidValue { offset }));
+}
+
+#[test]
+fn nested() {
+  let bytes = [1, 2, 3];
+  round_trip!(Scene {
+    name: "scene",
+    origin: Point { x: 10, y: 20 },
+    shapes: vec![
+      Shape::Nothing,
+      Shape::Circle { center: Point { x: 0, y: 0 }, radius: 2.0 }
+    ],
+    tag: Some(Wrapper(1, false)),
+    missing: None,
+    empty: Empty,
+    data: &bytes
+  });
+}
+
+struct Dummy;
+
+impl Object<SelfMananger> for Dummy {
+  fn do_transaction<'runtime>(&self, _packet: &Packet<'runtime, SelfMananger>) -> Result<Option<Packet<'runtime, SelfMananger>>, TransactionError> {
+    Ok(None)
+  }
+}
+
+impl FromProxy<SelfMananger> for Dummy {
+  fn from_proxy(_proxy: Proxy<SelfMananger>) -> Result<Self, FromProxyError> {
+    Err(FromProxyError::WrongInterface)
+  }
+}
+
+// Only runtime's writer and reader can do the reference
+#[derive(BinderWrite, BinderRead)]
+struct Named {
+  name: String,
+  object: Reference<SelfMananger, Dummy>
+}
+
+#[test]
+#[ignore = "needs /dev/binder"]
+fn reference_field() {
+  let dev = BinderDevice::open(Path::new("/dev/binder")).unwrap();
+  let rt = new_proxy_manager(dev).unwrap();
+  
+  let obj = Arc::new(Dummy);
+  let value = Named {
+    name: "dummy".to_string(),
+    object: Reference::from_local(rt.clone(), obj.clone())
+  };
+  
+  let mut builder = rt.new_packet();
+  builder.set_code(1);
+  let mut writer = builder.writer(DeadSimpleFormat::new());
+  value.write_to(&mut writer);
+  drop(writer);
+  
+  let packet = builder.build().unwrap();
+  assert_eq!(packet.iter_references().count(), 1);
+  
+  let mut reader = packet.reader(DeadSimpleFormatReader::new());
+  let result = Named::read_from(&mut reader).unwrap();
+  assert_eq!(result.name, "dummy");
+  assert!(Arc::ptr_eq(result.object.get(), &obj));
+  assert!(reader.read_u8().is_err());
+}
@@ -0,0 +1,90 @@
+use std::{ffi::CStr, fs::{self, File}, io, os::fd::AsRawFd, path::{Path, PathBuf}};
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::ioctl;
+
+const BINDERFS_MAX_NAME: usize = 255;
+
+// Equivalent to struct binderfs_device
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct BinderfsDeviceRaw {
+  name: [u8; BINDERFS_MAX_NAME + 1],
+  major: u32,
+  minor: u32
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BinderfsDevice {
+  pub name: String,
+  pub major: u32,
+  pub minor: u32,
+  
+  // Path to the device node inside the binderfs mount
+  pub path: PathBuf
+}
+
+// What the mounted binderfs's kernel supports, from the files
+// in 'features' directory. Older kernels lack some of the
+// files, those are treated as not supported
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BinderfsFeatures {
+  pub oneway_spam_detection: bool,
+  pub extended_error: bool,
+  pub freeze_notification: bool
+}
+
+fn check_device_name(name: &str) -> io::Result<()> {
+  if name.is_empty() || name.len() > BINDERFS_MAX_NAME || name.contains(['/', '\0']) || name == "." || name == ".." {
+    return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid binderfs device name"));
+  }
+  Ok(())
+}
+
+// Creates new binder device named 'name' in binderfs mounted
+// at 'mount'. Each device is its own binder context with its
+// own context manager
+pub fn binderfs_add_device(mount: &Path, name: &str) -> io::Result<BinderfsDevice> {
+  check_device_name(name)?;
+  
+  let control = File::open(mount.join("binder-control"))?;
+  let mut device = BinderfsDeviceRaw::zeroed();
+  device.name[..name.len()].copy_from_slice(name.as_bytes());
+  unsafe { ioctl::ioctl_binder_ctl_add(control.as_raw_fd(), &raw mut device) }?;
+  
+  Ok(BinderfsDevice {
+    // Kernel does not change the name, but read it back anyway
+    name: CStr::from_bytes_until_nul(&device.name)
+      .ok()
+      .and_then(|x| x.to_str().ok())
+      .unwrap_or(name)
+      .to_string(),
+    major: device.major,
+    minor: device.minor,
+    path: mount.join(name)
+  })
+}
+
+// binderfs removes device when its node is unlinked, processes
+// which have it open can keep using it until they close it
+pub fn binderfs_remove_device(mount: &Path, name: &str) -> io::Result<()> {
+  check_device_name(name)?;
+  fs::remove_file(mount.join(name))
+}
+
+pub fn binderfs_features(mount: &Path) -> io::Result<BinderfsFeatures> {
+  let read_feature = |file: &str| -> io::Result<bool> {
+    match fs::read_to_string(mount.join("features").join(file)) {
+      Ok(content) => Ok(content.trim() == "1"),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+      Err(e) => Err(e)
+    }
+  };
+  
+  Ok(BinderfsFeatures {
+    oneway_spam_detection: read_feature("oneway_spam_detection")?,
+    extended_error: read_feature("extended_error")?,
+    freeze_notification: read_feature("freeze_notification")?
+  })
+}
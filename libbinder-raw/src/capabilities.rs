@@ -0,0 +1,126 @@
+use std::{fs, os::fd::{AsRawFd, BorrowedFd}, path::PathBuf};
+
+use nix::{errno::Errno, libc};
+
+use crate::{Version, binder_version, binderfs::{self, BinderfsFeatures}, freeze};
+
+// Whether kernel supports something. Some can't be probed
+// without changing state of the device, those are Unknown
+// unless other probed capability implies them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Support {
+  Supported,
+  Unsupported,
+  Unknown
+}
+
+impl Support {
+  pub fn is_supported(self) -> bool {
+    self == Support::Supported
+  }
+  
+  pub fn is_unsupported(self) -> bool {
+    self == Support::Unsupported
+  }
+}
+
+impl From<bool> for Support {
+  fn from(value: bool) -> Self {
+    if value { Support::Supported } else { Support::Unsupported }
+  }
+}
+
+// What the kernel behind a binder device supports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DriverCapabilities {
+  pub version: Version,
+  
+  // BINDER_FREEZE and BINDER_GET_FROZEN_INFO
+  pub freeze: Support,
+  
+  // BINDER_GET_EXTENDED_ERROR
+  pub extended_error: Support,
+  
+  // BINDER_SET_CONTEXT_MGR_EXT and FLAT_BINDER_FLAG_TXN_SECURITY_CTX
+  pub security_context: Support,
+  
+  // BC_TRANSACTION_SG/BC_REPLY_SG, needed for buffer objects
+  pub scatter_gather: Support,
+  
+  // BC_REQUEST_FREEZE_NOTIFICATION and friends
+  pub freeze_notification: Support,
+  
+  // BINDER_ENABLE_ONEWAY_SPAM_DETECTION
+  pub oneway_spam_detection: Support
+}
+
+// binderfs mount which the device lives in, if any. The
+// fd is resolved so symlinks like /dev/binder works too
+fn find_binderfs_mount(fd: BorrowedFd) -> Option<PathBuf> {
+  let path = fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd())).ok()?;
+  let mount = path.parent()?;
+  mount.join("features").is_dir().then(|| mount.to_path_buf())
+}
+
+// Kernel looks up processes by pid in initial pid namespace, so
+// our pid only means the same to kernel when we are in it.
+// Its inode number is fixed (PROC_PID_INIT_INO)
+fn in_init_pid_namespace() -> Option<bool> {
+  let link = fs::read_link("/proc/self/ns/pid").ok()?;
+  Some(link.to_str()? == "pid:[4026531836]")
+}
+
+impl DriverCapabilities {
+  // Only uses binderfs feature files and ioctls which don't change
+  // anything. What can't be probed is implied by the order things
+  // landed in upstream kernel where possible:
+  //
+  // scatter gather (4.11) < security context (5.1) < freeze (5.9)
+  //   < oneway spam detection (5.13) < feature files (5.16)
+  //   < extended error (6.0) < freeze notification (6.12)
+  pub fn probe(fd: BorrowedFd) -> Result<Self, Errno> {
+    let version = binder_version(fd)?;
+    
+    // Querying own process is harmless. Kernel fails with EINVAL
+    // both for unknown ioctl and unknown pid, so EINVAL only means
+    // unsupported when kernel sees the same pid as us
+    let freeze = match freeze::binder_get_frozen_info(fd, std::process::id() as libc::pid_t) {
+      Ok(_) => Support::Supported,
+      Err(Errno::EINVAL) if in_init_pid_namespace() == Some(true) => Support::Unsupported,
+      Err(_) => Support::Unknown
+    };
+    
+    let features = find_binderfs_mount(fd)
+      .and_then(|mount| binderfs::binderfs_features(&mount).ok());
+    
+    if let Some(BinderfsFeatures { oneway_spam_detection, extended_error, freeze_notification }) = features {
+      return Ok(Self {
+        version,
+        freeze: Support::Supported,
+        extended_error: extended_error.into(),
+        security_context: Support::Supported,
+        scatter_gather: Support::Supported,
+        freeze_notification: freeze_notification.into(),
+        oneway_spam_detection: oneway_spam_detection.into()
+      });
+    }
+    
+    // Without feature files, newer ones are unknown unless
+    // freeze is known to be missing
+    let (older, newer) = match freeze {
+      Support::Supported => (Support::Supported, Support::Unknown),
+      Support::Unsupported => (Support::Unknown, Support::Unsupported),
+      Support::Unknown => (Support::Unknown, Support::Unknown)
+    };
+    
+    Ok(Self {
+      version,
+      freeze,
+      extended_error: newer,
+      security_context: older,
+      scatter_gather: older,
+      freeze_notification: newer,
+      oneway_spam_detection: newer
+    })
+  }
+}
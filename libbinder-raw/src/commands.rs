@@ -3,7 +3,7 @@ use bytemuck_utils::PodData;
 use nix::{request_code_none, request_code_read, request_code_write};
 use num_enum::{TryFromPrimitive, TryFromPrimitiveError};
 
-use crate::{BinderUsize, transaction::TransactionDataRaw};
+use crate::{BinderUsize, transaction::{TransactionDataRaw, TransactionDataSecCtxRaw, TransactionDataSgRaw}};
 
 const BINDER_CMD_MAGIC: u8 = b'c';
 
@@ -17,9 +17,19 @@ pub enum Command {
   SendTransaction = request_code_write!(BINDER_CMD_MAGIC, 0, size_of::<TransactionDataRaw>()),
   SendReply = request_code_write!(BINDER_CMD_MAGIC, 1, size_of::<TransactionDataRaw>()),
   FreeBuffer = request_code_write!(BINDER_CMD_MAGIC, 3, size_of::<BinderUsize>()),
+  AcquireWeakDone = request_code_write!(BINDER_CMD_MAGIC, 8, size_of::<PtrCookieRaw>()),
+  AcquireDone = request_code_write!(BINDER_CMD_MAGIC, 9, size_of::<PtrCookieRaw>()),
   RegisterLooper = request_code_none!(BINDER_CMD_MAGIC, 11),
   EnterLooper = request_code_none!(BINDER_CMD_MAGIC, 12),
-  ExitLooper = request_code_none!(BINDER_CMD_MAGIC, 13)
+  ExitLooper = request_code_none!(BINDER_CMD_MAGIC, 13),
+  RequestDeathNotification = request_code_write!(BINDER_CMD_MAGIC, 14, size_of::<HandleCookieRaw>()),
+  ClearDeathNotification = request_code_write!(BINDER_CMD_MAGIC, 15, size_of::<HandleCookieRaw>()),
+  DeadBinderDone = request_code_write!(BINDER_CMD_MAGIC, 16, size_of::<BinderUsize>()),
+  SendTransactionSg = request_code_write!(BINDER_CMD_MAGIC, 17, size_of::<TransactionDataSgRaw>()),
+  SendReplySg = request_code_write!(BINDER_CMD_MAGIC, 18, size_of::<TransactionDataSgRaw>()),
+  RequestFreezeNotification = request_code_write!(BINDER_CMD_MAGIC, 19, size_of::<HandleCookieRaw>()),
+  ClearFreezeNotification = request_code_write!(BINDER_CMD_MAGIC, 20, size_of::<HandleCookieRaw>()),
+  FreezeNotificationDone = request_code_write!(BINDER_CMD_MAGIC, 21, size_of::<BinderUsize>())
 }
 
 impl Command {
@@ -28,6 +38,15 @@ impl Command {
   }
 }
 
+// Equivalent to struct binder_handle_cookie, the kernel
+// declares it packed so there no padding between the two
+#[derive(Pod, Zeroable, Clone, Copy)]
+#[repr(C, packed)]
+pub struct HandleCookieRaw {
+  pub handle: u32,
+  pub cookie: BinderUsize
+}
+
 const BINDER_RET_MAGIC: u8 = b'r';
 
 #[derive(Pod, Zeroable, Clone, Copy)]
@@ -45,19 +64,64 @@ impl PtrCookieRaw {
   }
 }
 
+// Equivalent to struct binder_frozen_state_info
+#[derive(Pod, Zeroable, Clone, Copy)]
+#[repr(C)]
+pub struct FrozenStateInfoRaw {
+  pub cookie: BinderUsize,
+  pub is_frozen: u32,
+  pub reserved: u32
+}
+
+impl FrozenStateInfoRaw {
+  // Unaligned read does not matter
+  // any bit pattern is correct
+  pub fn from_raw_bytes(bytes: &[u8]) -> FrozenStateInfoRaw {
+    PodData::unwrap(PodData::make_sure_owned(PodData::<FrozenStateInfoRaw>::from_bytes(bytes)))
+  }
+}
+
+// Equivalent to struct binder_pri_ptr_cookie
+#[derive(Pod, Zeroable, Clone, Copy)]
+#[repr(C)]
+pub struct PriPtrCookieRaw {
+  pub priority: i32,
+  _pad: [u8; size_of::<BinderUsize>() - size_of::<i32>()],
+  pub ptr: BinderUsize,
+  pub cookie: BinderUsize
+}
+
+impl PriPtrCookieRaw {
+  // Unaligned read does not matter
+  // any bit pattern is correct
+  pub fn from_raw_bytes(bytes: &[u8]) -> PriPtrCookieRaw {
+    PodData::unwrap(PodData::make_sure_owned(PodData::<PriPtrCookieRaw>::from_bytes(bytes)))
+  }
+}
+
 #[repr(i32)]
 #[derive(Debug, Clone, Copy, TryFromPrimitive)]
 pub enum ReturnVal {
   Error = request_code_read!(BINDER_RET_MAGIC, 0, size_of::<i32>()),
   Ok = request_code_none!(BINDER_RET_MAGIC, 1),
   Transaction = request_code_read!(BINDER_RET_MAGIC, 2, size_of::<TransactionDataRaw>()),
+  TransactionSecCtx = request_code_read!(BINDER_RET_MAGIC, 2, size_of::<TransactionDataSecCtxRaw>()),
   Reply = request_code_read!(BINDER_RET_MAGIC, 3, size_of::<TransactionDataRaw>()),
+  AcquireResult = request_code_read!(BINDER_RET_MAGIC, 4, size_of::<i32>()),
   DeadReply = request_code_none!(BINDER_RET_MAGIC, 5),
   TransactionComplete = request_code_none!(BINDER_RET_MAGIC, 6),
   Noop = request_code_none!(BINDER_RET_MAGIC, 12),
+  AttemptAcquire = request_code_read!(BINDER_RET_MAGIC, 11, size_of::<PriPtrCookieRaw>()),
   SpawnLooper = request_code_none!(BINDER_RET_MAGIC, 13),
-  DeadBinder = request_code_none!(BINDER_RET_MAGIC, 15),
+  Finished = request_code_none!(BINDER_RET_MAGIC, 14),
+  DeadBinder = request_code_read!(BINDER_RET_MAGIC, 15, size_of::<BinderUsize>()),
+  ClearDeathNotificationDone = request_code_read!(BINDER_RET_MAGIC, 16, size_of::<BinderUsize>()),
   Failed = request_code_none!(BINDER_RET_MAGIC, 17),
+  FrozenReply = request_code_none!(BINDER_RET_MAGIC, 18),
+  OnewaySpamSuspect = request_code_none!(BINDER_RET_MAGIC, 19),
+  TransactionPendingFrozen = request_code_none!(BINDER_RET_MAGIC, 20),
+  FrozenBinder = request_code_read!(BINDER_RET_MAGIC, 21, size_of::<FrozenStateInfoRaw>()),
+  ClearFreezeNotificationDone = request_code_read!(BINDER_RET_MAGIC, 22, size_of::<BinderUsize>()),
   Acquire = request_code_read!(BINDER_RET_MAGIC, 8, size_of::<PtrCookieRaw>()),
   AcquireWeak = request_code_read!(BINDER_RET_MAGIC, 7, size_of::<PtrCookieRaw>()),
   Release = request_code_read!(BINDER_RET_MAGIC, 9, size_of::<PtrCookieRaw>()),
@@ -68,6 +132,18 @@ impl ReturnVal {
   pub fn try_from_bytes(bytes: [u8; 4]) -> Result<Self, TryFromPrimitiveError<Self>> {
     Self::try_from_primitive(i32::from_ne_bytes(bytes))
   }
+  
+  // Size of data following the return code, taken from the
+  // code itself so it works on codes this doesn't know
+  pub fn payload_size_of(code: u32) -> usize {
+    const IOC_SIZESHIFT: u32 = 16;
+    const IOC_SIZEMASK: u32 = (1 << 14) - 1;
+    ((code >> IOC_SIZESHIFT) & IOC_SIZEMASK) as usize
+  }
+  
+  pub fn payload_size(self) -> usize {
+    Self::payload_size_of(self as u32)
+  }
 }
 
 
@@ -0,0 +1,133 @@
+use std::{error::Error, ffi::c_void, fmt::Display, fs::OpenOptions, io, num::NonZeroUsize, os::fd::{AsFd, BorrowedFd, OwnedFd}, path::Path, ptr::NonNull};
+
+use nix::{errno::Errno, libc, sys::mman::{self, MapFlags, ProtFlags}};
+
+use crate::{BINDER_COMPILED_VERSION, Version, binder_version, capabilities::DriverCapabilities, freeze::{self, FrozenStatus}};
+
+// Same as what the runtime used before, Android's
+// libbinder uses 1 MiB minus two pages
+pub const DEFAULT_MAP_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum DeviceError {
+  Open(io::Error),
+  
+  // BINDER_VERSION ioctl failed, likely not a binder device
+  Version(Errno),
+  
+  // Kernel speaks other protocol than this is compiled for
+  VersionMismatch { kernel: Version, compiled: Version },
+  
+  Mmap(Errno)
+}
+
+impl Display for DeviceError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      DeviceError::Open(e) => write!(f, "cannot open binder device: {e}"),
+      DeviceError::Version(e) => write!(f, "cannot get binder version: {e}"),
+      DeviceError::VersionMismatch { kernel, compiled } => write!(f, "kernel binder version is {} but compiled for {}", kernel.version, compiled.version),
+      DeviceError::Mmap(e) => write!(f, "cannot map binder buffer: {e}")
+    }
+  }
+}
+
+impl Error for DeviceError {}
+
+// Opened binder device with its receive buffer mapped, the
+// buffer is where kernel puts incoming transactions. Kernel
+// only allows one mapping per open, so this owns both
+pub struct BinderDevice {
+  fd: OwnedFd,
+  map_ptr: NonNull<c_void>,
+  map_size: usize,
+  version: Version,
+  capabilities: DriverCapabilities
+}
+
+unsafe impl Sync for BinderDevice {}
+unsafe impl Send for BinderDevice {}
+
+impl BinderDevice {
+  pub fn open(path: &Path) -> Result<Self, DeviceError> {
+    Self::open_with_map_size(path, DEFAULT_MAP_SIZE)
+  }
+  
+  pub fn open_with_map_size(path: &Path, map_size: usize) -> Result<Self, DeviceError> {
+    let file = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .open(path)
+      .map_err(DeviceError::Open)?;
+    Self::from_fd(file.into(), map_size)
+  }
+  
+  // The fd must not be mapped yet
+  pub fn from_fd(fd: OwnedFd, map_size: usize) -> Result<Self, DeviceError> {
+    let version = binder_version(fd.as_fd()).map_err(DeviceError::Version)?;
+    if version != BINDER_COMPILED_VERSION {
+      return Err(DeviceError::VersionMismatch { kernel: version, compiled: BINDER_COMPILED_VERSION });
+    }
+    
+    // Probed before anything else touches the device, so
+    // the probe can't undo what caller set up
+    let capabilities = DriverCapabilities::probe(fd.as_fd()).map_err(DeviceError::Version)?;
+    
+    let len = NonZeroUsize::new(map_size).ok_or(DeviceError::Mmap(Errno::EINVAL))?;
+    
+    // Kernel forbids writable mapping, the buffer is only
+    // written by kernel
+    let map_ptr = unsafe {
+      mman::mmap(None, len, ProtFlags::PROT_READ, MapFlags::MAP_PRIVATE, fd.as_fd(), 0)
+    }.map_err(DeviceError::Mmap)?;
+    
+    Ok(Self {
+      fd,
+      map_ptr,
+      map_size,
+      version,
+      capabilities
+    })
+  }
+  
+  pub fn get_version(&self) -> Version {
+    self.version
+  }
+  
+  pub fn get_map_size(&self) -> usize {
+    self.map_size
+  }
+  
+  pub fn get_capabilities(&self) -> &DriverCapabilities {
+    &self.capabilities
+  }
+  
+  // Same as freeze::binder_freeze but fails early with
+  // EOPNOTSUPP if kernel is known to not support it
+  pub fn freeze(&self, pid: libc::pid_t, enable: bool, timeout_ms: u32) -> Result<(), Errno> {
+    if self.capabilities.freeze.is_unsupported() {
+      return Err(Errno::EOPNOTSUPP);
+    }
+    freeze::binder_freeze(self.fd.as_fd(), pid, enable, timeout_ms)
+  }
+  
+  pub fn get_frozen_info(&self, pid: libc::pid_t) -> Result<FrozenStatus, Errno> {
+    if self.capabilities.freeze.is_unsupported() {
+      return Err(Errno::EOPNOTSUPP);
+    }
+    freeze::binder_get_frozen_info(self.fd.as_fd(), pid)
+  }
+}
+
+impl AsFd for BinderDevice {
+  fn as_fd(&self) -> BorrowedFd<'_> {
+    self.fd.as_fd()
+  }
+}
+
+impl Drop for BinderDevice {
+  fn drop(&mut self) {
+    let ret = unsafe { mman::munmap(self.map_ptr, self.map_size) };
+    assert!(ret.is_ok(), "Error munmapping binder buffer: {}", ret.unwrap_err().desc());
+  }
+}
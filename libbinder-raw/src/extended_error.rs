@@ -0,0 +1,45 @@
+use std::os::fd::{AsRawFd, BorrowedFd};
+
+use bytemuck::{Pod, Zeroable};
+use nix::errno::Errno;
+
+use crate::{commands::ReturnVal, ioctl};
+
+// Equivalent to struct binder_extended_error
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct ExtendedErrorRaw {
+  id: u32,
+  command: u32,
+  param: i32
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ExtendedError {
+  // Debug id of the failed transaction, matches the one
+  // in kernel's binder logs
+  pub id: u32,
+  
+  // The return value sent for the failure, BR_OK
+  // if there were no error since last fetch
+  pub command: Option<ReturnVal>,
+  
+  // Why it failed, None if kernel did not give reason
+  pub errno: Option<Errno>
+}
+
+// Kernel keeps last error per thread and resets it after
+// this, so this has to be called by the thread which
+// received BR_FAILED_REPLY/BR_DEAD_REPLY
+pub fn binder_get_extended_error(fd: BorrowedFd) -> Result<ExtendedError, Errno> {
+  let mut error = ExtendedErrorRaw::zeroed();
+  unsafe { ioctl::ioctl_binder_get_extended_error(fd.as_raw_fd(), &raw mut error) }?;
+  
+  Ok(ExtendedError {
+    id: error.id,
+    command: ReturnVal::try_from(error.command as i32).ok(),
+    
+    // Kernel stores it as negative errno
+    errno: (error.param != 0).then(|| Errno::from_raw(-error.param))
+  })
+}
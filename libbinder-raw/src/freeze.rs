@@ -0,0 +1,70 @@
+use std::os::fd::{AsRawFd, BorrowedFd};
+
+use bytemuck::{Pod, Zeroable};
+use nix::{errno::Errno, libc};
+
+use crate::ioctl;
+
+// Equivalent to struct binder_freeze_info
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct FreezeInfoRaw {
+  pid: u32,
+  enable: u32,
+  timeout_ms: u32
+}
+
+// Equivalent to struct binder_frozen_status_info
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct FrozenStatusInfoRaw {
+  pid: u32,
+  
+  // bit 0: received sync transaction after being frozen
+  // bit 1: new pending sync transaction during freezing
+  sync_recv: u32,
+  async_recv: u32
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FrozenStatus {
+  // Sync transaction was sent to process after it was frozen
+  pub sync_received_while_frozen: bool,
+  
+  // There were sync transaction pending while freezing
+  pub sync_pending_during_freeze: bool,
+  
+  // Async transaction was sent to process after it was frozen
+  pub async_received_while_frozen: bool
+}
+
+// Freeze or unfreeze binder of a process. When freezing, kernel waits
+// up to timeout_ms for pending transactions of the process to finish
+// and fails with EAGAIN if they didn't
+//
+// After frozen, sync transactions to the process fails with BR_FROZEN_REPLY
+// while async ones gets queued
+pub fn binder_freeze(fd: BorrowedFd, pid: libc::pid_t, enable: bool, timeout_ms: u32) -> Result<(), Errno> {
+  let mut info = FreezeInfoRaw {
+    pid: pid as u32,
+    enable: enable as u32,
+    timeout_ms
+  };
+  unsafe { ioctl::ioctl_binder_freeze(fd.as_raw_fd(), &raw mut info) }?;
+  Ok(())
+}
+
+pub fn binder_get_frozen_info(fd: BorrowedFd, pid: libc::pid_t) -> Result<FrozenStatus, Errno> {
+  let mut info = FrozenStatusInfoRaw {
+    pid: pid as u32,
+    sync_recv: 0,
+    async_recv: 0
+  };
+  unsafe { ioctl::ioctl_binder_get_frozen_info(fd.as_raw_fd(), &raw mut info) }?;
+  
+  Ok(FrozenStatus {
+    sync_received_while_frozen: info.sync_recv & 0x01 != 0,
+    sync_pending_during_freeze: info.sync_recv & 0x02 != 0,
+    async_received_while_frozen: info.async_recv != 0
+  })
+}
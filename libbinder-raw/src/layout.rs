@@ -0,0 +1,90 @@
+// Compile time checks that the raw structs match the size
+// of their C counterparts in binder.h for the selected
+// protocol, mismatch here means kernel would misread them
+use crate::{
+  BinderUsize,
+  Version,
+  binderfs::BinderfsDeviceRaw,
+  commands::{FrozenStateInfoRaw, HandleCookieRaw, PriPtrCookieRaw, PtrCookieRaw},
+  extended_error::ExtendedErrorRaw,
+  freeze::{FreezeInfoRaw, FrozenStatusInfoRaw},
+  node_info::{NodeDebugInfoRaw, NodeInfoForRefRaw},
+  object::{ObjectHeaderRaw, buffer::{ObjectBufferRaw, ObjectFdArrayRaw}, file_descriptor::ObjectFdRaw, reference::ObjectRefRaw},
+  transaction::{TransactionDataRaw, TransactionDataSecCtxRaw, TransactionDataSgRaw},
+  write_read::ReadWrite
+};
+
+macro_rules! assert_size {
+  ($ty:ty, $size:expr) => {
+    const _: () = assert!(size_of::<$ty>() == $size, concat!("size of ", stringify!($ty), " does not match binder.h"));
+  };
+}
+
+// Sizes can still match with fields in wrong place, so
+// check offsets of the fields kernel reads directly
+macro_rules! assert_offset {
+  ($ty:ty, $($field:ident).+, $offset:expr) => {
+    const _: () = assert!(core::mem::offset_of!($ty, $($field).+) == $offset, concat!("offset of ", stringify!($ty), ".", stringify!($($field).+), " does not match binder.h"));
+  };
+}
+
+// Same on both protocols
+assert_size!(Version, 4);
+assert_size!(ObjectHeaderRaw, 4);
+assert_size!(FreezeInfoRaw, 12);
+assert_size!(FrozenStatusInfoRaw, 12);
+assert_size!(ExtendedErrorRaw, 12);
+assert_size!(NodeInfoForRefRaw, 24);
+assert_size!(BinderfsDeviceRaw, 264);
+
+// Protocol version 8, 64-bit binder_uintptr_t and binder_size_t
+#[cfg(not(feature = "ipc-32bit"))]
+mod v8 {
+  use super::*;
+  
+  assert_size!(BinderUsize, 8);
+  assert_size!(ObjectRefRaw, 24);
+  assert_size!(ObjectFdRaw, 24);
+  assert_size!(ObjectBufferRaw, 40);
+  assert_size!(ObjectFdArrayRaw, 32);
+  assert_size!(TransactionDataRaw, 64);
+  assert_size!(TransactionDataSgRaw, 72);
+  assert_size!(TransactionDataSecCtxRaw, 72);
+  assert_size!(ReadWrite, 48);
+  assert_size!(PtrCookieRaw, 16);
+  assert_size!(PriPtrCookieRaw, 24);
+  assert_size!(HandleCookieRaw, 12);
+  assert_size!(FrozenStateInfoRaw, 16);
+  assert_size!(NodeDebugInfoRaw, 24);
+  
+  assert_offset!(TransactionDataRaw, data, 48);
+  assert_offset!(ObjectRefRaw, binder_or_handle.handle, 8);
+  assert_offset!(ObjectRefRaw, extra_data, 16);
+  assert_offset!(ObjectBufferRaw, parent_offset, 32);
+}
+
+// Protocol version 7, 32-bit binder_uintptr_t and binder_size_t
+#[cfg(feature = "ipc-32bit")]
+mod v7 {
+  use super::*;
+  
+  assert_size!(BinderUsize, 4);
+  assert_size!(ObjectRefRaw, 16);
+  assert_size!(ObjectFdRaw, 16);
+  assert_size!(ObjectBufferRaw, 24);
+  assert_size!(ObjectFdArrayRaw, 20);
+  assert_size!(TransactionDataRaw, 40);
+  assert_size!(TransactionDataSgRaw, 44);
+  assert_size!(TransactionDataSecCtxRaw, 44);
+  assert_size!(ReadWrite, 24);
+  assert_size!(PtrCookieRaw, 8);
+  assert_size!(PriPtrCookieRaw, 12);
+  assert_size!(HandleCookieRaw, 8);
+  assert_size!(FrozenStateInfoRaw, 12);
+  assert_size!(NodeDebugInfoRaw, 16);
+  
+  assert_offset!(TransactionDataRaw, data, 32);
+  assert_offset!(ObjectRefRaw, binder_or_handle.handle, 8);
+  assert_offset!(ObjectRefRaw, extra_data, 12);
+  assert_offset!(ObjectBufferRaw, parent_offset, 20);
+}
@@ -4,14 +4,23 @@
 use std::os::fd::{AsRawFd, BorrowedFd};
 
 use bytemuck::{Pod, Zeroable};
+use enumflags2::BitFlags;
 use nix::errno::Errno;
 
 pub mod object;
 pub mod write_read;
 pub mod commands;
 pub mod transaction;
+pub mod freeze;
+pub mod extended_error;
+pub mod node_info;
+pub mod binderfs;
+pub mod device;
+pub mod capabilities;
 
-use crate::object::reference::ObjectRefLocal;
+mod layout;
+
+use crate::object::reference::{ObjectRefFlags, ObjectRefLocal};
 
 pub mod types {
   use crate::object;
@@ -20,41 +29,85 @@ pub mod types {
 
 // Equivalent to struct binder_version
 #[repr(C)]
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Pod, Zeroable)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Pod, Zeroable)]
 pub struct Version {
   pub version: i32
 }
 
-// TODO: Make sure binder_uintptr_t is correct? somehow detect BINDER_IPC_32BIT
-// it looked like BINDER_IPC_32BIT is for 32-bit userspace, but if im in 32-bit userspace
-// then 'usize' is 32-bit so not wrong? but not sure how accurate it is. Maybe 64-bit
-// userspace use 32-bit binder for some reason?
-pub type BinderUsize = usize;
+// Equivalent to binder_uintptr_t and binder_size_t. Kernel built with
+// BINDER_IPC_32BIT (protocol version 7) uses 32-bit ones regardless of
+// the userspace, it can't be guessed from 'usize' so its a feature
+#[cfg(not(feature = "ipc-32bit"))]
+pub type BinderUsize = u64;
+#[cfg(feature = "ipc-32bit")]
+pub type BinderUsize = u32;
 
 mod ioctl {
   use nix::{ioctl_readwrite, ioctl_write_ptr};
-  use crate::{Version, object::reference::ObjectRefRaw, write_read::ReadWrite};
+  use crate::{Version, binderfs::BinderfsDeviceRaw, extended_error::ExtendedErrorRaw, freeze::{FreezeInfoRaw, FrozenStatusInfoRaw}, node_info::{NodeDebugInfoRaw, NodeInfoForRefRaw}, object::reference::ObjectRefRaw, write_read::ReadWrite};
   
   const BINDER_IOC_MAGIC: u8  = b'b';
   const BINDER_IOC_TYPE_WRITE_READ: u8 = 1;
+  const BINDER_IOC_CTL_ADD: u8 = 1;
+  const BINDER_IOC_SET_MAX_THREADS: u8 = 5;
   const BINDER_IOC_TYPE_VERSION: u8 = 9;
+  const BINDER_IOC_GET_NODE_DEBUG_INFO: u8 = 11;
+  const BINDER_IOC_GET_NODE_INFO_FOR_REF: u8 = 12;
   const BINDER_IOC_SET_CONTEXT_MGR_EXT: u8 = 13;
+  const BINDER_IOC_FREEZE: u8 = 14;
+  const BINDER_IOC_GET_FROZEN_INFO: u8 = 15;
+  const BINDER_IOC_ENABLE_ONEWAY_SPAM_DETECTION: u8 = 16;
+  const BINDER_IOC_GET_EXTENDED_ERROR: u8 = 17;
 
   ioctl_readwrite!(ioctl_binder_version, BINDER_IOC_MAGIC, BINDER_IOC_TYPE_VERSION, Version);
   ioctl_readwrite!(ioctl_binder_write_read, BINDER_IOC_MAGIC, BINDER_IOC_TYPE_WRITE_READ, ReadWrite);
+  ioctl_write_ptr!(ioctl_binder_set_max_threads, BINDER_IOC_MAGIC, BINDER_IOC_SET_MAX_THREADS, u32);
+  ioctl_readwrite!(ioctl_binder_get_node_debug_info, BINDER_IOC_MAGIC, BINDER_IOC_GET_NODE_DEBUG_INFO, NodeDebugInfoRaw);
+  ioctl_readwrite!(ioctl_binder_get_node_info_for_ref, BINDER_IOC_MAGIC, BINDER_IOC_GET_NODE_INFO_FOR_REF, NodeInfoForRefRaw);
   ioctl_write_ptr!(ioctl_set_context_mgr_ext, BINDER_IOC_MAGIC, BINDER_IOC_SET_CONTEXT_MGR_EXT, ObjectRefRaw);
+  ioctl_write_ptr!(ioctl_binder_freeze, BINDER_IOC_MAGIC, BINDER_IOC_FREEZE, FreezeInfoRaw);
+  ioctl_readwrite!(ioctl_binder_get_frozen_info, BINDER_IOC_MAGIC, BINDER_IOC_GET_FROZEN_INFO, FrozenStatusInfoRaw);
+  ioctl_write_ptr!(ioctl_binder_enable_oneway_spam_detection, BINDER_IOC_MAGIC, BINDER_IOC_ENABLE_ONEWAY_SPAM_DETECTION, u32);
+  ioctl_readwrite!(ioctl_binder_get_extended_error, BINDER_IOC_MAGIC, BINDER_IOC_GET_EXTENDED_ERROR, ExtendedErrorRaw);
+  
+  // For binder-control of binderfs
+  ioctl_readwrite!(ioctl_binder_ctl_add, BINDER_IOC_MAGIC, BINDER_IOC_CTL_ADD, BinderfsDeviceRaw);
 }
 
+#[cfg(not(feature = "ipc-32bit"))]
 pub const BINDER_COMPILED_VERSION: Version = Version {
   version: 8
 };
+#[cfg(feature = "ipc-32bit")]
+pub const BINDER_COMPILED_VERSION: Version = Version {
+  version: 7
+};
 
-pub fn binder_set_context_mgr(fd: BorrowedFd, manager_object: &ObjectRefLocal) -> Result<(), Errno> {
-  let mut obj_ref = manager_object.into_raw();
+pub fn binder_set_context_mgr(fd: BorrowedFd, manager_object: &ObjectRefLocal, flags: BitFlags<ObjectRefFlags>) -> Result<(), Errno> {
+  let mut obj_ref = manager_object.into_raw_with_flags(flags);
   unsafe { ioctl::ioctl_set_context_mgr_ext(fd.as_raw_fd(), &raw mut obj_ref) }?;
   Ok(())
 }
 
+// Max number of extra looper threads kernel may ask
+// with BR_SPAWN_LOOPER, not counting ones which entered
+// with BC_ENTER_LOOPER
+pub fn binder_set_max_threads(fd: BorrowedFd, max_threads: u32) -> Result<(), Errno> {
+  unsafe { ioctl::ioctl_binder_set_max_threads(fd.as_raw_fd(), &raw const max_threads) }?;
+  Ok(())
+}
+
+// When enabled, this process gets BR_ONEWAY_SPAM_SUSPECT instead
+// of BR_TRANSACTION_COMPLETE for its own one way transactions
+// which target's kernel flagged (target is low on async buffer
+// space and this process uses much of it). The transaction is
+// still delivered. Target doesn't need it enabled for flagging
+pub fn binder_enable_oneway_spam_detection(fd: BorrowedFd, enable: bool) -> Result<(), Errno> {
+  let enable = enable as u32;
+  unsafe { ioctl::ioctl_binder_enable_oneway_spam_detection(fd.as_raw_fd(), &raw const enable) }?;
+  Ok(())
+}
+
 pub fn binder_version(fd: BorrowedFd) -> Result<Version, Errno> {
   let mut ver = BINDER_COMPILED_VERSION;
   unsafe { ioctl::ioctl_binder_version(fd.as_raw_fd(), &mut ver) }?;
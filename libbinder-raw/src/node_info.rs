@@ -0,0 +1,84 @@
+use std::os::fd::{AsRawFd, BorrowedFd};
+
+use bytemuck::{Pod, Zeroable};
+use nix::errno::Errno;
+
+use crate::{BinderUsize, ioctl, object::reference::ObjectRefLocal};
+
+// Equivalent to struct binder_node_debug_info
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct NodeDebugInfoRaw {
+  ptr: BinderUsize,
+  cookie: BinderUsize,
+  has_strong_ref: u32,
+  has_weak_ref: u32
+}
+
+// Equivalent to struct binder_node_info_for_ref
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct NodeInfoForRefRaw {
+  handle: u32,
+  strong_count: u32,
+  weak_count: u32,
+  reserved1: u32,
+  reserved2: u32,
+  reserved3: u32
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct NodeDebugInfo {
+  pub local_ref: ObjectRefLocal,
+  
+  // Whether kernel thinks this process holds strong/weak
+  // reference, as in BR_ACQUIRE/BR_INCREFS was sent
+  pub has_strong_ref: bool,
+  pub has_weak_ref: bool
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NodeRefCounts {
+  pub strong_count: u32,
+  pub weak_count: u32
+}
+
+// Gets the node owned by this process with smallest ptr
+// that is larger than 'after_ptr', None if there no more.
+// Start with 0 to get the first one
+pub fn binder_node_debug_info(fd: BorrowedFd, after_ptr: usize) -> Result<Option<NodeDebugInfo>, Errno> {
+  let mut info = NodeDebugInfoRaw {
+    ptr: after_ptr as BinderUsize,
+    ..NodeDebugInfoRaw::zeroed()
+  };
+  unsafe { ioctl::ioctl_binder_get_node_debug_info(fd.as_raw_fd(), &raw mut info) }?;
+  
+  if info.ptr == 0 {
+    return Ok(None);
+  }
+  
+  Ok(Some(NodeDebugInfo {
+    local_ref: ObjectRefLocal {
+      data: info.ptr as usize,
+      extra_data: info.cookie as usize
+    },
+    has_strong_ref: info.has_strong_ref != 0,
+    has_weak_ref: info.has_weak_ref != 0
+  }))
+}
+
+// Reference counts of the node which the handle refers to.
+// Kernel only allows context manager to do this, others
+// get EPERM
+pub fn binder_node_info_for_ref(fd: BorrowedFd, handle: u32) -> Result<NodeRefCounts, Errno> {
+  let mut info = NodeInfoForRefRaw {
+    handle,
+    ..NodeInfoForRefRaw::zeroed()
+  };
+  unsafe { ioctl::ioctl_binder_get_node_info_for_ref(fd.as_raw_fd(), &raw mut info) }?;
+  
+  Ok(NodeRefCounts {
+    strong_count: info.strong_count,
+    weak_count: info.weak_count
+  })
+}
@@ -0,0 +1,165 @@
+use bytemuck::{Pod, Zeroable};
+use bytemuck_utils::PodData;
+use enumflags2::{BitFlags, bitflags};
+
+use crate::{BinderUsize, object::{self, ObjectHeaderRaw, Type}};
+
+#[bitflags]
+#[repr(u32)]
+#[derive(Clone, Copy)]
+pub enum ObjectBufferFlags {
+  HasParent = 0x01
+}
+
+// Where the pointer to a buffer is located, the
+// index is index into offsets of the packet (the
+// n-th object) which must be another buffer object
+// and offset is byte offset inside that buffer
+#[derive(Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ObjectBufferParent {
+  pub index: usize,
+  pub offset: usize
+}
+
+// A blob of memory outside of the packet's data buffer
+// which kernel copies verbatim into receiver. The 'buffer'
+// is address in the sender's and after kernel copied, it is
+// address in the receiver's
+#[derive(Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ObjectBuffer {
+  pub buffer: usize,
+  pub length: usize,
+  pub parent: Option<ObjectBufferParent>
+}
+
+impl ObjectBuffer {
+  pub fn size_in_bytes_for_raw() -> usize {
+    size_of::<ObjectBufferRaw>()
+  }
+  
+  // How many bytes this buffer occupies in the
+  // scatter-gather area of kernel's buffer
+  pub fn size_in_sg_area(&self) -> usize {
+    self.length.next_multiple_of(size_of::<u64>())
+  }
+  
+  pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, ()> {
+    if bytes.len() < Type::bytes_needed() || Type::try_from_bytes(&bytes[..Type::bytes_needed()])? != Type::ByteBuffer {
+      return Err(());
+    }
+    
+    let raw = PodData::<ObjectBufferRaw>::try_from_bytes(bytes).map_err(|_| ())?;
+    let flags = BitFlags::<ObjectBufferFlags>::from_bits(raw.flags).map_err(|_| ())?;
+    Ok(ObjectBuffer {
+      buffer: raw.buffer as usize,
+      length: raw.length as usize,
+      parent: if flags.contains(ObjectBufferFlags::HasParent) {
+          Some(ObjectBufferParent {
+            index: raw.parent as usize,
+            offset: raw.parent_offset as usize
+          })
+        } else {
+          None
+        }
+    })
+  }
+  
+  pub fn with_raw_bytes<R, F: FnOnce(&[u8]) -> R>(&self, func: F) -> R {
+    let raw = self.into_raw();
+    func(bytemuck::bytes_of(&raw))
+  }
+  
+  pub(crate) fn into_raw(&self) -> ObjectBufferRaw {
+    let (flags, parent) = match self.parent {
+      Some(parent) => (BitFlags::from(ObjectBufferFlags::HasParent), parent),
+      None => (BitFlags::empty(), ObjectBufferParent { index: 0, offset: 0 })
+    };
+    
+    ObjectBufferRaw {
+      header: ObjectHeaderRaw {
+        kind: object::PTR
+      },
+      flags: flags.bits(),
+      buffer: self.buffer as BinderUsize,
+      length: self.length as BinderUsize,
+      parent: parent.index as BinderUsize,
+      parent_offset: parent.offset as BinderUsize
+    }
+  }
+}
+
+// An array of u32 fds which lives inside a buffer object
+// kernel translates each of them into receiver's fd
+//
+// Unlike ObjectFd, the fds received this way are owned by
+// the kernel's buffer and closed when the buffer is free'd
+#[derive(Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ObjectFdArray {
+  pub num_fds: usize,
+  
+  // The buffer which contains the fds
+  pub parent: ObjectBufferParent
+}
+
+impl ObjectFdArray {
+  pub fn size_in_bytes_for_raw() -> usize {
+    size_of::<ObjectFdArrayRaw>()
+  }
+  
+  pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, ()> {
+    if bytes.len() < Type::bytes_needed() || Type::try_from_bytes(&bytes[..Type::bytes_needed()])? != Type::FileDescriptorArray {
+      return Err(());
+    }
+    
+    let raw = PodData::<ObjectFdArrayRaw>::try_from_bytes(bytes).map_err(|_| ())?;
+    Ok(ObjectFdArray {
+      num_fds: raw.num_fds as usize,
+      parent: ObjectBufferParent {
+        index: raw.parent as usize,
+        offset: raw.parent_offset as usize
+      }
+    })
+  }
+  
+  pub fn with_raw_bytes<R, F: FnOnce(&[u8]) -> R>(&self, func: F) -> R {
+    let raw = self.into_raw();
+    func(bytemuck::bytes_of(&raw))
+  }
+  
+  pub(crate) fn into_raw(&self) -> ObjectFdArrayRaw {
+    ObjectFdArrayRaw {
+      header: ObjectHeaderRaw {
+        kind: object::FDA
+      },
+      _pad: 0,
+      num_fds: self.num_fds as BinderUsize,
+      parent: self.parent.index as BinderUsize,
+      parent_offset: self.parent.offset as BinderUsize
+    }
+  }
+}
+
+// Equivalent to struct binder_buffer_object
+#[derive(Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub(crate) struct ObjectBufferRaw {
+  header: ObjectHeaderRaw,
+  flags: u32,
+  buffer: BinderUsize,
+  length: BinderUsize,
+  
+  // Index into the offsets (not byte offset)
+  parent: BinderUsize,
+  pub(crate) parent_offset: BinderUsize
+}
+
+// Equivalent to struct binder_fd_array_object
+#[derive(Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub(crate) struct ObjectFdArrayRaw {
+  header: ObjectHeaderRaw,
+  _pad: u32,
+  num_fds: BinderUsize,
+  parent: BinderUsize,
+  parent_offset: BinderUsize
+}
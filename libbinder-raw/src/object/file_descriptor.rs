@@ -0,0 +1,76 @@
+use std::os::fd::RawFd;
+
+use bytemuck::{Pod, Zeroable};
+use bytemuck_utils::PodData;
+
+use crate::{BinderUsize, object::{self, ObjectHeaderRaw, Type}};
+
+// A file descriptor inside the packet, on sending side
+// the fd belongs to sender and kernel installs a copy of
+// it into receiver, so on receiving side the fd is a
+// new one owned by the receiver
+#[derive(Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ObjectFd {
+  pub fd: RawFd,
+  
+  // Kernel won't touch it, only meaningful to the userspace
+  pub cookie: usize
+}
+
+impl ObjectFd {
+  pub fn size_in_bytes_for_raw() -> usize {
+    size_of::<ObjectFdRaw>()
+  }
+  
+  pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, ()> {
+    if bytes.len() < Type::bytes_needed() || Type::try_from_bytes(&bytes[..Type::bytes_needed()])? != Type::FileDescriptor {
+      return Err(());
+    }
+    
+    let raw = PodData::<ObjectFdRaw>::try_from_bytes(bytes).map_err(|_| ())?;
+    Ok(ObjectFd {
+      // SAFETY: It is fd type :3
+      fd: unsafe { raw.fd_or_pad.fd } as RawFd,
+      cookie: raw.cookie as usize
+    })
+  }
+  
+  pub fn with_raw_bytes<R, F: FnOnce(&[u8]) -> R>(&self, func: F) -> R {
+    let raw = self.into_raw();
+    func(bytemuck::bytes_of(&raw))
+  }
+  
+  pub(crate) fn into_raw(&self) -> ObjectFdRaw {
+    // Zeroed first so the part of union not covered
+    // by fd is not garbage
+    let mut raw = ObjectFdRaw::zeroed();
+    raw.header = ObjectHeaderRaw {
+      kind: object::FD
+    };
+    raw.fd_or_pad.fd = self.fd as u32;
+    raw.cookie = self.cookie as BinderUsize;
+    raw
+  }
+}
+
+// Equivalent to struct binder_fd_object
+#[derive(Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub(crate) struct ObjectFdRaw {
+  header: ObjectHeaderRaw,
+  
+  // Exists to remain compatible with flat_binder_object
+  // layout which old userspace uses for fds
+  pad_flags: u32,
+  fd_or_pad: FdOrPadUnion,
+  cookie: BinderUsize
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Zeroable)]
+union FdOrPadUnion {
+  pad_binder: BinderUsize,
+  fd: u32
+}
+
+unsafe impl Pod for FdOrPadUnion {}
@@ -1,7 +1,7 @@
 use bytemuck::{Pod, Zeroable};
 use bytemuck_utils::PodData;
 
-use crate::types::reference::ObjectRefRaw;
+use crate::types::{buffer::{ObjectBufferRaw, ObjectFdArrayRaw}, file_descriptor::ObjectFdRaw, reference::ObjectRefRaw};
 
 const TYPE_LARGE: u8 = 0x85;
 
@@ -21,8 +21,10 @@ pub(crate)  const FDA: u32 = pack_chars(b'f', b'd', b'a', TYPE_LARGE);
 pub(crate)  const PTR: u32 = pack_chars(b'p', b't', b'*', TYPE_LARGE);
 
 pub mod reference;
+pub mod file_descriptor;
+pub mod buffer;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Type {
   RemoteReference,
   LocalReference,
@@ -55,8 +57,11 @@ impl Type {
     match self {
       Type::LocalReference => size_of::<ObjectRefRaw>(),
       Type::RemoteReference => size_of::<ObjectRefRaw>(),
-      
-      _ => todo!()
+      Type::WeakLocalReference => size_of::<ObjectRefRaw>(),
+      Type::WeakRemoteReference => size_of::<ObjectRefRaw>(),
+      Type::FileDescriptor => size_of::<ObjectFdRaw>(),
+      Type::ByteBuffer => size_of::<ObjectBufferRaw>(),
+      Type::FileDescriptorArray => size_of::<ObjectFdArrayRaw>()
     }
   }
   
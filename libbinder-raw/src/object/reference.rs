@@ -34,12 +34,22 @@ impl ObjectRefFlags {
   pub fn from_priority_bits(priority: u8) -> BitFlags<Self> {
     ObjectRefFlags::from_bits(priority as u32).ok().expect("cannot occur")
   }
+  
+  // Packets are able to carry fds, so by default let kernel
+  // deliver transactions containing them
+  pub fn default_for_local() -> BitFlags<Self> {
+    ObjectRefFlags::AcceptFds.into()
+  }
 }
 
+// Weak ones only keep the node from being freed, the owner
+// is free to destroy the object once strong ones are gone
 #[derive(Clone)]
 pub enum ObjectRef {
   Local(ObjectRefLocal),
-  Remote(ObjectRefRemote)
+  Remote(ObjectRefRemote),
+  WeakLocal(ObjectRefLocal),
+  WeakRemote(ObjectRefRemote)
 }
 
 impl ObjectRef {
@@ -48,28 +58,56 @@ impl ObjectRef {
   }
   
   pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, ()> {
-    let obj_type = object::Type::from_bytes(&bytes[..Type::bytes_needed()]);
+    let obj_type = object::Type::try_from_bytes(bytes.get(..Type::bytes_needed()).ok_or(())?)?;
+    if bytes.len() < size_of::<ObjectRefRaw>() {
+      return Err(());
+    }
+    
     let raw = PodData::<ObjectRefRaw>::from_bytes(bytes);
+    let local = || ObjectRefLocal {
+      // SAFETY: It is binder type :3
+      data: unsafe { raw.binder_or_handle.binder } as usize,
+      extra_data: raw.extra_data as usize
+    };
+    let remote = || ObjectRefRemote {
+      // SAFETY: It is handle type :3
+      data_handle: unsafe { raw.binder_or_handle.handle },
+      extra_local_data: raw.extra_data as usize
+    };
+    
     match obj_type {
-      object::Type::LocalReference => Ok(ObjectRef::Local(ObjectRefLocal {
-        // SAFETY: It is binder type :3
-        data: unsafe { raw.binder_or_handle.binder },
-        extra_data: raw.extra_data
-      })),
-      object::Type::RemoteReference => Ok(ObjectRef::Remote(ObjectRefRemote {
-        // SAFETY: It is handle type :3
-        data_handle: unsafe { raw.binder_or_handle.handle },
-        extra_local_data: raw.extra_data
-      })),
+      object::Type::LocalReference => Ok(ObjectRef::Local(local())),
+      object::Type::RemoteReference => Ok(ObjectRef::Remote(remote())),
+      object::Type::WeakLocalReference => Ok(ObjectRef::WeakLocal(local())),
+      object::Type::WeakRemoteReference => Ok(ObjectRef::WeakRemote(remote())),
       
-      _ => panic!("ObjectRef only need to handle BINDER and HANDLE nothing else")
+      // Not a reference
+      _ => Err(())
     }
   }
   
   pub fn with_raw_bytes<R, F: FnOnce(&[u8]) -> R>(&self, func: F) -> R {
+    self.with_raw_bytes_flags(ObjectRefFlags::default_for_local(), func)
+  }
+  
+  // The flags only matter for local reference, and kernel only
+  // look at them the first time the local object is sent out
+  pub fn with_raw_bytes_flags<R, F: FnOnce(&[u8]) -> R>(&self, flags: BitFlags<ObjectRefFlags>, func: F) -> R {
     let raw = match self {
-      ObjectRef::Local(x) => x.into_raw(),
-      ObjectRef::Remote(x) => x.into_raw()
+      ObjectRef::Local(x) => x.into_raw_with_flags(flags),
+      ObjectRef::Remote(x) => x.into_raw(),
+      ObjectRef::WeakLocal(x) => ObjectRefRaw {
+        header: ObjectHeaderRaw {
+          kind: object::WEAK_BINDER
+        },
+        ..x.into_raw_with_flags(flags)
+      },
+      ObjectRef::WeakRemote(x) => ObjectRefRaw {
+        header: ObjectHeaderRaw {
+          kind: object::WEAK_HANDLE
+        },
+        ..x.into_raw()
+      }
     };
     
     let ret = func(bytemuck::bytes_of(&raw));
@@ -93,16 +131,16 @@ pub struct ObjectRefRemote {
 pub const CONTEXT_MANAGER_REF: ObjectRefRemote = ObjectRefRemote { data_handle: 0, extra_local_data: 0 };
 
 impl ObjectRefLocal {
-  pub(crate) fn into_raw(&self) -> ObjectRefRaw {
+  pub(crate) fn into_raw_with_flags(&self, flags: BitFlags<ObjectRefFlags>) -> ObjectRefRaw {
     ObjectRefRaw {
       header: ObjectHeaderRaw {
         kind: object::BINDER
       },
-      flags: 0,
+      flags: flags.bits(),
       binder_or_handle: BinderOrHandleUnion {
-        binder: self.data
+        binder: self.data as BinderUsize
       },
-      extra_data: self.extra_data
+      extra_data: self.extra_data as BinderUsize
     }
   }
 }
@@ -133,7 +171,7 @@ impl ObjectRefRemote {
 pub(crate) struct ObjectRefRaw {
   header: ObjectHeaderRaw,
   flags: u32,
-  binder_or_handle: BinderOrHandleUnion,
+  pub(crate) binder_or_handle: BinderOrHandleUnion,
   
   // On local process (the owner of private object), can possibly have
   // arbitrary data here. With Box<dyn Trait>, a vtable part of trait can
@@ -157,15 +195,15 @@ pub(crate) struct ObjectRefRaw {
   //
   // Kernel does not care what is put in extra_data and pointer to object. Heck it
   // does not have to valid pointer. Kernel won't touch it ^w^
-  extra_data: BinderUsize
+  pub(crate) extra_data: BinderUsize
 }
 
 // It is a union inside flat_binder_object
 #[repr(C)]
 #[derive(Copy, Clone, Zeroable)]
-union BinderOrHandleUnion {
+pub(crate) union BinderOrHandleUnion {
   binder: BinderUsize,
-  handle: u32
+  pub(crate) handle: u32
 }
 
 unsafe impl Pod for BinderOrHandleUnion {}
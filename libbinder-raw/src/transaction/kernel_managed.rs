@@ -1,10 +1,10 @@
-use std::{os::fd::BorrowedFd, slice, sync::Arc};
+use std::{ffi::{CStr, c_char}, os::fd::BorrowedFd, slice, sync::Arc};
 
 use bytemuck_utils::PodData;
 use enumflags2::BitFlags;
 use nix::errno::Errno;
 
-use crate::{BinderUsize, ObjectRefLocal, commands::Command, object::reference::{ObjectRef, ObjectRefRemote}, transaction::{BinderOrHandleUnion, BufferStruct, DataUnion, TransactionDataCommon, TransactionDataRaw}, write_read::binder_read_write};
+use crate::{BinderUsize, ObjectRefLocal, commands::Command, object::reference::{ObjectRef, ObjectRefRemote}, transaction::{BinderOrHandleUnion, BufferStruct, DataUnion, TransactionDataCommon, TransactionDataRaw, TransactionDataSecCtxRaw}, write_read::binder_read_write};
 
 struct KernelBuffer<'binder> {
   binder_dev: BorrowedFd<'binder>,
@@ -36,6 +36,10 @@ pub struct TransactionKernelManaged<'binder> {
   // runs this is dropped first and safe
   data: TransactionDataCommon<'static, 'static>,
   
+  // Same as data, the 'static is placeholder. It points
+  // inside the kernel buffer
+  security_context: Option<&'static CStr>,
+  
   // has to come after the data, as the data refers to
   // the kernel buffer
   _kernel_buf: Arc<KernelBuffer<'binder>>
@@ -67,23 +71,23 @@ impl<'binder> TransactionKernelManaged<'binder> {
     func(bytes)
   }
   
-  fn as_raw(&self) -> TransactionDataRaw {
+  pub(super) fn as_raw(&self) -> TransactionDataRaw {
     let (target, extra_data) = match &self.data.target {
-      ObjectRef::Local(x) => (BinderOrHandleUnion { binder: x.data }, x.extra_data),
-      ObjectRef::Remote(x) => (BinderOrHandleUnion { handle: x.data_handle }, 0)
+      ObjectRef::Local(x) | ObjectRef::WeakLocal(x) => (BinderOrHandleUnion { binder: x.data as BinderUsize }, x.extra_data as BinderUsize),
+      ObjectRef::Remote(x) | ObjectRef::WeakRemote(x) => (BinderOrHandleUnion { handle: x.data_handle }, 0)
     };
     
     TransactionDataRaw {
-      data_size: self.data.data_slice.len(),
-      offsets_size: self.data.offsets.len() * size_of::<usize>(),
+      data_size: self.data.data_slice.len() as BinderUsize,
+      offsets_size: (self.data.offsets.len() * size_of::<BinderUsize>()) as BinderUsize,
       sender_pid: 0,
       sender_uid: 0,
       flags: self.data.flags.bits(),
       code: self.data.code,
       data: DataUnion {
         ptr: BufferStruct {
-          buffer: self.data.data_slice.as_ptr().addr(),
-          offsets: self.data.offsets.as_ptr().addr()
+          buffer: self.data.data_slice.as_ptr().addr() as BinderUsize,
+          offsets: self.data.offsets.as_ptr().addr() as BinderUsize
         }
       },
       extra_data,
@@ -99,6 +103,38 @@ impl<'binder> TransactionKernelManaged<'binder> {
     size_of::<TransactionDataRaw>()
   }
   
+  pub fn bytes_needed_with_security_context() -> usize {
+    size_of::<TransactionDataSecCtxRaw>()
+  }
+  
+  // Security context of the sender, only present if the
+  // target object asked for it
+  pub fn get_security_context<'a>(&'a self) -> Option<&'a CStr> {
+    self.security_context
+  }
+  
+  // SAFETY: Same as from_bytes, but the 'bytes' assumed to be from
+  // BR_TRANSACTION_SEC_CTX
+  pub unsafe fn from_bytes_with_security_context(binder_dev: BorrowedFd<'binder>, bytes: &[u8]) -> Self {
+    if bytes.len() != Self::bytes_needed_with_security_context() {
+      panic!("Size of the 'bytes' is not same the size of binder_transaction_data_secctx ({} bytes)", Self::bytes_needed_with_security_context());
+    }
+    
+    let raw = PodData::<TransactionDataSecCtxRaw>::from_bytes(bytes);
+    
+    // SAFETY: Caller ensured that it is from kernel
+    let mut ret = unsafe { Self::from_bytes(binder_dev, bytemuck::bytes_of(&raw.transaction_data), false) };
+    
+    // SAFETY: Kernel gave pointer to NUL terminated string inside the
+    // buffer, which lives as long as this struct
+    ret.security_context = if raw.secctx == 0 {
+        None
+      } else {
+        Some(unsafe { CStr::from_ptr(raw.secctx as *const c_char) })
+      };
+    ret
+  }
+  
   // SAFETY: The 'bytes' has to be from kernel from the correct binder_dev
   // and the bytes assumed to be from BR_TRANSACTION/BR_REPLY
   //
@@ -112,10 +148,11 @@ impl<'binder> TransactionKernelManaged<'binder> {
     
     // SAFETY: The buffers data as far as 'static concerned lives longer
     // before the 'static reference gone
-    let data_slice: &'static [u8] = unsafe { slice::from_raw_parts(raw.data.ptr.buffer as *mut _, raw.data_size) };
-    let offsets: &'static [usize] = unsafe { slice::from_raw_parts(raw.data.ptr.offsets as *mut _, raw.offsets_size / size_of::<usize>()) };
+    let data_slice: &'static [u8] = unsafe { slice::from_raw_parts(raw.data.ptr.buffer as usize as *mut _, raw.data_size as usize) };
+    let offsets: &'static [BinderUsize] = unsafe { slice::from_raw_parts(raw.data.ptr.offsets as usize as *mut _, raw.offsets_size as usize / size_of::<BinderUsize>()) };
     
     Self {
+      security_context: None,
       _kernel_buf: Arc::new(KernelBuffer {
         buffer_ptr: unsafe { raw.data.ptr.buffer },
         binder_dev
@@ -125,17 +162,19 @@ impl<'binder> TransactionKernelManaged<'binder> {
         target: if is_reply {
             ObjectRef::Remote(ObjectRefRemote {
               data_handle: unsafe { raw.target.handle },
-              extra_local_data: raw.extra_data
+              extra_local_data: raw.extra_data as usize
             })
           } else {
             ObjectRef::Local(ObjectRefLocal {
-              data: unsafe { raw.target.binder },
-              extra_data: raw.extra_data
+              data: unsafe { raw.target.binder } as usize,
+              extra_data: raw.extra_data as usize
             })
           },
         flags: BitFlags::from_bits(raw.flags).ok().unwrap(),
         data_slice,
-        offsets
+        offsets,
+        sender_pid: raw.sender_pid,
+        sender_uid: raw.sender_uid
       }
     }
   }
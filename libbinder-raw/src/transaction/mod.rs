@@ -22,6 +22,20 @@ impl<'buffer, 'buffer_offsets> Transaction<'_, 'buffer, 'buffer_offsets> {
     }
   }
   
+  // Same as with_bytes but gives binder_transaction_data_sg which
+  // used with BC_TRANSACTION_SG/BC_REPLY_SG. The buffers_size is
+  // total size of all buffer objects inside scatter-gather area
+  pub fn with_bytes_sg<F: FnOnce(&[u8]) -> R, R>(&self, buffers_size: usize, func: F) -> R {
+    let raw = TransactionDataSgRaw {
+      transaction_data: match self {
+        Self::NotKernelManaged(x) => x.as_raw(),
+        Self::KernelManaged(x) => x.as_raw()
+      },
+      buffers_size: buffers_size as BinderUsize
+    };
+    func(bytemuck::bytes_of(&raw))
+  }
+  
   pub fn with_common_mut<F, R>(&mut self, func: F) -> R
     where F: FnOnce(&mut TransactionDataCommon) -> R
   {
@@ -45,7 +59,12 @@ pub struct TransactionDataCommon<'buf, 'buf_offsets> {
   pub flags: BitFlags<TransactionFlag>,
   pub code: u32,
   pub data_slice: &'buf [u8],
-  pub offsets: &'buf_offsets [BinderUsize]
+  pub offsets: &'buf_offsets [BinderUsize],
+  
+  // Filled by kernel for incoming transaction, for one way
+  // transaction the pid is always 0. Ignored for outgoing
+  pub sender_pid: nix::libc::pid_t,
+  pub sender_uid: nix::libc::uid_t
 }
 
 #[bitflags]
@@ -79,7 +98,7 @@ struct BufferStruct {
 
 #[repr(C)]
 #[derive(Clone, Copy, Zeroable)]
-union DataUnion {
+pub(crate) union DataUnion {
   ptr: BufferStruct,
   _unused: [u8; 8]
 }
@@ -91,14 +110,31 @@ unsafe impl Pod for DataUnion {}
 #[repr(C)]
 pub(crate) struct TransactionDataRaw {
   target: BinderOrHandleUnion,
-  extra_data: usize,
+  extra_data: BinderUsize,
   code: u32,
   flags: u32,
   sender_pid: nix::libc::pid_t,
   sender_uid: nix::libc::uid_t,
   data_size: BinderUsize,
   offsets_size: BinderUsize,
-  data: DataUnion
+  pub(crate) data: DataUnion
 }
 
+// Equivalent to struct binder_transaction_data_sg
+#[derive(Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(crate) struct TransactionDataSgRaw {
+  transaction_data: TransactionDataRaw,
+  buffers_size: BinderUsize
+}
 
+// Equivalent to struct binder_transaction_data_secctx
+#[derive(Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(crate) struct TransactionDataSecCtxRaw {
+  transaction_data: TransactionDataRaw,
+  
+  // Pointer to NUL terminated string inside
+  // the kernel buffer
+  secctx: BinderUsize
+}
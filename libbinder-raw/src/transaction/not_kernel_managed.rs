@@ -1,4 +1,4 @@
-use crate::{object::reference::ObjectRef, transaction::{BinderOrHandleUnion, BufferStruct, DataUnion, TransactionDataCommon, TransactionDataRaw}};
+use crate::{BinderUsize, object::reference::ObjectRef, transaction::{BinderOrHandleUnion, BufferStruct, DataUnion, TransactionDataCommon, TransactionDataRaw}};
 
 #[derive(Clone)]
 pub struct TransactionNotKernelMananged<'buffer, 'buffer_offsets> {
@@ -12,23 +12,23 @@ impl TransactionNotKernelMananged<'_, '_> {
     func(bytes)
   }
   
-  fn as_raw(&self) -> TransactionDataRaw {
+  pub(super) fn as_raw(&self) -> TransactionDataRaw {
     let (target, extra_data) = match &self.data.target {
-      ObjectRef::Local(x) => (BinderOrHandleUnion { binder: x.data }, x.extra_data),
-      ObjectRef::Remote(x) => (BinderOrHandleUnion { handle: x.data_handle }, 0)
+      ObjectRef::Local(x) | ObjectRef::WeakLocal(x) => (BinderOrHandleUnion { binder: x.data as BinderUsize }, x.extra_data as BinderUsize),
+      ObjectRef::Remote(x) | ObjectRef::WeakRemote(x) => (BinderOrHandleUnion { handle: x.data_handle }, 0)
     };
     
     TransactionDataRaw {
-      data_size: self.data.data_slice.len(),
-      offsets_size: self.data.offsets.len() * size_of::<usize>(),
+      data_size: self.data.data_slice.len() as BinderUsize,
+      offsets_size: (self.data.offsets.len() * size_of::<BinderUsize>()) as BinderUsize,
       sender_pid: 0,
       sender_uid: 0,
       flags: self.data.flags.bits(),
       code: self.data.code,
       data: DataUnion {
         ptr: BufferStruct {
-          buffer: self.data.data_slice.as_ptr().addr(),
-          offsets: self.data.offsets.as_ptr().addr()
+          buffer: self.data.data_slice.as_ptr().addr() as BinderUsize,
+          offsets: self.data.offsets.as_ptr().addr() as BinderUsize
         }
       },
       extra_data,
@@ -31,19 +31,19 @@ pub fn binder_read_write(
     read_buffer_filled_size: 0,
     write_buffer_consumed: 0,
     
-    write_buffer: write_buf.as_ptr().addr(),
-    write_buffer_size: write_buf.len(),
+    write_buffer: write_buf.as_ptr().addr() as BinderUsize,
+    write_buffer_size: write_buf.len() as BinderUsize,
     
-    read_buffer: read_buf.as_ptr().addr(),
-    read_buffer_size: read_buf.len()
+    read_buffer: read_buf.as_ptr().addr() as BinderUsize,
+    read_buffer_size: read_buf.len() as BinderUsize
   };
   
   unsafe { ioctl::ioctl_binder_write_read(fd.as_raw_fd(), &raw mut rw) }
     .map_err(|x| {
-      (x, (rw.write_buffer_consumed, rw.read_buffer_filled_size))
+      (x, (rw.write_buffer_consumed as usize, rw.read_buffer_filled_size as usize))
     })?;
   
-  Ok((rw.write_buffer_consumed, rw.read_buffer_filled_size))
+  Ok((rw.write_buffer_consumed as usize, rw.read_buffer_filled_size as usize))
 }
 
 
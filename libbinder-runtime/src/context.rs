@@ -1,9 +1,9 @@
-use std::{borrow::Cow, cell::RefCell, mem::{self, ManuallyDrop}, os::fd::BorrowedFd, sync::Arc};
+use std::{borrow::Cow, cell::RefCell, mem::{self, ManuallyDrop}, sync::Arc, time::Instant};
 
 use libbinder::{command_buffer::{Command, CommandBuffer}, packet::Packet as libbinder_Packet, return_buffer::{ReturnBuffer, ReturnValue}};
-use libbinder_raw::{transaction::TransactionFlag, types::reference::ObjectRefLocal};
+use libbinder_raw::{device::BinderDevice, transaction::TransactionFlag, types::reference::ObjectRefLocal};
 
-use crate::{ArcRuntime, object::{self, Object}, packet::Packet};
+use crate::{ArcRuntime, identity::CallingIdentity, object::{self, Object}, packet::Packet};
 
 struct Session {
   ret_buf: (Vec<ReturnValue<'static>>, Vec<u8>),
@@ -18,7 +18,7 @@ pub struct Context {
 const RET_BUF_SIZE: usize = 8 * 1024 * 1024;
 
 impl Context {
-  pub fn new(binder_dev: BorrowedFd<'_>) -> Self {
+  pub fn new(binder_dev: &BinderDevice) -> Self {
     Self {
       bufs: RefCell::new(Some(Session {
         ret_buf: ReturnBuffer::new(binder_dev, RET_BUF_SIZE).into_buffers(),
@@ -51,9 +51,11 @@ impl Context {
     
     loop {
       let session = self.bufs.borrow_mut().take().unwrap();
-      let mut ret_buf: ReturnBuffer<'runtime> = ReturnBuffer::from_buffers(runtime.get_binder(), session.ret_buf);
-      let mut cmd_buf: CommandBuffer<'runtime, 'data> = CommandBuffer::from_buffers(runtime.get_binder(), session.cmd_buf);
+      let mut ret_buf: ReturnBuffer<'runtime> = ReturnBuffer::from_buffers(runtime.get_binder_device(), session.ret_buf);
+      let mut cmd_buf: CommandBuffer<'runtime, 'data> = CommandBuffer::from_buffers(runtime.get_binder_device(), session.cmd_buf);
       let mut queued_transactions: Vec<(ObjectRefLocal, libbinder_Packet<'runtime>)> = unsafe { std::mem::transmute(session.queued_transactions) };
+      let mut queued_deaths = Vec::new();
+      let mut queued_freezes = Vec::new();
       
       // Run initial commands
       if is_initial {
@@ -85,14 +87,14 @@ impl Context {
             match ret {
               ReturnValue::Acquire(_) => {
                 if ref_state.0 != false {
-                  panic!("Kernel sent BC_ACQUIRE when object's strong ref count is nonzero");
+                  panic!("Kernel sent BR_ACQUIRE when object's strong ref count is nonzero");
                 }
                 ref_state.0 = true;
                 kill_object = false;
               },
               ReturnValue::Release(_) => {
-                if ref_state.0 != false {
-                  panic!("Kernel sent BC_RELEASE when object's strong ref count is zero");
+                if ref_state.0 == false {
+                  panic!("Kernel sent BR_RELEASE when object's strong ref count is zero");
                 }
                 ref_state.0 = false;
                 
@@ -105,14 +107,14 @@ impl Context {
               },
               ReturnValue::AcquireWeak(_) => {
                 if ref_state.1 != false {
-                  panic!("Kernel sent BC_INCREFS when object's weak ref count is nonzero");
+                  panic!("Kernel sent BR_INCREFS when object's weak ref count is nonzero");
                 }
                 ref_state.1 = true;
                 kill_object = false;
               },
               ReturnValue::ReleaseWeak(_) => {
-                if ref_state.1 != false {
-                  panic!("Kernel sent BC_DECREFS when object's weak ref count is zero");
+                if ref_state.1 == false {
+                  panic!("Kernel sent BR_DECREFS when object's weak ref count is zero");
                 }
                 ref_state.1 = false;
                 
@@ -130,21 +132,47 @@ impl Context {
               ref_states.remove(local_ref).unwrap();
               unsafe { Arc::decrement_strong_count(Arc::as_ptr(&obj)) };
             }
+            drop(ref_states);
+            
+            // Kernel keeps the node pending until acknowledged
+            let ack = match ret {
+              ReturnValue::Acquire(_) => Some(Command::AcquireDone(*local_ref)),
+              ReturnValue::AcquireWeak(_) => Some(Command::AcquireWeakDone(*local_ref)),
+              _ => None
+            };
+            
+            if let Some(ack) = ack {
+              CommandBuffer::new(runtime.get_binder_device())
+                .enqueue_command(ack)
+                .exec_always_block(None)
+                .unwrap();
+            }
           }
           ReturnValue::Reply(_) => if is_initial { ret_handle_func(&ret) },
           ReturnValue::TransactionFailed => if is_initial { ret_handle_func(&ret) },
           ReturnValue::Ok => if is_initial { ret_handle_func(&ret) },
           ReturnValue::Error(e) => panic!("Error from binder {e}"),
-          ReturnValue::SpawnLooper => (),
+          ReturnValue::SpawnLooper => runtime.spawn_looper(),
           ReturnValue::TransactionComplete => if is_initial { ret_handle_func(&ret) },
           ReturnValue::DeadReply => if is_initial { ret_handle_func(&ret) },
+          ReturnValue::FrozenReply => if is_initial { ret_handle_func(&ret) },
+          ReturnValue::TransactionPendingFrozen => if is_initial { ret_handle_func(&ret) },
           ReturnValue::Noop => (),
+          ReturnValue::DeadBinder(cookie) => queued_deaths.push(*cookie),
+          ReturnValue::ClearDeathNotificationDone(_) => (),
+          ReturnValue::FrozenBinder(cookie, is_frozen) => queued_freezes.push((*cookie, *is_frozen)),
+          ReturnValue::ClearFreezeNotificationDone(_) => (),
+          ReturnValue::OnewaySpamSuspect => if is_initial { ret_handle_func(&ret) },
+          ReturnValue::AcquireResult(_) => (),
+          ReturnValue::AttemptAcquire(_, _) => (),
+          ReturnValue::Finished => (),
+          ReturnValue::Unknown(_) => ()
         }
       }
       
       is_initial = false;
       
-      if queued_transactions.is_empty() {
+      if queued_transactions.is_empty() && queued_deaths.is_empty() && queued_freezes.is_empty() {
         // Put back the original buffers
         *self.bufs.borrow_mut() = Some(Session {
           cmd_buf: cmd_buf.into_buffers(),
@@ -165,9 +193,25 @@ impl Context {
         for (obj, packet) in queued_transactions.drain(..) {
           let obj = ManuallyDrop::new(unsafe { object::from_local_ref(obj.clone()) });
           let packet = Packet::new(runtime, packet);
+          if packet.get_flags().contains(TransactionFlag::OneWay) {
+            let handler = runtime.____rt.incoming_oneway_spam.lock()
+              .unwrap()
+              .as_mut()
+              .and_then(|x| x.record(packet.get_sender_uid(), Instant::now()));
+            
+            if let Some(handler) = handler {
+              handler(packet.get_sender_uid());
+            }
+          }
+          
+          let prev_identity = CallingIdentity::enter(CallingIdentity {
+            pid: packet.get_sender_pid(),
+            uid: packet.get_sender_uid()
+          });
           let reply = obj.do_transaction(&packet).unwrap();
+          CallingIdentity::leave(prev_identity);
           
-          let mut cmd_buf = CommandBuffer::new(runtime.get_binder());
+          let mut cmd_buf = CommandBuffer::new(runtime.get_binder_device());
           
           if packet.get_flags().contains(TransactionFlag::OneWay) {
             assert!(reply.is_none(), "This one way transaction!");
@@ -180,6 +224,46 @@ impl Context {
         }
         
         queued_transactions.clear();
+        
+        // Recipients are called outside so they can do
+        // transactions of their own
+        for cookie in queued_deaths.drain(..) {
+          let recipients = runtime.____rt.death_recipients.lock()
+            .unwrap()
+            .remove(&(cookie as u32))
+            .unwrap_or_default();
+          
+          for recipient in recipients {
+            recipient.binder_died();
+          }
+          
+          CommandBuffer::new(runtime.get_binder_device())
+            .enqueue_command(Command::DeadBinderDone(cookie))
+            .exec_always_block(None)
+            .unwrap();
+        }
+        
+        for (cookie, is_frozen) in queued_freezes.drain(..) {
+          let listeners = runtime.____rt.freeze_listeners.lock()
+            .unwrap()
+            .get_mut(&(cookie as u32))
+            .map(|(state, listeners)| {
+              *state = Some(is_frozen);
+              listeners.clone()
+            })
+            .unwrap_or_default();
+          
+          for listener in listeners {
+            listener.frozen_state_changed(is_frozen);
+          }
+          
+          // Kernel holds next notification for this handle until this
+          CommandBuffer::new(runtime.get_binder_device())
+            .enqueue_command(Command::FreezeNotificationDone(cookie))
+            .exec_always_block(None)
+            .unwrap();
+        }
+        
         // Loop back again to check new entry
       }
     }
@@ -0,0 +1,12 @@
+// Receives notification when remote object's process dies
+// it is called from the thread which read BR_DEAD_BINDER
+// normally the background worker thread
+pub trait DeathRecipient: Sync + Send + 'static {
+  fn binder_died(&self);
+}
+
+impl<F: Fn() + Sync + Send + 'static> DeathRecipient for F {
+  fn binder_died(&self) {
+    self()
+  }
+}
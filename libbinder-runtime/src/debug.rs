@@ -0,0 +1,77 @@
+use libbinder_raw::{node_info::{self, NodeDebugInfo}, types::reference::ObjectRefLocal};
+use nix::errno::Errno;
+
+use crate::{ArcRuntime, object::Object};
+
+// A local object as seen by kernel and by the runtime
+pub struct KernelNode {
+  pub kernel: NodeDebugInfo,
+  
+  // Runtime's view of (strong, weak) from reference_states,
+  // None if runtime does not track it, which means leak
+  // or runtime already released it but kernel haven't
+  pub runtime_state: Option<(bool, bool)>
+}
+
+impl KernelNode {
+  pub fn get_local_ref(&self) -> ObjectRefLocal {
+    self.kernel.local_ref
+  }
+  
+  // Whether kernel and runtime agrees about the references
+  pub fn is_consistent(&self) -> bool {
+    self.runtime_state == Some((self.kernel.has_strong_ref, self.kernel.has_weak_ref))
+  }
+}
+
+// Iterates over all nodes kernel has for this process, each
+// step asks kernel so nodes created or released meanwhile
+// may or may not be seen
+pub struct KernelNodes<'runtime, Mgr: Object<Mgr> + ?Sized> {
+  runtime: &'runtime ArcRuntime<Mgr>,
+  last_ptr: usize,
+  is_done: bool
+}
+
+impl<'runtime, Mgr: Object<Mgr> + ?Sized> KernelNodes<'runtime, Mgr> {
+  pub(crate) fn new(runtime: &'runtime ArcRuntime<Mgr>) -> Self {
+    Self {
+      runtime,
+      last_ptr: 0,
+      is_done: false
+    }
+  }
+}
+
+impl<'runtime, Mgr: Object<Mgr> + ?Sized> Iterator for KernelNodes<'runtime, Mgr> {
+  type Item = Result<KernelNode, Errno>;
+  
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.is_done {
+      return None;
+    }
+    
+    let info = match node_info::binder_node_debug_info(self.runtime.get_binder(), self.last_ptr) {
+      Ok(Some(x)) => x,
+      Ok(None) => {
+        self.is_done = true;
+        return None;
+      },
+      Err(e) => {
+        self.is_done = true;
+        return Some(Err(e));
+      }
+    };
+    
+    self.last_ptr = info.local_ref.data;
+    let runtime_state = self.runtime.____rt.reference_states.lock()
+      .unwrap()
+      .get(&info.local_ref)
+      .copied();
+    
+    Some(Ok(KernelNode {
+      kernel: info,
+      runtime_state
+    }))
+  }
+}
@@ -0,0 +1,12 @@
+// Receives notification when remote object's process is
+// frozen or thawed, it is called from the thread which read
+// BR_FROZEN_BINDER normally the background worker thread
+pub trait FreezeListener: Sync + Send + 'static {
+  fn frozen_state_changed(&self, is_frozen: bool);
+}
+
+impl<F: Fn(bool) + Sync + Send + 'static> FreezeListener for F {
+  fn frozen_state_changed(&self, is_frozen: bool) {
+    self(is_frozen)
+  }
+}
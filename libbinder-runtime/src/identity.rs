@@ -0,0 +1,56 @@
+use std::cell::Cell;
+
+use nix::{libc, unistd};
+
+// Who is the caller of transaction currently being handled
+// by this thread. When not handling any transaction, it is
+// this process itself
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CallingIdentity {
+  // For one way transaction kernel does not tell the pid
+  // and it is 0
+  pub pid: libc::pid_t,
+  pub uid: libc::uid_t
+}
+
+thread_local! {
+  static CURRENT_IDENTITY: Cell<Option<CallingIdentity>> = const { Cell::new(None) };
+}
+
+impl CallingIdentity {
+  fn this_process() -> Self {
+    Self {
+      pid: unistd::getpid().as_raw(),
+      uid: unistd::geteuid().as_raw()
+    }
+  }
+  
+  pub fn current() -> Self {
+    CURRENT_IDENTITY.get().unwrap_or_else(Self::this_process)
+  }
+  
+  // Make current identity this process, mainly used when
+  // handler calls out to other services and wants them to
+  // see this process instead of the original caller.
+  //
+  // Returns the previous one, to be given to 'restore'
+  pub fn clear() -> Self {
+    let prev = Self::current();
+    CURRENT_IDENTITY.set(None);
+    prev
+  }
+  
+  pub fn restore(identity: Self) {
+    CURRENT_IDENTITY.set(Some(identity));
+  }
+  
+  // Used by runtime around the call to Object::do_transaction
+  // returns previous state so nested transaction restore it
+  pub(crate) fn enter(identity: Self) -> Option<Self> {
+    CURRENT_IDENTITY.replace(Some(identity))
+  }
+  
+  pub(crate) fn leave(prev: Option<Self>) {
+    CURRENT_IDENTITY.set(prev);
+  }
+}
@@ -1,44 +1,73 @@
 #![feature(ptr_metadata)]
 
-use std::{collections::HashMap, os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd}, ptr, sync::{Arc, Mutex, RwLock, Weak, atomic::{AtomicBool, AtomicU64, Ordering}}, thread::{self, JoinHandle}};
+use std::{collections::HashMap, error::Error, fmt::Display, mem, os::fd::{AsFd, BorrowedFd, OwnedFd}, sync::{Arc, Mutex, RwLock, Weak, atomic::{AtomicBool, AtomicU64, Ordering}}, thread::{self, JoinHandle}};
 
 use libbinder::command_buffer::{Command, CommandBuffer};
-use libbinder_raw::types::reference::{CONTEXT_MANAGER_REF, ObjectRefLocal, ObjectRefRemote};
-use nix::libc;
+use libbinder_raw::{device::BinderDevice, types::reference::{CONTEXT_MANAGER_REF, ObjectRefFlags, ObjectRefLocal, ObjectRefRemote}};
+use nix::{errno::Errno, libc};
 use thread_local::ThreadLocal;
 
-use crate::{object::Object, packet::builder::PacketBuilder, proxy::{Proxy, SelfMananger}, util::OwnedMmap, worker::worker};
+use crate::{death::DeathRecipient, debug::KernelNodes, freeze::FreezeListener, object::Object, packet::builder::PacketBuilder, proxy::{Proxy, SelfMananger}, spam::IncomingOnewaySpam, worker::worker};
 
+pub mod death;
+pub mod debug;
+pub mod freeze;
+pub mod identity;
 pub mod object;
 pub mod packet;
 pub mod proxy;
 pub mod reference;
 
-mod util;
 mod worker;
 mod context;
+mod spam;
 
 pub(crate) struct Shared<Mgr: Object<Mgr> + ?Sized> {
-  pub(crate) binder_dev: Arc<OwnedFd>,
+  pub(crate) binder_dev: Arc<BinderDevice>,
   mgr: RwLock<(Option<Arc<Mgr>>, Option<ObjectRefLocal>)>,
   
-  // Used by Binder to store incoming transaction and buffer :3
-  // don't need to be used
-  _binder_mem: OwnedMmap,
   shutdown_pipe_wr: OwnedFd,
-  _shutdown_pipe_ro: Arc<OwnedFd>,
-  worker: Mutex<Option<JoinHandle<()>>>,
+  shutdown_pipe_ro: Arc<OwnedFd>,
+  
+  // The first one is the main looper, rest are
+  // spawned when kernel asks with BR_SPAWN_LOOPER
+  workers: Mutex<Vec<JoinHandle<()>>>,
   is_looper_stopped: AtomicBool,
   
   // .0 is there any strong reference from outside
   // .1 is there any weak reference from outside
-  // .0 and .1 will never be false at same time
+  // .0 and .1 are both false only while the object is sent
+  // but kernel haven't sent BR_INCREFS/BR_ACQUIRE yet
+  //
+  // Runtime holds one strong count of the object for each entry
   reference_states: Mutex<HashMap<ObjectRefLocal, (bool, bool)>>,
   
   // The ref count here may be touched to 0 when only read lock
   // is taken, check again when upgrade to write lock
   remote_reference_counters: RwLock<HashMap<ObjectRefRemote, AtomicU64>>,
   
+  // Number of weak references to the remote, the runtime
+  // holds one BC_INCREFS for each nonzero entry
+  weak_remote_reference_counters: Mutex<HashMap<ObjectRefRemote, u64>>,
+  
+  // Kernel only allows one death notification per handle, so
+  // all recipients for a handle shares one and the handle is
+  // used as the cookie
+  death_recipients: Mutex<HashMap<u32, Vec<Arc<dyn DeathRecipient>>>>,
+  
+  // Same as death notification, one per handle and handle is
+  // the cookie. Also remembers last known state so listeners
+  // added later don't have to wait for next change
+  freeze_listeners: Mutex<HashMap<u32, (Option<bool>, Vec<Arc<dyn FreezeListener>>)>>,
+  
+  // Called with the target, when a one way transaction
+  // from this process is suspected as spam
+  oneway_spam_handler: RwLock<Option<Arc<dyn Fn(&Proxy<Mgr>) + Sync + Send>>>,
+  
+  // Counts one way transactions coming to this process, see
+  // set_incoming_oneway_spam_handler
+  incoming_oneway_spam: Mutex<Option<IncomingOnewaySpam>>,
+  
   exec_context: ThreadLocal<context::Context>
 }
 
@@ -47,10 +76,19 @@ unsafe impl<Mgr: Object<Mgr> + ?Sized> Send for Shared<Mgr> {}
 
 impl<Mgr: Object<Mgr> + ?Sized> Shared<Mgr> {
   fn stop_looper(&self) {
-    let handle = self.worker.lock().unwrap().take();
-    if let Some(thrd) = handle {
-      nix::unistd::write(self.shutdown_pipe_wr.as_fd(), "UwU".as_bytes()).unwrap();
-      
+    // Leaves it empty so no more loopers get spawned
+    let handles = mem::take(&mut *self.workers.lock().unwrap());
+    if handles.is_empty() {
+      return;
+    }
+    
+    nix::unistd::write(self.shutdown_pipe_wr.as_fd(), "UwU".as_bytes()).unwrap();
+    
+    // Closing any fd to binder makes kernel kick out threads
+    // blocked waiting for work, so they can see the pipe
+    drop(self.binder_dev.as_fd().try_clone_to_owned().unwrap());
+    
+    for thrd in handles {
       if thread::current().id() != thrd.thread().id() {
         thrd.join().unwrap();
       }
@@ -67,7 +105,7 @@ impl<Mgr: Object<Mgr> + ?Sized> Drop for Shared<Mgr> {
       drop(unsafe { object::from_local_ref::<Mgr>(local_ref) });
     }
     
-    let mut buf = CommandBuffer::new(self.binder_dev.as_fd());
+    let mut buf = CommandBuffer::new(&self.binder_dev);
     for (&remote_ref, counter) in self.remote_reference_counters.get_mut().unwrap().iter_mut() {
       if *counter.get_mut() == 0 {
         // There was stale reference inside
@@ -93,53 +131,89 @@ impl<Mgr: Object<Mgr> + ?Sized> Clone for ArcRuntime<Mgr> {
   }
 }
 
-pub fn new_proxy_manager<B: Into<OwnedFd>>(binder_dev: B) -> Result<ArcRuntime<SelfMananger>, ()> {
+// Same default as Android's libbinder
+pub const DEFAULT_MAX_THREADS: u32 = 15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuntimeError {
+  SetMaxThreads(Errno),
+  
+  // e.g. EBUSY if other process already is the manager
+  SetContextManager(Errno),
+  
+  // Manager wants security context but kernel can't give it
+  SecurityContextNotSupported
+}
+
+impl Display for RuntimeError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      RuntimeError::SetMaxThreads(e) => write!(f, "cannot set max threads: {e}"),
+      RuntimeError::SetContextManager(e) => write!(f, "cannot become context manager: {e}"),
+      RuntimeError::SecurityContextNotSupported => write!(f, "kernel does not support security context")
+    }
+  }
+}
+
+impl Error for RuntimeError {}
+
+pub fn new_proxy_manager(binder_dev: BinderDevice) -> Result<ArcRuntime<SelfMananger>, RuntimeError> {
   ArcRuntime::new(binder_dev, |_, proxy| SelfMananger(proxy))
 }
 
 impl<Mgr: Object<Mgr>> ArcRuntime<Mgr> {
-  pub fn new<F, B: Into<OwnedFd>>(binder_dev: B, manager_proxy_provider: F) -> Result<Self, ()>
+  pub fn new<F>(binder_dev: BinderDevice, manager_proxy_provider: F) -> Result<Self, RuntimeError>
+    where F: FnOnce(ArcRuntime<Mgr>, Proxy<Mgr>) -> Mgr
+  {
+    Self::new_with_max_threads(binder_dev, DEFAULT_MAX_THREADS, manager_proxy_provider)
+  }
+  
+  pub fn new_as_manager<F>(binder_dev: BinderDevice, manager_provider: F) -> Result<Self, RuntimeError>
+    where F: FnOnce(ArcRuntime<Mgr>) -> Mgr
+  {
+    Self::new_as_manager_with_max_threads(binder_dev, DEFAULT_MAX_THREADS, manager_provider)
+  }
+  
+  // max_threads is how many looper threads kernel may ask
+  // the runtime to spawn on top of the main one, zero means
+  // only the main looper handles incoming transactions
+  pub fn new_with_max_threads<F>(binder_dev: BinderDevice, max_threads: u32, manager_proxy_provider: F) -> Result<Self, RuntimeError>
     where F: FnOnce(ArcRuntime<Mgr>, Proxy<Mgr>) -> Mgr
   {
-    let rt = Self::new_impl(binder_dev)?;
+    let rt = Self::new_impl(binder_dev, max_threads)?;
     let mgr = Arc::new(manager_proxy_provider(rt.clone(), Proxy::new(rt.downgrade(), CONTEXT_MANAGER_REF)));
     *rt.____rt.mgr.write().unwrap() = (Some(mgr), None);
     Ok(rt)
   }
   
-  pub fn new_as_manager<F, B: Into<OwnedFd>>(binder_dev: B, manager_provider: F) -> Result<Self, ()>
+  pub fn new_as_manager_with_max_threads<F>(binder_dev: BinderDevice, max_threads: u32, manager_provider: F) -> Result<Self, RuntimeError>
     where F: FnOnce(ArcRuntime<Mgr>) -> Mgr
   {
-    let rt = Self::new_impl(binder_dev)?;
+    let rt = Self::new_impl(binder_dev, max_threads)?;
     let mgr = Arc::new(manager_provider(rt.clone()));
+    let flags = object::local_ref_flags(mgr.as_ref());
+    if flags.contains(ObjectRefFlags::SendSecurityContext) && rt.get_binder_device().get_capabilities().security_context.is_unsupported() {
+      return Err(RuntimeError::SecurityContextNotSupported);
+    }
+    
     let mgr_ref = object::into_local_ref(mgr.clone());
     *rt.____rt.mgr.write().unwrap() = (Some(mgr), Some(mgr_ref));
-    rt.____rt.reference_states.lock().unwrap().insert(mgr_ref, (true, false));
     
-    libbinder_raw::binder_set_context_mgr(rt.____rt.binder_dev.as_fd(), &mgr_ref).unwrap();
+    // Kernel treats context manager as already having
+    // strong and weak reference and never acquire it
+    rt.____rt.reference_states.lock().unwrap().insert(mgr_ref, (true, true));
+    
+    libbinder_raw::binder_set_context_mgr(rt.____rt.binder_dev.as_fd(), &mgr_ref, flags).map_err(RuntimeError::SetContextManager)?;
     
     Ok(rt)
   }
   
-  fn new_impl<B: Into<OwnedFd>>(binder_dev: B) -> Result<Self, ()> {
-    let binder_dev = Arc::new(binder_dev.into());
-    let binder_mem = {
-      let len = 8 * 1024 * 1024;
-      let ptr = unsafe {
-          libc::mmap(ptr::null_mut(),
-            len,
-            libc::PROT_READ,
-            libc::MAP_PRIVATE,
-            binder_dev.as_raw_fd(),
-            0
-          )
-        };
-      
-      OwnedMmap {
-        ptr: ptr.cast(),
-        len
-      }
-    };
+  fn new_impl(binder_dev: BinderDevice, max_threads: u32) -> Result<Self, RuntimeError> {
+    let binder_dev = Arc::new(binder_dev);
+    
+    // Set before main looper starts, so kernel can
+    // ask for more right from first transaction
+    libbinder_raw::binder_set_max_threads(binder_dev.as_fd(), max_threads).map_err(RuntimeError::SetMaxThreads)?;
     
     let ret  = ArcRuntime {
       ____rt: Arc::new_cyclic(|weak| {
@@ -153,15 +227,19 @@ impl<Mgr: Object<Mgr>> ArcRuntime<Mgr> {
         
         Shared {
           mgr: RwLock::new((None, None)),
-          _binder_mem: binder_mem,
           is_looper_stopped: AtomicBool::new(false),
-          worker: Mutex::new(Some(thread::spawn(move || {
-            worker(binder_dev2, weak_rt, ro2)
-          }))),
+          workers: Mutex::new(vec![thread::spawn(move || {
+            worker(binder_dev2, weak_rt, ro2, true)
+          })]),
           reference_states: Mutex::new(HashMap::new()),
           remote_reference_counters: RwLock::new(HashMap::new()),
+          weak_remote_reference_counters: Mutex::new(HashMap::new()),
+          death_recipients: Mutex::new(HashMap::new()),
+          freeze_listeners: Mutex::new(HashMap::new()),
+          oneway_spam_handler: RwLock::new(None),
+          incoming_oneway_spam: Mutex::new(None),
           shutdown_pipe_wr: wr,
-          _shutdown_pipe_ro: ro,
+          shutdown_pipe_ro: ro,
           exec_context: ThreadLocal::new(),
           binder_dev
         }
@@ -194,6 +272,105 @@ impl<Mgr: Object<Mgr> + ?Sized> ArcRuntime<Mgr> {
     self.____rt.binder_dev.as_fd()
   }
   
+  pub fn get_binder_device(&self) -> &BinderDevice {
+    &self.____rt.binder_dev
+  }
+  
+  // Makes kernel tell this process when one way transaction
+  // it sent got flagged as spam by the target's kernel, see
+  // set_oneway_spam_handler. Doesn't affect incoming ones
+  pub fn set_oneway_spam_detection(&self, enable: bool) -> Result<(), Errno> {
+    if self.get_binder_device().get_capabilities().oneway_spam_detection.is_unsupported() {
+      return Err(Errno::EOPNOTSUPP);
+    }
+    libbinder_raw::binder_enable_oneway_spam_detection(self.get_binder(), enable)
+  }
+  
+  // The handler is called when a one way transaction sent from
+  // this process is flagged as spam. Kernel only reports it after
+  // set_oneway_spam_detection(true) on this process. Transaction
+  // is still delivered and returns Ok
+  pub fn set_oneway_spam_handler(&self, handler: Option<Arc<dyn Fn(&Proxy<Mgr>) + Sync + Send>>) {
+    *self.____rt.oneway_spam_handler.write().unwrap() = handler;
+  }
+  
+  // The handler is called with sender's uid when it sends more
+  // than max_per_second one way transactions to this process.
+  // It is counted by runtime from transactions it receives
+  pub fn set_incoming_oneway_spam_handler(&self, max_per_second: u32, handler: Option<Arc<dyn Fn(libc::uid_t) + Sync + Send>>) {
+    *self.____rt.incoming_oneway_spam.lock().unwrap() = handler.map(|x| IncomingOnewaySpam::new(max_per_second, x));
+  }
+  
+  // All local objects kernel knows about, along with
+  // the runtime's view on them. For debugging leaks
+  pub fn iter_kernel_nodes(&self) -> KernelNodes<'_, Mgr> {
+    KernelNodes::new(self)
+  }
+  
+  pub(crate) fn acquire_weak_remote(&self, remote_ref: ObjectRefRemote) {
+    let mut counters = self.____rt.weak_remote_reference_counters.lock().unwrap();
+    let counter = counters.entry(remote_ref).or_insert(0);
+    if *counter == 0 {
+      let mut cmd_buf = CommandBuffer::new(self.get_binder_device());
+      cmd_buf.enqueue_command(Command::AcquireWeak(remote_ref));
+      cmd_buf.exec_always_block(None).unwrap();
+    }
+    *counter += 1;
+  }
+  
+  pub(crate) fn release_weak_remote(&self, remote_ref: ObjectRefRemote) {
+    let mut counters = self.____rt.weak_remote_reference_counters.lock().unwrap();
+    let counter = counters.get_mut(&remote_ref).expect("Releasing weak remote which was not acquired");
+    *counter -= 1;
+    if *counter == 0 {
+      counters.remove(&remote_ref);
+      
+      let mut cmd_buf = CommandBuffer::new(self.get_binder_device());
+      
+      // Proxies dropped while this was held left them
+      let has_strong = self.____rt.remote_reference_counters.read()
+        .unwrap()
+        .get(&remote_ref)
+        .is_some_and(|x| x.load(Ordering::Relaxed) > 0);
+      if !has_strong {
+        self.enqueue_clear_notifications(&mut cmd_buf, remote_ref);
+      }
+      
+      cmd_buf.enqueue_command(Command::ReleaseWeak(remote_ref));
+      cmd_buf.exec_always_block(None).unwrap();
+    }
+  }
+  
+  // Handle number may be reused later, so clear the notifications
+  // before letting go of the handle else kernel keeps delivering
+  // them to a handle nobody tracks
+  pub(crate) fn enqueue_clear_notifications(&self, cmd_buf: &mut CommandBuffer, remote_ref: ObjectRefRemote) {
+    let cookie = remote_ref.data_handle as usize;
+    if self.____rt.death_recipients.lock().unwrap().remove(&remote_ref.data_handle).is_some() {
+      cmd_buf.enqueue_command(Command::ClearDeathNotification(remote_ref, cookie));
+    }
+    if self.____rt.freeze_listeners.lock().unwrap().remove(&remote_ref.data_handle).is_some() {
+      cmd_buf.enqueue_command(Command::ClearFreezeNotification(remote_ref, cookie));
+    }
+  }
+  
+  // Called when kernel sends BR_SPAWN_LOOPER
+  pub(crate) fn spawn_looper(&self) {
+    let mut workers = self.____rt.workers.lock().unwrap();
+    if workers.is_empty() {
+      // Runtime is shutting down, kernel will
+      // just use the existing ones
+      return;
+    }
+    
+    let binder_dev = self.____rt.binder_dev.clone();
+    let weak_rt = self.downgrade();
+    let shutdown_pipe_ro = self.____rt.shutdown_pipe_ro.clone();
+    workers.push(thread::spawn(move || {
+      worker(binder_dev, weak_rt, shutdown_pipe_ro, false)
+    }));
+  }
+  
   pub fn stop_background_threads(&self) {
     if self.____rt.is_looper_stopped.swap(true, Ordering::Relaxed) {
       panic!("Looper already stopped");
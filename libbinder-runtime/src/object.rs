@@ -1,23 +1,35 @@
-use std::{any::Any, fmt::{Debug, Display}, mem, ptr::{self, DynMetadata}, sync::Arc};
+use std::{any::Any, error::Error, fmt::{Debug, Display}, mem, ptr::{self, DynMetadata}, sync::Arc};
 
-use libbinder_raw::types::reference::ObjectRefLocal;
+use enumflags2::BitFlags;
+use libbinder_raw::types::reference::{ObjectRefFlags, ObjectRefLocal};
+use nix::errno::Errno;
 
 use crate::{packet::Packet, proxy::Proxy};
 
 pub enum TransactionError {
   // The target of reply/transaction, no longer exist
-  UnreachableTarget,
+  // errno is the reason kernel gave, if any
+  UnreachableTarget(Option<Errno>),
   
   // The transaction is sent, but then target dies
   // no reply is given but the transaction did sent
   NoReply,
   
-  // Transaction did not sent at all
-  FailedReply,
+  // Transaction did not sent at all, errno is the
+  // reason kernel gave (e.g. ENOSPC, EPERM), if any
+  FailedReply(Option<Errno>),
   
   // The reply was malformed
   MalformedReply,
   
+  // The target's process is frozen, the transaction
+  // did not get sent
+  FrozenTarget,
+  
+  // Kernel does not support something the packet needs
+  // (e.g. buffer objects), the transaction did not get sent
+  NotSupported,
+  
   // Error message from local, in this case the transaction did not get sent
   // runtime never uses this, it exists for convenience
   LocalError(Box<dyn Display>),
@@ -31,10 +43,14 @@ pub enum TransactionError {
 impl Debug for TransactionError {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match self {
-      TransactionError::UnreachableTarget => writeln!(f, "UnreachableTarget"),
+      TransactionError::UnreachableTarget(None) => writeln!(f, "UnreachableTarget"),
+      TransactionError::UnreachableTarget(Some(errno)) => writeln!(f, "UnreachableTarget: {errno}"),
       TransactionError::NoReply => writeln!(f, "NoReply"),
-      TransactionError::FailedReply =>  writeln!(f, "FailedReply"),
+      TransactionError::FailedReply(None) =>  writeln!(f, "FailedReply"),
+      TransactionError::FailedReply(Some(errno)) =>  writeln!(f, "FailedReply: {errno}"),
       TransactionError::MalformedReply =>  writeln!(f, "MalformedReply"),
+      TransactionError::FrozenTarget =>  writeln!(f, "FrozenTarget"),
+      TransactionError::NotSupported =>  writeln!(f, "NotSupported"),
       TransactionError::LocalError(display) => display.fmt(f),
       TransactionError::RemoteError(display) => display.fmt(f)
     }
@@ -48,10 +64,62 @@ impl Debug for TransactionError {
 // sent outside
 pub trait Object<Mgr: Object<Mgr> + ?Sized>: Sync + Send + Any + 'static {
   fn do_transaction<'packet, 'runtime>(&self, packet: &'packet Packet<'runtime, Mgr>) -> Result<Option<Packet<'runtime, Mgr>>, TransactionError>;
+  
+  // Whether incoming packets should carry the caller's
+  // security context. Kernel only asks this once, when
+  // the object is sent out for the first time
+  fn wants_security_context(&self) -> bool {
+    false
+  }
+  
+  // Nice value which thread handling incoming transaction
+  // runs at most, caller with lower nice value keeps its
+  // own. Same as above kernel only asks this once
+  fn min_priority(&self) -> u8 {
+    0
+  }
+  
+  // Whether kernel should deliver transactions containing
+  // fds to this object, those are rejected otherwise
+  fn accepts_fds(&self) -> bool {
+    true
+  }
+}
+
+// Flags of flat_binder_object when sending local object
+pub(crate) fn local_ref_flags<Mgr: Object<Mgr> + ?Sized, T: Object<Mgr> + ?Sized>(obj: &T) -> BitFlags<ObjectRefFlags> {
+  let mut flags = ObjectRefFlags::from_priority_bits(obj.min_priority());
+  if obj.accepts_fds() {
+    flags |= ObjectRefFlags::AcceptFds;
+  }
+  if obj.wants_security_context() {
+    flags |= ObjectRefFlags::SendSecurityContext;
+  }
+  flags
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FromProxyError {
+  // Remote object implements some other interface
+  WrongInterface,
+  
+  // Remote object couldn't be asked what it is (e.g. it died)
+  Unreachable
 }
 
+impl Display for FromProxyError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      FromProxyError::WrongInterface => write!(f, "remote object implements other interface"),
+      FromProxyError::Unreachable => write!(f, "remote object is unreachable")
+    }
+  }
+}
+
+impl Error for FromProxyError {}
+
 pub trait FromProxy<Mgr: Object<Mgr> + ?Sized>: Object<Mgr> + Sized {
-  fn from_proxy(proxy: Proxy<Mgr>) -> Result<Self, ()>;
+  fn from_proxy(proxy: Proxy<Mgr>) -> Result<Self, FromProxyError>;
 }
 
 // Does not touch the reference counter
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use enumflags2::BitFlags;
-use libbinder::formats::WriteFormat;
+use libbinder::formats::{WriteError, WriteFormat};
 use libbinder_raw::{transaction::TransactionFlag, types::reference::ObjectRef};
 
 use crate::{ArcRuntime, object::Object, packet::{Packet, writer::Writer}};
@@ -13,7 +13,8 @@ pub struct PacketBuilder<'runtime, Mgr: Object<Mgr> + ?Sized> {
   // This are living reference that must be kept
   // this is non empty, if packet builder was made
   // from packet which has some references inside
-  pub(super) _kept_refs: Vec<(ObjectRef, Option<Arc<dyn Object<Mgr>>>)>
+  // or weak local reference was written
+  pub(super) kept_refs: Vec<(ObjectRef, Option<Arc<dyn Object<Mgr>>>)>
 }
 
 impl<'packet, 'runtime: 'packet, Mgr: Object<Mgr> + ?Sized> PacketBuilder<'runtime, Mgr> {
@@ -21,7 +22,7 @@ impl<'packet, 'runtime: 'packet, Mgr: Object<Mgr> + ?Sized> PacketBuilder<'runti
     Self {
       builder: libbinder::packet::builder::PacketBuilder::new(runtime.get_binder()),
       runtime,
-      _kept_refs: Vec::new()
+      kept_refs: Vec::new()
     }
   }
   
@@ -42,12 +43,21 @@ impl<'packet, 'runtime: 'packet, Mgr: Object<Mgr> + ?Sized> PacketBuilder<'runti
   pub fn writer<Format: WriteFormat<'packet>>(&'packet mut self, format: Format) -> Writer<'packet, 'runtime, Format, Mgr> {
     Writer {
       runtime: self.runtime,
-      writer: self.builder.writer(format)
+      writer: self.builder.writer(format),
+      kept_refs: &mut self.kept_refs
     }
   }
   
-  pub fn build(mut self) -> Packet<'runtime, Mgr> {
-    Packet::new(self.runtime, self.builder.build())
+  pub fn build(mut self) -> Result<Packet<'runtime, Mgr>, WriteError> {
+    let mut packet = self.builder.build()?;
+    
+    // Readers are able to take fds out of the reply
+    // so let kernel deliver replies containing them
+    if !packet.get_flags().contains(TransactionFlag::OneWay) {
+      packet.set_flags(packet.get_flags() | TransactionFlag::AcceptFds);
+    }
+    
+    Ok(Packet::new(self.runtime, packet))
   }
 }
 
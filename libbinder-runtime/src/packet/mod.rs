@@ -1,4 +1,4 @@
-use std::sync::{Arc, atomic::Ordering};
+use std::{ffi::CStr, sync::{Arc, atomic::Ordering}};
 
 use crate::{ArcRuntime, object::{self, Object}, packet::{builder::PacketBuilder, reader::Reader}};
 
@@ -8,8 +8,11 @@ pub mod builder;
 
 use enumflags2::BitFlags;
 pub use libbinder::formats::*;
+pub use libbinder::packet::{reader::BufferView, writer::BufferHandle};
+pub use libbinder::packet::encoding::{self, BinderRead, BinderWrite, PacketReader, PacketWriter};
 pub use libbinder_raw::transaction::TransactionFlag;
 use libbinder_raw::types::reference::ObjectRef;
+use nix::libc;
 
 #[derive(Clone)]
 pub struct Packet<'runtime, Mgr: Object<Mgr> + ?Sized> {
@@ -24,7 +27,12 @@ impl<'packet, 'runtime: 'packet, Mgr: Object<Mgr> + ?Sized> Packet<'runtime, Mgr
     
     for (_, kernel_ref) in packet.iter_references() {
       let obj = match kernel_ref {
-        ObjectRef::Local(local) => Some(unsafe { object::from_local_ref::<Mgr>(local) }),
+        ObjectRef::Local(local) | ObjectRef::WeakLocal(local) => {
+          // Packet keeps its own count, so object lives as long as it
+          let obj = unsafe { object::from_local_ref::<Mgr>(local) };
+          unsafe { Arc::increment_strong_count(Arc::as_ptr(&obj)) };
+          Some(obj)
+        },
         ObjectRef::Remote(remote_ref) => {
           // If remote, we increment one
           runtime.____rt.remote_reference_counters.read()
@@ -34,7 +42,10 @@ impl<'packet, 'runtime: 'packet, Mgr: Object<Mgr> + ?Sized> Packet<'runtime, Mgr
             .fetch_add(1, Ordering::Relaxed);
           
           None
-        }
+        },
+        
+        // Whoever wrote it holds the weak reference
+        ObjectRef::WeakRemote(_) => None
       };
       refs.push((kernel_ref, obj));
     }
@@ -61,7 +72,7 @@ impl<'packet, 'runtime: 'packet, Mgr: Object<Mgr> + ?Sized> Packet<'runtime, Mgr
     PacketBuilder {
       runtime: self.runtime,
       builder: self.packet.into(),
-      _kept_refs: self.refs
+      kept_refs: self.refs
     }
   }
   
@@ -76,5 +87,61 @@ impl<'packet, 'runtime: 'packet, Mgr: Object<Mgr> + ?Sized> Packet<'runtime, Mgr
   pub fn get_flags(&self) -> BitFlags<TransactionFlag> {
     self.packet.get_flags()
   }
+  
+  pub fn get_sender_pid(&self) -> libc::pid_t {
+    self.packet.get_sender_pid()
+  }
+  
+  pub fn get_sender_uid(&self) -> libc::uid_t {
+    self.packet.get_sender_uid()
+  }
+  
+  pub fn get_security_context(&self) -> Option<&CStr> {
+    self.packet.get_security_context()
+  }
 }
 
+
+#[cfg(test)]
+mod tests {
+  use std::{path::Path, sync::Arc};
+  
+  use libbinder::formats::dead_simple::DeadSimpleFormat;
+  use libbinder_raw::device::BinderDevice;
+  
+  use crate::{new_proxy_manager, object::{Object, TransactionError}, packet::Packet, proxy::SelfMananger, reference::Reference};
+  
+  struct Dummy;
+  
+  impl Object<SelfMananger> for Dummy {
+    fn do_transaction<'packet, 'runtime>(&self, _packet: &'packet Packet<'runtime, SelfMananger>) -> Result<Option<Packet<'runtime, SelfMananger>>, TransactionError> {
+      Ok(None)
+    }
+  }
+  
+  // Packet holds its own strong count for local objects written
+  // in it, before it borrowed the count of whoever wrote it
+  #[test]
+  #[ignore = "needs /dev/binder"]
+  fn packet_keeps_local_object_alive() {
+    let dev = BinderDevice::open(Path::new("/dev/binder")).unwrap();
+    let rt = new_proxy_manager(dev).unwrap();
+    
+    let obj = Arc::new(Dummy);
+    let reference = Reference::from_local(rt.clone(), obj.clone());
+    let before = Arc::strong_count(&obj);
+    
+    let mut builder = rt.new_packet();
+    builder.set_code(1);
+    builder.writer(DeadSimpleFormat::new()).write_ref(&reference);
+    let packet = builder.build().unwrap();
+    assert_eq!(Arc::strong_count(&obj), before + 1);
+    
+    // Builder made from it keeps the count too
+    let builder = packet.into_builder();
+    assert_eq!(Arc::strong_count(&obj), before + 1);
+    
+    drop(builder);
+    assert_eq!(Arc::strong_count(&obj), before);
+  }
+}
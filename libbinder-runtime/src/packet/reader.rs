@@ -1,10 +1,10 @@
-use std::{ffi::CStr, sync::{Arc, atomic::Ordering}};
+use std::{ffi::CStr, marker::PhantomData, os::fd::OwnedFd, sync::{Arc, atomic::Ordering}};
 
 use delegate::delegate;
-use libbinder::formats::{ReadFormat, SliceReadResult};
+use libbinder::{formats::{ReadError, ReadFormat, SliceReadResult}, packet::{encoding::{BinderRead, PacketReader}, reader::BufferView}};
 use libbinder_raw::types::reference::ObjectRef;
 
-use crate::{ArcRuntime, object::{self, FromProxy, Object}, proxy::Proxy, reference::{LocalObject, Reference, RemoteObject}};
+use crate::{ArcRuntime, object::{self, FromProxy, Object}, proxy::Proxy, reference::{LocalObject, Reference, RemoteObject, WeakLocalObject, WeakReference, WeakRemoteObject}};
 
 pub struct Reader<'packet, 'runtime: 'packet, Format: ReadFormat<'packet>, Mgr: Object<Mgr> + ?Sized> {
   pub(super) runtime: &'runtime ArcRuntime<Mgr>,
@@ -16,7 +16,7 @@ impl<'packet, 'runtime: 'packet, Format: ReadFormat<'packet>, Mgr: Object<Mgr> +
     self.runtime
   }
   
-  pub fn read_reference<T: FromProxy<Mgr>>(&mut self) -> Result<Reference<Mgr, T>, ()> {
+  pub fn read_reference<T: FromProxy<Mgr>>(&mut self) -> Result<Reference<Mgr, T>, ReadError> {
     let mut concrete = None;
     self.reader.read_reference(|object_ref| {
       match object_ref {
@@ -50,6 +50,46 @@ impl<'packet, 'runtime: 'packet, Format: ReadFormat<'packet>, Mgr: Object<Mgr> +
             })));
           }
         }
+        
+        // Can't be made strong from here
+        ObjectRef::WeakLocal(_) | ObjectRef::WeakRemote(_) => ()
+      };
+      
+      concrete.is_some()
+    })?;
+    
+    Ok(concrete.unwrap())
+  }
+  
+  // Accepts strong ones too, the kernel's reference given by
+  // the packet is gone with it so the runtime takes its own
+  pub fn read_weak_reference<T: FromProxy<Mgr>>(&mut self) -> Result<WeakReference<Mgr, T>, ReadError> {
+    let mut concrete = None;
+    self.reader.read_reference(|object_ref| {
+      match object_ref {
+        ObjectRef::Remote(remote_ref) | ObjectRef::WeakRemote(remote_ref) => {
+          self.runtime.acquire_weak_remote(*remote_ref);
+          concrete = Some(WeakReference::Remote(Arc::new(WeakRemoteObject {
+            runtime: self.runtime.clone(),
+            inner: *remote_ref,
+            _phantom: PhantomData
+          })));
+        }
+        
+        ObjectRef::Local(local_ref) | ObjectRef::WeakLocal(local_ref) => {
+          // Kernel only gives back local objects which are still
+          // alive, runtime keeps them alive while kernel has it
+          let obj = unsafe { object::from_local_ref::<Mgr>(local_ref.clone()) };
+          unsafe { Arc::increment_strong_count(Arc::as_ptr(&obj)); };
+          
+          if let Ok(x) = Arc::downcast::<T>(obj) {
+            concrete = Some(WeakReference::Local(Arc::new(WeakLocalObject {
+              inner: *local_ref,
+              runtime: self.runtime.clone(),
+              typed: Arc::downgrade(&x)
+            })));
+          }
+        }
       };
       
       concrete.is_some()
@@ -60,44 +100,83 @@ impl<'packet, 'runtime: 'packet, Format: ReadFormat<'packet>, Mgr: Object<Mgr> +
   
   delegate!(
     to self.reader {
-      pub fn read_u8(&mut self) -> Result<u8, ()>;
-      pub fn read_u16(&mut self) -> Result<u16, ()>;
-      pub fn read_u32(&mut self) -> Result<u32, ()>;
-      pub fn read_u64(&mut self) -> Result<u64, ()>;
-      pub fn read_usize(&mut self) -> Result<usize, ()>;
+      pub fn read_u8(&mut self) -> Result<u8, ReadError>;
+      pub fn read_u16(&mut self) -> Result<u16, ReadError>;
+      pub fn read_u32(&mut self) -> Result<u32, ReadError>;
+      pub fn read_u64(&mut self) -> Result<u64, ReadError>;
+      pub fn read_usize(&mut self) -> Result<usize, ReadError>;
+      
+      pub fn read_i8(&mut self) -> Result<i8, ReadError>;
+      pub fn read_i16(&mut self) -> Result<i16, ReadError>;
+      pub fn read_i32(&mut self) -> Result<i32, ReadError>;
+      pub fn read_i64(&mut self) -> Result<i64, ReadError>;
+      pub fn read_isize(&mut self) -> Result<isize, ReadError>;
+      
+      pub fn read_f32(&mut self) -> Result<f32, ReadError>;
+      pub fn read_f64(&mut self) -> Result<f64, ReadError>;
+      pub fn read_str(&mut self) -> Result<&'packet str, ReadError>;
+      pub fn read_cstr(&mut self) -> Result<&'packet CStr, ReadError>;
+      pub fn read_bool(&mut self) -> Result<bool, ReadError>;
+      
+      pub fn read_u8_slice(&mut self) -> Result<&'packet [u8], ReadError>;
+      pub fn read_u16_slice(&mut self) -> Result<SliceReadResult<'packet, u16>, ReadError>;
+      pub fn read_u32_slice(&mut self) -> Result<SliceReadResult<'packet, u32>, ReadError>;
+      pub fn read_u64_slice(&mut self) -> Result<SliceReadResult<'packet, u64>, ReadError>;
+      pub fn read_usize_slice(&mut self) -> Result<SliceReadResult<'packet, usize>, ReadError>;
+      
+      pub fn read_i8_slice(&mut self) -> Result<&'packet [i8], ReadError>;
+      pub fn read_i16_slice(&mut self) -> Result<SliceReadResult<'packet, i16>, ReadError>;
+      pub fn read_i32_slice(&mut self) -> Result<SliceReadResult<'packet, i32>, ReadError>;
+      pub fn read_i64_slice(&mut self) -> Result<SliceReadResult<'packet, i64>, ReadError>;
+      pub fn read_isize_slice(&mut self) -> Result<SliceReadResult<'packet, isize>, ReadError>;
+      
+      pub fn read_f32_slice(&mut self) -> Result<SliceReadResult<'packet, f32>, ReadError>;
+      pub fn read_f64_slice(&mut self) -> Result<SliceReadResult<'packet, f64>, ReadError>;
+      pub fn read_str_slice(&mut self) -> Result<Vec<&'packet str>, ReadError>;
+      pub fn read_cstr_slice(&mut self) -> Result<Vec<&'packet CStr>, ReadError>;
+      pub fn read_bool_slice(&mut self) -> Result<SliceReadResult<'packet, bool>, ReadError>;
       
-      pub fn read_i8(&mut self) -> Result<i8, ()>;
-      pub fn read_i16(&mut self) -> Result<i16, ()>;
-      pub fn read_i32(&mut self) -> Result<i32, ()>;
-      pub fn read_i64(&mut self) -> Result<i64, ()>;
-      pub fn read_isize(&mut self) -> Result<isize, ()>;
+      pub fn read_fd(&mut self) -> Result<OwnedFd, ReadError>;
+      pub fn read_buffer(&mut self) -> Result<BufferView<'packet>, ReadError>;
+      pub fn read_fd_array(&mut self) -> Result<Vec<OwnedFd>, ReadError>;
+      pub fn read_with<T, F: FnOnce(&mut Format) -> Result<T, ReadError>>(&mut self, func: F) -> Result<T, ReadError>;
+    }
+  );
+}
+
+impl<'packet, 'runtime: 'packet, Format: ReadFormat<'packet>, Mgr: Object<Mgr> + ?Sized> PacketReader<'packet> for Reader<'packet, 'runtime, Format, Mgr> {
+  delegate!(
+    to self.reader {
+      fn get_current_offset(&self) -> usize;
       
-      pub fn read_f32(&mut self) -> Result<f32, ()>;
-      pub fn read_f64(&mut self) -> Result<f64, ()>;
-      pub fn read_str(&mut self) -> Result<&'packet str, ()>;
-      pub fn read_cstr(&mut self) -> Result<&'packet CStr, ()>;
-      pub fn read_bool(&mut self) -> Result<bool, ()>;
+      fn read_u8(&mut self) -> Result<u8, ReadError>;
+      fn read_u16(&mut self) -> Result<u16, ReadError>;
+      fn read_u32(&mut self) -> Result<u32, ReadError>;
+      fn read_u64(&mut self) -> Result<u64, ReadError>;
+      fn read_usize(&mut self) -> Result<usize, ReadError>;
       
-      pub fn read_u8_slice(&mut self) -> Result<&'packet [u8], ()>;
-      pub fn read_u16_slice(&mut self) -> Result<SliceReadResult<'packet, u16>, ()>;
-      pub fn read_u32_slice(&mut self) -> Result<SliceReadResult<'packet, u32>, ()>;
-      pub fn read_u64_slice(&mut self) -> Result<SliceReadResult<'packet, u64>, ()>;
-      pub fn read_usize_slice(&mut self) -> Result<SliceReadResult<'packet, usize>, ()>;
+      fn read_i8(&mut self) -> Result<i8, ReadError>;
+      fn read_i16(&mut self) -> Result<i16, ReadError>;
+      fn read_i32(&mut self) -> Result<i32, ReadError>;
+      fn read_i64(&mut self) -> Result<i64, ReadError>;
+      fn read_isize(&mut self) -> Result<isize, ReadError>;
       
-      pub fn read_i8_slice(&mut self) -> Result<&'packet [i8], ()>;
-      pub fn read_i16_slice(&mut self) -> Result<SliceReadResult<'packet, i16>, ()>;
-      pub fn read_i32_slice(&mut self) -> Result<SliceReadResult<'packet, i32>, ()>;
-      pub fn read_i64_slice(&mut self) -> Result<SliceReadResult<'packet, i64>, ()>;
-      pub fn read_isize_slice(&mut self) -> Result<SliceReadResult<'packet, isize>, ()>;
+      fn read_f32(&mut self) -> Result<f32, ReadError>;
+      fn read_f64(&mut self) -> Result<f64, ReadError>;
+      fn read_bool(&mut self) -> Result<bool, ReadError>;
       
-      pub fn read_f32_slice(&mut self) -> Result<SliceReadResult<'packet, f32>, ()>;
-      pub fn read_f64_slice(&mut self) -> Result<SliceReadResult<'packet, f64>, ()>;
-      pub fn read_str_slice(&mut self) -> Result<Vec<&'packet str>, ()>;
-      pub fn read_cstr_slice(&mut self) -> Result<Vec<&'packet CStr>, ()>;
-      pub fn read_bool_slice(&mut self) -> Result<&'packet [bool], ()>;
+      fn read_str(&mut self) -> Result<&'packet str, ReadError>;
+      fn read_cstr(&mut self) -> Result<&'packet CStr, ReadError>;
+      fn read_u8_slice(&mut self) -> Result<&'packet [u8], ReadError>;
     }
   );
 }
 
+impl<'packet, 'runtime: 'packet, Format: ReadFormat<'packet>, Mgr: Object<Mgr> + ?Sized, T: FromProxy<Mgr>> BinderRead<'packet, Reader<'packet, 'runtime, Format, Mgr>> for Reference<Mgr, T> {
+  fn read_from(reader: &mut Reader<'packet, 'runtime, Format, Mgr>) -> Result<Self, ReadError> {
+    reader.read_reference()
+  }
+}
+
 
 
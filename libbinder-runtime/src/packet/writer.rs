@@ -1,13 +1,28 @@
-use std::ffi::CStr;
+use std::{error::Error, ffi::CStr, fmt::Display, io, os::fd::BorrowedFd, sync::Arc};
 
 use delegate::delegate;
-use libbinder::formats::WriteFormat;
+use libbinder::{formats::{WriteError, WriteFormat}, packet::{encoding::{BinderWrite, PacketWriter}, writer::BufferHandle}};
+use libbinder_raw::types::reference::ObjectRef;
 
-use crate::{ArcRuntime, object::Object, reference::Reference};
+use crate::{ArcRuntime, object::{self, Object}, reference::{Reference, WeakReference}};
+
+// Weak local reference can't be written once all strong
+// references to the object are dropped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ObjectGone;
+
+impl Display for ObjectGone {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "local object is already gone")
+  }
+}
+
+impl Error for ObjectGone {}
 
 pub struct Writer<'packet, 'runtime: 'packet, Format: WriteFormat<'packet>, Mgr: Object<Mgr> + ?Sized> {
   pub(super) runtime: &'runtime ArcRuntime<Mgr>,
-  pub(super) writer: libbinder::packet::writer::Writer<'packet, 'runtime, Format>
+  pub(super) writer: libbinder::packet::writer::Writer<'packet, 'runtime, Format>,
+  pub(super) kept_refs: &'packet mut Vec<(ObjectRef, Option<Arc<dyn Object<Mgr>>>)>
 }
 
 impl<'packet, 'runtime: 'packet, Format: WriteFormat<'packet>, Mgr: Object<Mgr> + ?Sized> Writer<'packet, 'runtime, Format, Mgr> {
@@ -17,10 +32,41 @@ impl<'packet, 'runtime: 'packet, Format: WriteFormat<'packet>, Mgr: Object<Mgr>
   
   pub fn write_ref<T: Object<Mgr> + ?Sized>(&mut self, reference: &'packet Reference<Mgr, T>) -> &mut Self {
     assert!(self.runtime.ptr_eq(Reference::get_runtime(reference)), "attempt to write reference belonging to different runtime");
-    self.writer.write_obj_ref(Reference::get_obj_ref(reference));
+    self.writer.write_obj_ref_with_flags(Reference::get_obj_ref(reference), object::local_ref_flags(reference.get().as_ref()));
     self
   }
   
+  // Fails if it is local object which is already gone
+  pub fn write_weak_ref<T: Object<Mgr> + ?Sized>(&mut self, reference: &'packet WeakReference<Mgr, T>) -> Result<&mut Self, ObjectGone> {
+    assert!(self.runtime.ptr_eq(WeakReference::get_runtime(reference)), "attempt to write reference belonging to different runtime");
+    match reference {
+      WeakReference::Local(x) => {
+        let typed = x.typed.upgrade().ok_or(ObjectGone)?;
+        let flags = object::local_ref_flags(typed.as_ref());
+        
+        // Packet keeps it alive until sent, runtime
+        // takes over once kernel knows about it
+        unsafe { Arc::increment_strong_count(Arc::as_ptr(&typed)) };
+        self.kept_refs.push((ObjectRef::WeakLocal(x.inner), Some(unsafe { object::from_local_ref::<Mgr>(x.inner) })));
+        
+        self.writer.write_obj_ref_with_flags(ObjectRef::WeakLocal(x.inner), flags);
+      },
+      WeakReference::Remote(x) => {
+        self.writer.write_obj_ref(ObjectRef::WeakRemote(x.inner));
+      }
+    }
+    Ok(self)
+  }
+  
+  delegate!(
+    to self.writer {
+      pub fn write_fd(&mut self, fd: BorrowedFd) -> io::Result<()>;
+      pub fn write_buffer(&mut self, data: &[u8], parent: Option<(BufferHandle, usize)>) -> io::Result<BufferHandle>;
+      pub fn write_fd_array(&mut self, fds: &[BorrowedFd], parent: BufferHandle, parent_offset: usize) -> io::Result<()>;
+      pub fn get_format_mut(&mut self) -> &mut Format;
+    }
+  );
+  
   delegate!(
     #[expr($; self)]
     to self.writer {
@@ -77,9 +123,48 @@ impl<'packet, 'runtime: 'packet, Format: WriteFormat<'packet>, Mgr: Object<Mgr>
       pub fn write_str_array<const LEN: usize>(&mut self, value: &[&str; LEN]) -> &mut Self;
       pub fn write_cstr_array<const LEN: usize>(&mut self, value: &[&CStr; LEN]) -> &mut Self;
       pub fn write_bool_array<const LEN: usize>(&mut self, value: &[bool; LEN]) -> &mut Self;
+      
+      pub fn set_error(&mut self, error: WriteError) -> &mut Self;
     }
   );
 }
 
+impl<'packet, 'runtime: 'packet, Format: WriteFormat<'packet>, Mgr: Object<Mgr> + ?Sized> PacketWriter for Writer<'packet, 'runtime, Format, Mgr> {
+  delegate!(
+    to self.writer {
+      fn get_current_offset(&mut self) -> usize;
+      #[expr($;)]
+      fn set_error(&mut self, error: WriteError);
+      
+      fn write_u8(&mut self, data: u8);
+      fn write_u16(&mut self, data: u16);
+      fn write_u32(&mut self, data: u32);
+      fn write_u64(&mut self, data: u64);
+      fn write_usize(&mut self, data: usize);
+      
+      fn write_i8(&mut self, data: i8);
+      fn write_i16(&mut self, data: i16);
+      fn write_i32(&mut self, data: i32);
+      fn write_i64(&mut self, data: i64);
+      fn write_isize(&mut self, data: isize);
+      
+      fn write_f32(&mut self, data: f32);
+      fn write_f64(&mut self, data: f64);
+      fn write_bool(&mut self, data: bool);
+      
+      fn write_str(&mut self, data: &str);
+      fn write_cstr(&mut self, data: &CStr);
+      fn write_u8_slice(&mut self, data: &[u8]);
+    }
+  );
+}
+
+// Only runtime's writer can write references
+impl<'packet, 'runtime: 'packet, Format: WriteFormat<'packet>, Mgr: Object<Mgr> + ?Sized, T: Object<Mgr> + ?Sized> BinderWrite<'packet, Writer<'packet, 'runtime, Format, Mgr>> for Reference<Mgr, T> {
+  fn write_to(&'packet self, writer: &mut Writer<'packet, 'runtime, Format, Mgr>) {
+    writer.write_ref(self);
+  }
+}
+
 
 
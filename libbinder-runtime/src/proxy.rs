@@ -1,9 +1,10 @@
 use std::{borrow::Cow, mem::ManuallyDrop, sync::{Arc, atomic::{AtomicU64, Ordering}}};
 
 use libbinder::{command_buffer::{Command, CommandBuffer}, return_buffer::ReturnValue};
-use libbinder_raw::{transaction::TransactionFlag, types::reference::{CONTEXT_MANAGER_REF, ObjectRef, ObjectRefRemote}};
+use libbinder_raw::{extended_error, node_info::{self, NodeRefCounts}, transaction::TransactionFlag, types::reference::{CONTEXT_MANAGER_REF, ObjectRef, ObjectRefFlags, ObjectRefRemote}};
+use nix::errno::Errno;
 
-use crate::{ArcRuntime, WeakRuntime, context::Context, object::{self, FromProxy, Object, TransactionError}, packet::Packet};
+use crate::{ArcRuntime, WeakRuntime, context::Context, death::DeathRecipient, freeze::FreezeListener, object::{self, FromProxy, FromProxyError, Object, TransactionError}, packet::Packet};
 
 pub struct Proxy<Mgr: Object<Mgr> + ?Sized> {
   runtime: WeakRuntime<Mgr>,
@@ -24,7 +25,14 @@ impl<Mgr: Object<Mgr> + ?Sized> Drop for Proxy<Mgr> {
         return;
       }
       
-      let mut cmd_buf = CommandBuffer::new(rt.get_binder());
+      let mut cmd_buf = CommandBuffer::new(rt.get_binder_device());
+      
+      // Weak references keep the handle and kernel keeps delivering
+      // notifications to it, the last one clears them instead
+      if !rt.____rt.weak_remote_reference_counters.lock().unwrap().contains_key(&self.remote_ref) {
+        rt.enqueue_clear_notifications(&mut cmd_buf, self.remote_ref);
+      }
+      
       cmd_buf.enqueue_command(Command::Release(self.remote_ref.clone()));
       cmd_buf.exec_always_block(None).unwrap();
       
@@ -46,6 +54,120 @@ impl<Mgr: Object<Mgr> + ?Sized> Proxy<Mgr> {
   pub fn get_runtime(&self) -> ArcRuntime<Mgr> {
     self.runtime.upgrade().unwrap()
   }
+  
+  // Strong and weak count of the remote object as kernel
+  // sees it. Kernel only allows context manager to do this
+  pub fn kernel_ref_counts(&self) -> Result<NodeRefCounts, Errno> {
+    let rt = self.get_runtime();
+    node_info::binder_node_info_for_ref(rt.get_binder(), self.remote_ref.data_handle)
+  }
+  
+  // The recipient is called once when the process owning the
+  // remote object dies. If it already dead, it is called
+  // shortly after
+  pub fn link_to_death(&self, recipient: Arc<dyn DeathRecipient>) {
+    let rt = self.get_runtime();
+    let mut death_recipients = rt.____rt.death_recipients.lock().unwrap();
+    let recipients = death_recipients.entry(self.remote_ref.data_handle).or_insert(Vec::new());
+    
+    if recipients.is_empty() {
+      // First recipient for this handle, ask kernel to notify
+      let ctx = rt.____rt.exec_context.get_or(|| Context::new(rt.get_binder_device()));
+      ctx.exec_without_ret(&rt, |cmd_buf| {
+        cmd_buf.enqueue_command(Command::RequestDeathNotification(self.remote_ref, self.remote_ref.data_handle as usize));
+      });
+    }
+    
+    recipients.push(recipient);
+  }
+  
+  // Returns false if the recipient was not linked or
+  // the remote object already died
+  pub fn unlink_to_death(&self, recipient: &Arc<dyn DeathRecipient>) -> bool {
+    let rt = self.get_runtime();
+    let mut death_recipients = rt.____rt.death_recipients.lock().unwrap();
+    let Some(recipients) = death_recipients.get_mut(&self.remote_ref.data_handle) else {
+      return false;
+    };
+    
+    let Some(idx) = recipients.iter().position(|x| Arc::ptr_eq(x, recipient)) else {
+      return false;
+    };
+    recipients.remove(idx);
+    
+    if recipients.is_empty() {
+      // No one else interested, let kernel know
+      death_recipients.remove(&self.remote_ref.data_handle);
+      
+      let ctx = rt.____rt.exec_context.get_or(|| Context::new(rt.get_binder_device()));
+      ctx.exec_without_ret(&rt, |cmd_buf| {
+        cmd_buf.enqueue_command(Command::ClearDeathNotification(self.remote_ref, self.remote_ref.data_handle as usize));
+      });
+    }
+    
+    true
+  }
+  
+  // The listener is called each time the process owning the
+  // remote object is frozen or thawed. It is also called with
+  // current state shortly after being added
+  pub fn add_freeze_listener(&self, listener: Arc<dyn FreezeListener>) -> Result<(), Errno> {
+    let rt = self.get_runtime();
+    // Unlike ioctls, unknown command fails the whole write
+    // so don't try unless known to be there
+    if !rt.get_binder_device().get_capabilities().freeze_notification.is_supported() {
+      return Err(Errno::EOPNOTSUPP);
+    }
+    
+    let mut freeze_listeners = rt.____rt.freeze_listeners.lock().unwrap();
+    let (state, listeners) = freeze_listeners.entry(self.remote_ref.data_handle).or_insert((None, Vec::new()));
+    
+    if listeners.is_empty() {
+      // First listener for this handle, ask kernel to notify
+      // kernel replies with current state right away
+      let ctx = rt.____rt.exec_context.get_or(|| Context::new(rt.get_binder_device()));
+      ctx.exec_without_ret(&rt, |cmd_buf| {
+        cmd_buf.enqueue_command(Command::RequestFreezeNotification(self.remote_ref, self.remote_ref.data_handle as usize));
+      });
+    }
+    
+    listeners.push(listener.clone());
+    let state = *state;
+    drop(freeze_listeners);
+    
+    // Others already subscribed, kernel won't resend
+    // current state so tell it here
+    if let Some(is_frozen) = state {
+      listener.frozen_state_changed(is_frozen);
+    }
+    Ok(())
+  }
+  
+  // Returns false if the listener was not added
+  pub fn remove_freeze_listener(&self, listener: &Arc<dyn FreezeListener>) -> bool {
+    let rt = self.get_runtime();
+    let mut freeze_listeners = rt.____rt.freeze_listeners.lock().unwrap();
+    let Some((_, listeners)) = freeze_listeners.get_mut(&self.remote_ref.data_handle) else {
+      return false;
+    };
+    
+    let Some(idx) = listeners.iter().position(|x| Arc::ptr_eq(x, listener)) else {
+      return false;
+    };
+    listeners.remove(idx);
+    
+    if listeners.is_empty() {
+      // No one else interested, let kernel know
+      freeze_listeners.remove(&self.remote_ref.data_handle);
+      
+      let ctx = rt.____rt.exec_context.get_or(|| Context::new(rt.get_binder_device()));
+      ctx.exec_without_ret(&rt, |cmd_buf| {
+        cmd_buf.enqueue_command(Command::ClearFreezeNotification(self.remote_ref, self.remote_ref.data_handle as usize));
+      });
+    }
+    
+    true
+  }
 }
 
 impl<Mgr: Object<Mgr> + ?Sized> Object<Mgr> for Proxy<Mgr> {
@@ -56,23 +178,50 @@ impl<Mgr: Object<Mgr> + ?Sized> Object<Mgr> for Proxy<Mgr> {
     );
     
     let rt = packet.get_runtime();
-    let ctx = rt.____rt.exec_context.get_or(|| Context::new(rt.get_binder()));
+    let capabilities = rt.get_binder_device().get_capabilities();
+    if packet.packet.buffers_size() != 0 && capabilities.scatter_gather.is_unsupported() {
+      return Err(TransactionError::NotSupported);
+    }
+    
+    // Objects can ask for security context when published by
+    // any transaction, not just as the context manager
+    if capabilities.security_context.is_unsupported() {
+      for (_, reference) in packet.iter_references() {
+        if let ObjectRef::Local(local) | ObjectRef::WeakLocal(local) = reference {
+          let obj = ManuallyDrop::new(unsafe { object::from_local_ref::<Mgr>(local) });
+          if object::local_ref_flags::<Mgr, _>(obj.as_ref()).contains(ObjectRefFlags::SendSecurityContext) {
+            return Err(TransactionError::NotSupported);
+          }
+        }
+      }
+    }
+    
+    let ctx = rt.____rt.exec_context.get_or(|| Context::new(rt.get_binder_device()));
     let mut ret = None;
     
     let mut has_transaction_complete = false;
     let mut has_failed = false;
+    let mut has_dead_reply = false;
+    let mut is_spam_suspect = false;
+    
+    // Local references which got entry because of this packet
+    let mut new_locals = Vec::new();
     
     for (_, reference) in packet.iter_references() {
       match reference {
-        ObjectRef::Local(local) => {
+        ObjectRef::Local(local) | ObjectRef::WeakLocal(local) => {
           let obj = ManuallyDrop::new(unsafe { object::from_local_ref::<Mgr>(local) });
-          unsafe { Arc::increment_strong_count(Arc::as_ptr(&obj)) };
           
+          // Keep the object alive until kernel releases it, kernel
+          // sends BR_INCREFS/BR_ACQUIRE later if it is new to kernel
           rt.____rt.reference_states.lock()
             .unwrap()
             .entry(local)
-            .or_insert((true, false))
-            .0 = true;
+            .or_insert_with(|| {
+              unsafe { Arc::increment_strong_count(Arc::as_ptr(&obj)) };
+              new_locals.push(local);
+              (false, false)
+            });
         },
         ObjectRef::Remote(remote) => {
           // Get counter for the remote reference
@@ -88,7 +237,10 @@ impl<Mgr: Object<Mgr> + ?Sized> Object<Mgr> for Proxy<Mgr> {
               .or_insert(AtomicU64::new(0))
               .fetch_add(1, Ordering::Relaxed);
           }
-        }
+        },
+        
+        // The weak reference which was written keeps it
+        ObjectRef::WeakRemote(_) => ()
       }
     }
     
@@ -119,14 +271,72 @@ impl<Mgr: Object<Mgr> + ?Sized> Object<Mgr> for Proxy<Mgr> {
         },
         ReturnValue::DeadReply => {
           assert!(ret.is_none());
-          ret = Some(Err(TransactionError::UnreachableTarget));
-        }
+          has_dead_reply = true;
+        },
+        ReturnValue::FrozenReply => {
+          assert!(ret.is_none());
+          ret = Some(Err(TransactionError::FrozenTarget));
+        },
+        ReturnValue::TransactionPendingFrozen => {
+          // One way transaction is queued until target thawed
+          has_transaction_complete = true;
+        },
+        ReturnValue::DeadBinder(_) => (),
+        ReturnValue::ClearDeathNotificationDone(_) => (),
+        ReturnValue::FrozenBinder(_, _) => (),
+        ReturnValue::ClearFreezeNotificationDone(_) => (),
+        ReturnValue::OnewaySpamSuspect => {
+          // Sent instead of TransactionComplete
+          has_transaction_complete = true;
+          is_spam_suspect = true;
+        },
+        ReturnValue::AcquireResult(_) => (),
+        ReturnValue::AttemptAcquire(_, _) => (),
+        ReturnValue::Finished => (),
+        ReturnValue::Unknown(_) => ()
       }
     });
     
+    // Kernel only gives errno through separate ioctl
+    // it is per thread so fetch it now before anything else
+    let errno = if (has_failed || has_dead_reply) && !capabilities.extended_error.is_unsupported() {
+      extended_error::binder_get_extended_error(rt.get_binder())
+        .ok()
+        .and_then(|x| x.errno)
+    } else {
+      None
+    };
+    
+    if has_dead_reply {
+      if has_transaction_complete {
+        // Target got the transaction but died before replying
+        ret = Some(Err(TransactionError::NoReply));
+      } else {
+        ret = Some(Err(TransactionError::UnreachableTarget(errno)));
+      }
+    }
+    
+    if !has_transaction_complete {
+      // Transaction never left, kernel already dropped whatever it
+      // took so it won't send BR_INCREFS/BR_ACQUIRE for these
+      let mut ref_states = rt.____rt.reference_states.lock().unwrap();
+      let unused: Vec<_> = new_locals.into_iter()
+        .filter(|local| ref_states.get(local) == Some(&(false, false)))
+        .collect();
+      for local in &unused {
+        ref_states.remove(local);
+      }
+      drop(ref_states);
+      
+      // Object may be dropped here, so do it outside the lock
+      for local in unused {
+        drop(unsafe { object::from_local_ref::<Mgr>(local) });
+      }
+    }
+    
     let is_oneway = packet.get_flags().contains(TransactionFlag::OneWay);
     if let Some(x) = ret {
-      if is_oneway {
+      if is_oneway && x.is_ok() {
         panic!("kernel responded with reply for one way transaction!");
       }
       x.map(|x| Some(x))
@@ -135,19 +345,28 @@ impl<Mgr: Object<Mgr> + ?Sized> Object<Mgr> for Proxy<Mgr> {
         (true, false) => {
           if !is_oneway {
             panic!("kernel didnt reply back when expected");
-          } else {
-            Ok(None)
           }
+          
+          // Transaction is still delivered, so it isn't an error
+          if is_spam_suspect {
+            let handler = rt.____rt.oneway_spam_handler.read().unwrap().clone();
+            if let Some(handler) = handler {
+              handler(self);
+            }
+          }
+          Ok(None)
         },
-        (true, true) => Err(TransactionError::FailedReply),
-        (false, _) => panic!("kernel did not response")
+        // Kernel does not send TransactionComplete
+        // when it fails before sending
+        (_, true) => Err(TransactionError::FailedReply(errno)),
+        (false, false) => panic!("kernel did not response")
       }
     }
   }
 }
 
 impl<Mgr: Object<Mgr> + ?Sized> FromProxy<Mgr> for Proxy<Mgr> {
-  fn from_proxy(proxy: Proxy<Mgr>) -> Result<Self, ()> {
+  fn from_proxy(proxy: Proxy<Mgr>) -> Result<Self, FromProxyError> {
     Ok(proxy)
   }
 }
@@ -155,7 +374,7 @@ impl<Mgr: Object<Mgr> + ?Sized> FromProxy<Mgr> for Proxy<Mgr> {
 pub struct SelfMananger(pub Proxy<SelfMananger>);
 
 impl FromProxy<SelfMananger> for SelfMananger {
-  fn from_proxy(proxy: Proxy<SelfMananger>) -> Result<Self, ()> {
+  fn from_proxy(proxy: Proxy<SelfMananger>) -> Result<Self, FromProxyError> {
     Ok(SelfMananger(proxy))
   }
 }
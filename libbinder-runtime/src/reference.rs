@@ -1,8 +1,8 @@
-use std::sync::Arc;
+use std::{marker::PhantomData, sync::{Arc, Weak, atomic::Ordering}};
 
-use libbinder_raw::types::reference::{ObjectRef, ObjectRefLocal, ObjectRefRemote};
+use libbinder_raw::types::reference::{CONTEXT_MANAGER_REF, ObjectRef, ObjectRefLocal, ObjectRefRemote};
 
-use crate::{ArcRuntime, object::{self, Object}};
+use crate::{ArcRuntime, object::{self, FromProxy, Object}, proxy::Proxy};
 
 pub struct LocalObject<Mgr: Object<Mgr> + ?Sized, T: Object<Mgr> + ?Sized> {
   pub(crate) runtime: ArcRuntime<Mgr>,
@@ -21,6 +21,88 @@ pub enum Reference<Mgr: Object<Mgr> + ?Sized, T: Object<Mgr> + ?Sized> {
   Remote(Arc<RemoteObject<Mgr, T>>)
 }
 
+pub struct WeakLocalObject<Mgr: Object<Mgr> + ?Sized, T: Object<Mgr> + ?Sized> {
+  pub(crate) runtime: ArcRuntime<Mgr>,
+  pub(crate) inner: ObjectRefLocal,
+  pub(crate) typed: Weak<T>
+}
+
+// Holds one weak reference counted in runtime, which
+// keeps kernel's node for the handle around
+pub struct WeakRemoteObject<Mgr: Object<Mgr> + ?Sized, T: Object<Mgr> + ?Sized> {
+  pub(crate) runtime: ArcRuntime<Mgr>,
+  pub(crate) inner: ObjectRefRemote,
+  pub(crate) _phantom: PhantomData<fn() -> Arc<T>>
+}
+
+impl<Mgr: Object<Mgr> + ?Sized, T: Object<Mgr> + ?Sized> Drop for WeakRemoteObject<Mgr, T> {
+  fn drop(&mut self) {
+    self.runtime.release_weak_remote(self.inner);
+  }
+}
+
+pub enum WeakReference<Mgr: Object<Mgr> + ?Sized, T: Object<Mgr> + ?Sized> {
+  Local(Arc<WeakLocalObject<Mgr, T>>),
+  Remote(Arc<WeakRemoteObject<Mgr, T>>)
+}
+
+impl<Mgr: Object<Mgr> + ?Sized, T: Object<Mgr> + ?Sized> Clone for WeakReference<Mgr, T> {
+  fn clone(&self) -> Self {
+    match self {
+      WeakReference::Local(x) => Self::Local(x.clone()),
+      WeakReference::Remote(x) => Self::Remote(x.clone())
+    }
+  }
+}
+
+impl<Mgr: Object<Mgr> + ?Sized, T: FromProxy<Mgr>> WeakReference<Mgr, T> {
+  // Kernel has no way to attempt a strong reference (it doesn't
+  // implement BC_ATTEMPT_ACQUIRE) and BC_ACQUIRE on a node with
+  // only weak references left fails without telling. So remote
+  // one only promotes while this process still has strong
+  // reference to it, even if other processes still have some
+  pub fn promote(&self) -> Option<Reference<Mgr, T>> {
+    match self {
+      WeakReference::Local(x) => {
+        let typed = x.typed.upgrade()?;
+        Some(Reference::Local(Arc::new(LocalObject {
+          runtime: x.runtime.clone(),
+          inner: x.inner,
+          typed
+        })))
+      },
+      WeakReference::Remote(x) => {
+        if x.inner != CONTEXT_MANAGER_REF {
+          let counters = x.runtime.____rt.remote_reference_counters.read().unwrap();
+          
+          // Count can be zero while the proxy dropping it
+          // haven't removed the entry yet
+          counters.get(&x.inner)?
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| (count > 0).then(|| count + 1))
+            .ok()?;
+        }
+        
+        // Dropping the proxy takes the count back
+        let typed = T::from_proxy(Proxy::new(x.runtime.downgrade(), x.inner)).ok()?;
+        Some(Reference::Remote(Arc::new(RemoteObject {
+          runtime: x.runtime.clone(),
+          inner: x.inner,
+          typed: Arc::new(typed)
+        })))
+      }
+    }
+  }
+}
+
+impl<Mgr: Object<Mgr> + ?Sized, T: Object<Mgr> + ?Sized> WeakReference<Mgr, T> {
+  pub(crate) fn get_runtime(this: &Self) -> &ArcRuntime<Mgr> {
+    match this {
+      WeakReference::Local(x) => &x.runtime,
+      WeakReference::Remote(x) => &x.runtime
+    }
+  }
+}
+
 impl<Mgr: Object<Mgr> + ?Sized, T: Object<Mgr> + ?Sized> Clone for Reference<Mgr, T> {
   fn clone(&self) -> Self {
     match self {
@@ -48,6 +130,24 @@ impl<Mgr: Object<Mgr> + ?Sized, T: Object<Mgr> + ?Sized> Reference<Mgr, T> {
     }
   }
   
+  pub fn downgrade(this: &Self) -> WeakReference<Mgr, T> {
+    match this {
+      Reference::Local(x) => WeakReference::Local(Arc::new(WeakLocalObject {
+        runtime: x.runtime.clone(),
+        inner: x.inner,
+        typed: Arc::downgrade(&x.typed)
+      })),
+      Reference::Remote(x) => {
+        x.runtime.acquire_weak_remote(x.inner);
+        WeakReference::Remote(Arc::new(WeakRemoteObject {
+          runtime: x.runtime.clone(),
+          inner: x.inner,
+          _phantom: PhantomData
+        }))
+      }
+    }
+  }
+  
   pub(crate) fn get_runtime(this: &Self) -> &ArcRuntime<Mgr> {
     match this {
       Reference::Local(x) => &x.runtime,
@@ -0,0 +1,98 @@
+use std::{collections::HashMap, sync::Arc, time::{Duration, Instant}};
+
+use nix::libc;
+
+// Kernel only reports suspected spam to the sender, and only
+// when sender enabled it, the receiving side never hears about
+// it. So runtime counts incoming one way transactions per sender
+// uid (kernel gives no pid for them) over one second windows
+pub(crate) struct IncomingOnewaySpam {
+  max_per_second: u32,
+  handler: Arc<dyn Fn(libc::uid_t) + Sync + Send>,
+  
+  // Start of current window and count in it
+  counts: HashMap<libc::uid_t, (Instant, u32)>
+}
+
+impl IncomingOnewaySpam {
+  const WINDOW: Duration = Duration::from_secs(1);
+  
+  pub(crate) fn new(max_per_second: u32, handler: Arc<dyn Fn(libc::uid_t) + Sync + Send>) -> Self {
+    Self {
+      max_per_second,
+      handler,
+      counts: HashMap::new()
+    }
+  }
+  
+  // Returns the handler if the uid just went over the limit, it
+  // is returned once per window so offender isn't reported for
+  // every transaction
+  pub(crate) fn record(&mut self, uid: libc::uid_t, now: Instant) -> Option<Arc<dyn Fn(libc::uid_t) + Sync + Send>> {
+    // Forget senders which went quiet
+    if self.counts.len() > 64 {
+      self.counts.retain(|_, (start, _)| now.duration_since(*start) < Self::WINDOW);
+    }
+    
+    let (start, count) = self.counts.entry(uid).or_insert((now, 0));
+    if now.duration_since(*start) >= Self::WINDOW {
+      *start = now;
+      *count = 0;
+    }
+    
+    *count += 1;
+    if *count == self.max_per_second.saturating_add(1) {
+      Some(self.handler.clone())
+    } else {
+      None
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{sync::Arc, time::{Duration, Instant}};
+  
+  use super::IncomingOnewaySpam;
+  
+  fn spam() -> IncomingOnewaySpam {
+    IncomingOnewaySpam::new(2, Arc::new(|_| ()))
+  }
+  
+  #[test]
+  fn fires_once_per_window() {
+    let mut spam = spam();
+    let now = Instant::now();
+    assert!(spam.record(1000, now).is_none());
+    assert!(spam.record(1000, now).is_none());
+    assert!(spam.record(1000, now).is_some());
+    assert!(spam.record(1000, now).is_none());
+    assert!(spam.record(1000, now + Duration::from_millis(999)).is_none());
+  }
+  
+  #[test]
+  fn window_resets() {
+    let mut spam = spam();
+    let now = Instant::now();
+    for _ in 0..3 {
+      spam.record(1000, now);
+    }
+    
+    let later = now + Duration::from_secs(1);
+    assert!(spam.record(1000, later).is_none());
+    assert!(spam.record(1000, later).is_none());
+    assert!(spam.record(1000, later).is_some());
+  }
+  
+  #[test]
+  fn uids_counted_separately() {
+    let mut spam = spam();
+    let now = Instant::now();
+    spam.record(1000, now);
+    spam.record(1000, now);
+    assert!(spam.record(1001, now).is_none());
+    assert!(spam.record(1001, now).is_none());
+    assert!(spam.record(1000, now).is_some());
+    assert!(spam.record(1001, now).is_some());
+  }
+}
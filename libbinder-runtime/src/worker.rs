@@ -1,15 +1,22 @@
 use std::{os::fd::{AsFd, OwnedFd}, sync::Arc};
 
 use libbinder::{command_buffer::{Command, CommandBuffer}, return_buffer::ReturnValue};
+use libbinder_raw::device::BinderDevice;
 use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
 
 use crate::{WeakRuntime, context::Context, object::Object};
 
-pub fn worker<Mgr: Object<Mgr> + ?Sized>(binder_dev: Arc<OwnedFd>, weak_rt: WeakRuntime<Mgr>, shutdown_pipe_ro: Arc<OwnedFd>) {
-  let ctx = Context::new(binder_dev.as_fd());
+// is_main is true for the looper started by runtime, the
+// rest are spawned because kernel asked for it
+pub fn worker<Mgr: Object<Mgr> + ?Sized>(binder_dev: Arc<BinderDevice>, weak_rt: WeakRuntime<Mgr>, shutdown_pipe_ro: Arc<OwnedFd>, is_main: bool) {
+  let ctx = Context::new(&binder_dev);
   
-  let mut cmd_buf = CommandBuffer::new(binder_dev.as_fd());
-  cmd_buf.enqueue_command(Command::EnterLooper);
+  let mut cmd_buf = CommandBuffer::new(&binder_dev);
+  if is_main {
+    cmd_buf.enqueue_command(Command::EnterLooper);
+  } else {
+    cmd_buf.enqueue_command(Command::RegisterLooper);
+  }
   cmd_buf.exec_always_block(None).unwrap();
   
   loop {
@@ -34,10 +41,10 @@ pub fn worker<Mgr: Object<Mgr> + ?Sized>(binder_dev: Arc<OwnedFd>, weak_rt: Weak
         }
       });
     }
-    
-    let mut cmd_buf = CommandBuffer::new(binder_dev.as_fd());
-    cmd_buf.enqueue_command(Command::ExitLooper);
-    cmd_buf.exec_always_block(None).unwrap();
   }
+  
+  let mut cmd_buf = CommandBuffer::new(&binder_dev);
+  cmd_buf.enqueue_command(Command::ExitLooper);
+  cmd_buf.exec_always_block(None).unwrap();
 }
 
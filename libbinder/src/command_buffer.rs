@@ -1,6 +1,6 @@
 use std::{borrow::Cow, io, marker::PhantomData, os::fd::{AsFd, AsRawFd, BorrowedFd}};
 
-use libbinder_raw::{commands::Command as CommandRaw, types::reference::{ObjectRef, ObjectRefRemote}, write_read::binder_read_write};
+use libbinder_raw::{BinderUsize, device::BinderDevice, commands::{Command as CommandRaw, HandleCookieRaw, PtrCookieRaw}, types::reference::{ObjectRef, ObjectRefLocal, ObjectRefRemote}, write_read::binder_read_write};
 use nix::{errno::Errno, poll::{PollFd, PollFlags, PollTimeout, poll}};
 
 use crate::{packet::Packet, return_buffer::ReturnBuffer};
@@ -12,9 +12,31 @@ pub enum Command<'binder: 'data, 'data> {
   Release(ObjectRefRemote),
   AcquireWeak(ObjectRefRemote),
   ReleaseWeak(ObjectRefRemote),
+  
+  // Acknowledges BR_ACQUIRE and BR_INCREFS, must be sent
+  // after the reference is actually taken
+  AcquireDone(ObjectRefLocal),
+  AcquireWeakDone(ObjectRefLocal),
   SendTransaction(ObjectRefRemote, Cow<'data, Packet<'binder>>),
   SendReply(Cow<'data, Packet<'binder>>),
-  RegisterLooper
+  RegisterLooper,
+  
+  // The usize is cookie, which kernel will give back
+  // in BR_DEAD_BINDER and BR_CLEAR_DEATH_NOTIFICATION_DONE
+  RequestDeathNotification(ObjectRefRemote, usize),
+  ClearDeathNotification(ObjectRefRemote, usize),
+  
+  // The usize is cookie given by BR_DEAD_BINDER
+  DeadBinderDone(usize),
+  
+  // The usize is cookie, which kernel will give back
+  // in BR_FROZEN_BINDER and BR_CLEAR_FREEZE_NOTIFICATION_DONE
+  RequestFreezeNotification(ObjectRefRemote, usize),
+  ClearFreezeNotification(ObjectRefRemote, usize),
+  
+  // The usize is cookie given by BR_FROZEN_BINDER, kernel
+  // won't send next one until this is sent
+  FreezeNotificationDone(usize)
 }
 
 pub struct CommandBuffer<'binder, 'data> {
@@ -61,12 +83,12 @@ impl ExecResult {
 }
 
 impl<'binder, 'data> CommandBuffer<'binder, 'data> {
-  pub fn new(binder_dev: BorrowedFd<'binder>) -> Self {
+  pub fn new(binder_dev: &'binder BinderDevice) -> Self {
     Self {
       buffer: Vec::new(),
       commands_end_offsets: Vec::new(),
       _phantom: PhantomData {},
-      binder_dev
+      binder_dev: binder_dev.as_fd()
     }
   }
   
@@ -88,27 +110,95 @@ impl<'binder, 'data> CommandBuffer<'binder, 'data> {
         self.buffer.extend_from_slice(&CommandRaw::ReleaseWeak.as_bytes());
         self.buffer.extend_from_slice(&remote_ref.data_handle.to_ne_bytes());
       },
+      Command::AcquireDone(local_ref) => {
+        self.buffer.extend_from_slice(&CommandRaw::AcquireDone.as_bytes());
+        self.buffer.extend_from_slice(bytemuck::bytes_of(&PtrCookieRaw {
+          ptr: local_ref.data as BinderUsize,
+          cookie: local_ref.extra_data as BinderUsize
+        }));
+      },
+      Command::AcquireWeakDone(local_ref) => {
+        self.buffer.extend_from_slice(&CommandRaw::AcquireWeakDone.as_bytes());
+        self.buffer.extend_from_slice(bytemuck::bytes_of(&PtrCookieRaw {
+          ptr: local_ref.data as BinderUsize,
+          cookie: local_ref.extra_data as BinderUsize
+        }));
+      },
       Command::EnterLooper => self.buffer.extend_from_slice(&CommandRaw::EnterLooper.as_bytes()),
       Command::ExitLooper => self.buffer.extend_from_slice(&CommandRaw::ExitLooper.as_bytes()),
       Command::RegisterLooper => self.buffer.extend_from_slice(&CommandRaw::RegisterLooper.as_bytes()),
+      Command::RequestDeathNotification(remote_ref, cookie) => {
+        self.buffer.extend_from_slice(&CommandRaw::RequestDeathNotification.as_bytes());
+        self.buffer.extend_from_slice(bytemuck::bytes_of(&HandleCookieRaw {
+          handle: remote_ref.data_handle,
+          cookie: cookie as BinderUsize
+        }));
+      },
+      Command::ClearDeathNotification(remote_ref, cookie) => {
+        self.buffer.extend_from_slice(&CommandRaw::ClearDeathNotification.as_bytes());
+        self.buffer.extend_from_slice(bytemuck::bytes_of(&HandleCookieRaw {
+          handle: remote_ref.data_handle,
+          cookie: cookie as BinderUsize
+        }));
+      },
+      Command::DeadBinderDone(cookie) => {
+        self.buffer.extend_from_slice(&CommandRaw::DeadBinderDone.as_bytes());
+        self.buffer.extend_from_slice(&(cookie as BinderUsize).to_ne_bytes());
+      },
+      Command::RequestFreezeNotification(remote_ref, cookie) => {
+        self.buffer.extend_from_slice(&CommandRaw::RequestFreezeNotification.as_bytes());
+        self.buffer.extend_from_slice(bytemuck::bytes_of(&HandleCookieRaw {
+          handle: remote_ref.data_handle,
+          cookie: cookie as BinderUsize
+        }));
+      },
+      Command::ClearFreezeNotification(remote_ref, cookie) => {
+        self.buffer.extend_from_slice(&CommandRaw::ClearFreezeNotification.as_bytes());
+        self.buffer.extend_from_slice(bytemuck::bytes_of(&HandleCookieRaw {
+          handle: remote_ref.data_handle,
+          cookie: cookie as BinderUsize
+        }));
+      },
+      Command::FreezeNotificationDone(cookie) => {
+        self.buffer.extend_from_slice(&CommandRaw::FreezeNotificationDone.as_bytes());
+        self.buffer.extend_from_slice(&(cookie as BinderUsize).to_ne_bytes());
+      },
       Command::SendReply(packet) => {
         assert!(packet.get_binder_dev().as_raw_fd() == self.binder_dev.as_raw_fd(), "attempt to send packet belonging different binder device");
         
-        self.buffer.extend_from_slice(&CommandRaw::SendReply.as_bytes());
-        packet.get_transaction()
-          .with_bytes(|x| {
-            self.buffer.extend_from_slice(x);
-          });
+        let buffers_size = packet.buffers_size();
+        if buffers_size == 0 {
+          self.buffer.extend_from_slice(&CommandRaw::SendReply.as_bytes());
+          packet.get_transaction()
+            .with_bytes(|x| {
+              self.buffer.extend_from_slice(x);
+            });
+        } else {
+          self.buffer.extend_from_slice(&CommandRaw::SendReplySg.as_bytes());
+          packet.get_transaction()
+            .with_bytes_sg(buffers_size, |x| {
+              self.buffer.extend_from_slice(x);
+            });
+        }
       },
       Command::SendTransaction(target, packet) => {
         assert!(packet.get_binder_dev().as_raw_fd() == self.binder_dev.as_raw_fd(), "attempt to send packet belonging different binder device");
         
-        self.buffer.extend_from_slice(&CommandRaw::SendTransaction.as_bytes());
         let mut transact = packet.get_transaction().clone();
         transact.with_common_mut(|common| common.target = ObjectRef::Remote(target));
-        transact.with_bytes(|bytes| {
-          self.buffer.extend_from_slice(bytes);
-        })
+        
+        let buffers_size = packet.buffers_size();
+        if buffers_size == 0 {
+          self.buffer.extend_from_slice(&CommandRaw::SendTransaction.as_bytes());
+          transact.with_bytes(|bytes| {
+            self.buffer.extend_from_slice(bytes);
+          })
+        } else {
+          self.buffer.extend_from_slice(&CommandRaw::SendTransactionSg.as_bytes());
+          transact.with_bytes_sg(buffers_size, |bytes| {
+            self.buffer.extend_from_slice(bytes);
+          })
+        }
       }
     }
     
@@ -204,11 +294,11 @@ impl<'binder, 'data> CommandBuffer<'binder, 'data> {
             continue 'retry_loop;
           }
           
+          let num_executed = self.find_cmd_idx_from_bytes_written(bytes_written + offset).map(|x| x + 1).unwrap_or(0);
           if let Some(buf) = return_buf {
-            buf.parse(bytes_read);
+            buf.parse(bytes_read).map_err(|e| (num_executed, io::Error::new(io::ErrorKind::InvalidData, e)))?;
           }
           
-          let num_executed = self.find_cmd_idx_from_bytes_written(bytes_written + offset).map(|x| x + 1).unwrap_or(0);
           if num_executed == self.commands_end_offsets.len() {
             return Ok(ExecResult::WouldBlockOnRead);
           } else {
@@ -216,18 +306,23 @@ impl<'binder, 'data> CommandBuffer<'binder, 'data> {
           }
         }
         Err((e, (bytes_written, bytes_read))) => {
+          let num_executed = self.find_cmd_idx_from_bytes_written(bytes_written + offset).map(|x| x + 1).unwrap_or(0);
+          
+          // The ioctl error is more important than parse error
           if let Some(buf) = return_buf {
-            buf.parse(bytes_read);
+            let _ = buf.parse(bytes_read);
           }
           
-          return Err((self.find_cmd_idx_from_bytes_written(bytes_written + offset).map(|x| x + 1).unwrap_or(0), e.into()));
+          return Err((num_executed, e.into()));
         }
       }
     }
     
     assert!(bytes_written + offset == self.buffer.len());
     if let Some(buf) = return_buf {
-      buf.parse(bytes_read);
+      // Parse error means kernel sent something weird, let
+      // caller decide what to do with rest of parsed values
+      buf.parse(bytes_read).map_err(|e| (self.commands_end_offsets.len(), io::Error::new(io::ErrorKind::InvalidData, e)))?;
     }
     
     Ok(ExecResult::Ok)
@@ -256,7 +351,7 @@ impl<'binder, 'data> CommandBuffer<'binder, 'data> {
   // Use the existing vector buffers, it is cleared
   // before use. Mainly to reuse underlying buffer
   // for efficiency
-  pub fn from_buffers(binder_dev: BorrowedFd<'binder>, mut raw: (Vec<u8>, Vec<usize>)) -> CommandBuffer<'binder, 'data> {
+  pub fn from_buffers(binder_dev: &'binder BinderDevice, mut raw: (Vec<u8>, Vec<usize>)) -> CommandBuffer<'binder, 'data> {
     raw.0.clear();
     raw.1.clear();
     
@@ -264,7 +359,7 @@ impl<'binder, 'data> CommandBuffer<'binder, 'data> {
       _phantom: PhantomData,
       buffer: raw.0,
       commands_end_offsets: raw.1,
-      binder_dev
+      binder_dev: binder_dev.as_fd()
     }
   }
 }
@@ -4,7 +4,7 @@ use std::{alloc::{self, Layout}, ffi::CStr, slice};
 
 use bytemuck::PodCastError;
 
-use crate::formats::{InnerReader, InnerWriter, ReadFormat, SliceReadResult, WriteFormat};
+use crate::formats::{InnerReader, InnerWriter, ReadError, ReadFormat, SliceReadResult, WriteFormat};
 
 pub struct DeadSimpleFormat<'writer> {
   writer: Option<Box<dyn InnerWriter<'writer> + 'writer>>
@@ -193,13 +193,16 @@ impl Clone for DeadSimpleFormatReader<'_> {
 
 macro_rules! impl_slice {
   ($name:ident, $type:ty) => {
-    fn $name(&mut self) -> Result<SliceReadResult<'reader, $type>, ()> {
+    fn $name(&mut self) -> Result<SliceReadResult<'reader, $type>, ReadError> {
       let length = self.read_usize()?;
-      let bytes = self.get_reader_mut().read(length * size_of::<$type>())?;
+      let offset = self.get_reader().get_current_offset();
+      let size = length.checked_mul(size_of::<$type>())
+        .ok_or(ReadError::LengthOverflow { offset })?;
+      let bytes = self.get_reader_mut().read(size)?;
       // Ensure that reader actually read all byte necessary
       assert!(bytes.len() == length * size_of::<$type>());
       Ok(
-        bytemuck::try_from_bytes::<$type>(bytes)
+        bytemuck::try_cast_slice::<u8, $type>(bytes)
           .map(SliceReadResult::Borrowed)
           .unwrap_or_else(|e| {
             // Unable to do cast, alignment might be wrong
@@ -248,78 +251,79 @@ impl<'reader> ReadFormat<'reader> for DeadSimpleFormatReader<'reader> {
     self.reader.as_ref().unwrap()
   }
   
-  fn read_u8(&mut self) -> Result<u8, ()> {
+  fn read_u8(&mut self) -> Result<u8, ReadError> {
     self.get_reader_mut().read(1)
       .map(|x| x[0])
   }
   
-  fn read_u16(&mut self) -> Result<u16, ()> {
+  fn read_u16(&mut self) -> Result<u16, ReadError> {
     self.get_reader_mut().read(2)
       .map(|x| u16::from_ne_bytes(x.try_into().unwrap()))
   }
   
-  fn read_u32(&mut self) -> Result<u32, ()> {
+  fn read_u32(&mut self) -> Result<u32, ReadError> {
     self.get_reader_mut().read(4)
       .map(|x| u32::from_ne_bytes(x.try_into().unwrap()))
   }
   
-  fn read_u64(&mut self) -> Result<u64, ()> {
+  fn read_u64(&mut self) -> Result<u64, ReadError> {
     self.get_reader_mut().read(8)
       .map(|x| u64::from_ne_bytes(x.try_into().unwrap()))
   }
   
-  fn read_usize(&mut self) -> Result<usize, ()> {
+  fn read_usize(&mut self) -> Result<usize, ReadError> {
     self.get_reader_mut().read(size_of::<usize>())
       .map(|x| usize::from_ne_bytes(x.try_into().unwrap()))
   }
   
-  fn read_i8(&mut self) -> Result<i8, ()> {
+  fn read_i8(&mut self) -> Result<i8, ReadError> {
     self.get_reader_mut().read(1)
       .map(|x| i8::from_ne_bytes(x.try_into().unwrap()))
   }
   
-  fn read_i16(&mut self) -> Result<i16, ()> {
+  fn read_i16(&mut self) -> Result<i16, ReadError> {
     self.get_reader_mut().read(2)
       .map(|x| i16::from_ne_bytes(x.try_into().unwrap()))
   }
   
-  fn read_i32(&mut self) -> Result<i32, ()> {
+  fn read_i32(&mut self) -> Result<i32, ReadError> {
     self.get_reader_mut().read(4)
       .map(|x| i32::from_ne_bytes(x.try_into().unwrap()))
   }
   
-  fn read_i64(&mut self) -> Result<i64, ()> {
+  fn read_i64(&mut self) -> Result<i64, ReadError> {
     self.get_reader_mut().read(8)
       .map(|x| i64::from_ne_bytes(x.try_into().unwrap()))
   }
   
-  fn read_isize(&mut self) -> Result<isize, ()> {
+  fn read_isize(&mut self) -> Result<isize, ReadError> {
     self.get_reader_mut().read(size_of::<isize>())
       .map(|x| isize::from_ne_bytes(x.try_into().unwrap()))
   }
   
-  fn read_f32(&mut self) -> Result<f32, ()> {
+  fn read_f32(&mut self) -> Result<f32, ReadError> {
     self.get_reader_mut().read(size_of::<f32>())
       .map(|x| f32::from_ne_bytes(x.try_into().unwrap()))
   }
   
-  fn read_f64(&mut self) -> Result<f64, ()> {
+  fn read_f64(&mut self) -> Result<f64, ReadError> {
     self.get_reader_mut().read(size_of::<f64>())
       .map(|x| f64::from_ne_bytes(x.try_into().unwrap()))
   }
   
-  fn read_bool(&mut self) -> Result<bool, ()> {
+  fn read_bool(&mut self) -> Result<bool, ReadError> {
+    let offset = self.get_reader().get_current_offset();
     let raw = self.read_u8()?;
     if raw == 0 {
       Ok(false)
     } else if raw == 1 {
-      Ok(false)
+      Ok(true)
     } else {
-      Err(())
+      Err(ReadError::InvalidValue { offset })
     }
   }
   
-  fn read_cstr(&mut self) -> Result<&'reader std::ffi::CStr, ()> {
+  fn read_cstr(&mut self) -> Result<&'reader std::ffi::CStr, ReadError> {
     // Find the length of CString
     let mut length = 0;
     loop {
@@ -330,18 +334,20 @@ impl<'reader> ReadFormat<'reader> for DeadSimpleFormatReader<'reader> {
       length += 1;
     }
     
-    Ok(CStr::from_bytes_with_nul(self.get_reader_mut().read(length)?).unwrap())
+    // Include the nul, so next read starts after it
+    Ok(CStr::from_bytes_with_nul(self.get_reader_mut().read(length + 1)?).unwrap())
   }
   
-  fn read_str(&mut self) -> Result<&'reader str, ()> {
+  fn read_str(&mut self) -> Result<&'reader str, ReadError> {
     let length = self.read_usize()?;
+    let offset = self.get_reader().get_current_offset();
     let bytes = self.get_reader_mut().read(length)?;
     assert!(bytes.len() == length);
     str::from_utf8(bytes)
-      .map_err(|_| ())
+      .map_err(|_| ReadError::InvalidUtf8 { offset })
   }
   
-  fn read_u8_slice(&mut self) -> Result<&'reader [u8], ()> {
+  fn read_u8_slice(&mut self) -> Result<&'reader [u8], ReadError> {
     let length = self.read_usize()?;
     let bytes = self.get_reader_mut().read(length)?;
     assert!(bytes.len() == length);
@@ -352,7 +358,7 @@ impl<'reader> ReadFormat<'reader> for DeadSimpleFormatReader<'reader> {
   impl_slice!(read_u64_slice, u64);
   impl_slice!(read_usize_slice, usize);
   
-  fn read_i8_slice(&mut self) -> Result<&'reader [i8], ()> {
+  fn read_i8_slice(&mut self) -> Result<&'reader [i8], ReadError> {
     let length = self.read_usize()?;
     Ok(bytemuck::cast_slice(self.get_reader_mut().read(length)?))
   }
@@ -364,7 +370,7 @@ impl<'reader> ReadFormat<'reader> for DeadSimpleFormatReader<'reader> {
   impl_slice!(read_f32_slice, f32);
   impl_slice!(read_f64_slice, f64);
   
-  fn read_str_slice(&mut self, result: &mut Vec<&'reader str>) -> Result<(), ()> {
+  fn read_str_slice(&mut self, result: &mut Vec<&'reader str>) -> Result<(), ReadError> {
     let length = self.read_usize()?;
     result.reserve(length);
     for _ in 0..length {
@@ -374,7 +380,7 @@ impl<'reader> ReadFormat<'reader> for DeadSimpleFormatReader<'reader> {
     Ok(())
   }
   
-  fn read_cstr_slice(&mut self, result: &mut Vec<&'reader CStr>) -> Result<(), ()> {
+  fn read_cstr_slice(&mut self, result: &mut Vec<&'reader CStr>) -> Result<(), ReadError> {
     let length = self.read_usize()?;
     result.reserve(length);
     for _ in 0..length {
@@ -384,16 +390,17 @@ impl<'reader> ReadFormat<'reader> for DeadSimpleFormatReader<'reader> {
     Ok(())
   }
   
-  fn read_bool_slice(&mut self) -> Result<&'reader [bool], ()> {
+  fn read_bool_slice(&mut self) -> Result<SliceReadResult<'reader, bool>, ReadError> {
     let bytes = self.read_u8_slice()?;
     
     // There bytes which has invalid bit pattern for bool
-    if bytes.iter().find(|&&x| x != 0x00 && x != 0x01).is_some() {
-      return Err(());
+    if let Some(idx) = bytes.iter().position(|&x| x != 0x00 && x != 0x01) {
+      let offset = self.get_reader().get_current_offset() - bytes.len() + idx;
+      return Err(ReadError::InvalidValue { offset });
     }
     
     // SAFETY: Checked that it is valid bits
-    Ok(unsafe { slice::from_raw_parts(bytes.as_ptr().cast::<bool>(), bytes.len()) })
+    Ok(SliceReadResult::Borrowed(unsafe { slice::from_raw_parts(bytes.as_ptr().cast::<bool>(), bytes.len()) }))
   }
 }
 
@@ -0,0 +1,636 @@
+// HIDL's HwParcel format, as libhwbinder writes it. Primitives
+// are written in place padded to multiple of 4 bytes and strings
+// and slices are hidl_string and hidl_vec. Those are written as
+// buffer object containing the struct itself and another buffer
+// object for the data, which is embedded into the struct
+//
+// The struct is 16 bytes, the pointer to the data which kernel
+// fixes up, u32 length and bool whether it owns the buffer
+
+use std::{ffi::CStr, slice};
+
+use bytemuck::Pod;
+use libbinder_raw::types::buffer::ObjectBufferParent;
+
+use crate::{formats::{InnerReader, InnerWriter, ReadError, ReadFormat, SliceReadResult, WriteError, WriteFormat, cast_or_copy}, packet::{reader::BufferView, writer::BufferHandle}};
+
+const ALIGNMENT: usize = size_of::<u32>();
+
+const EMBEDDED_STRUCT_SIZE: usize = 16;
+const OFFSET_OF_BUFFER: usize = 0;
+const OFFSET_OF_SIZE: usize = 8;
+
+fn padding_for(len: usize) -> usize {
+  len.next_multiple_of(ALIGNMENT) - len
+}
+
+// The hidl_string/hidl_vec struct, pointer is left
+// for kernel to fill
+fn embedded_struct(size: u32) -> [u8; EMBEDDED_STRUCT_SIZE] {
+  let mut raw = [0; EMBEDDED_STRUCT_SIZE];
+  raw[OFFSET_OF_SIZE..OFFSET_OF_SIZE + size_of::<u32>()].copy_from_slice(&size.to_ne_bytes());
+  raw
+}
+
+fn embedded_size(raw: &[u8]) -> Option<usize> {
+  if raw.len() != EMBEDDED_STRUCT_SIZE {
+    return None;
+  }
+  
+  let size = &raw[OFFSET_OF_SIZE..OFFSET_OF_SIZE + size_of::<u32>()];
+  Some(u32::from_ne_bytes(size.try_into().unwrap()) as usize)
+}
+
+pub struct HwParcelFormat<'writer> {
+  writer: Option<Box<dyn InnerWriter<'writer> + 'writer>>
+}
+
+impl<'writer> HwParcelFormat<'writer> {
+  pub fn new() -> Self {
+    Self {
+      writer: None
+    }
+  }
+  
+  fn write_padded(&mut self, bytes: &[u8]) {
+    let writer = self.get_writer_mut();
+    writer.write(bytes);
+    writer.write(&[0; ALIGNMENT][..padding_for(bytes.len())]);
+  }
+  
+  // Sizes in the struct are u32, longer data fails the packet
+  fn hidl_size(&mut self, size: usize) -> Option<u32> {
+    let Ok(size) = u32::try_from(size) else {
+      let offset = self.get_writer().get_current_offset();
+      self.get_writer_mut().set_error(WriteError::LengthTooLarge { offset });
+      return None;
+    };
+    Some(size)
+  }
+  
+  // Writes the struct and the data embedded in it, gives
+  // the handle to the data's buffer
+  fn write_embedded(&mut self, data: &[u8], size: usize) -> Option<BufferHandle> {
+    let size = self.hidl_size(size)?;
+    let writer = self.get_writer_mut();
+    let parent = writer.write_buffer(&embedded_struct(size), None).unwrap();
+    let handle = writer.write_buffer(data, Some((parent, OFFSET_OF_BUFFER)))
+      .expect("pointer is inside the struct");
+    Some(handle)
+  }
+  
+  fn write_embedded_slice<T: Pod>(&mut self, data: &[T]) {
+    self.write_embedded(bytemuck::cast_slice(data), data.len());
+  }
+  
+  // HwParcel's interface token is only the descriptor
+  pub fn write_interface_token(&mut self, descriptor: &CStr) {
+    self.write_cstr(descriptor);
+  }
+}
+
+impl<'writer> WriteFormat<'writer> for HwParcelFormat<'writer> {
+  fn set_writer(&mut self, writer: Box<dyn InnerWriter<'writer> + 'writer>) {
+    self.writer = Some(writer);
+  }
+  
+  fn get_writer_mut(&mut self) -> &mut Box<dyn InnerWriter<'writer> + 'writer> {
+    self.writer.as_mut().unwrap()
+  }
+  
+  fn get_writer(&self) -> &Box<dyn InnerWriter<'writer> + 'writer> {
+    self.writer.as_ref().unwrap()
+  }
+  
+  fn write_u8(&mut self, data: u8) {
+    self.write_padded(&data.to_ne_bytes());
+  }
+  
+  fn write_u16(&mut self, data: u16) {
+    self.write_padded(&data.to_ne_bytes());
+  }
+  
+  fn write_u32(&mut self, data: u32) {
+    self.write_padded(&data.to_ne_bytes());
+  }
+  
+  fn write_u64(&mut self, data: u64) {
+    self.write_padded(&data.to_ne_bytes());
+  }
+  
+  fn write_usize(&mut self, data: usize) {
+    self.write_u64(data as u64);
+  }
+  
+  fn write_i8(&mut self, data: i8) {
+    self.write_padded(&data.to_ne_bytes());
+  }
+  
+  fn write_i16(&mut self, data: i16) {
+    self.write_padded(&data.to_ne_bytes());
+  }
+  
+  fn write_i32(&mut self, data: i32) {
+    self.write_padded(&data.to_ne_bytes());
+  }
+  
+  fn write_i64(&mut self, data: i64) {
+    self.write_padded(&data.to_ne_bytes());
+  }
+  
+  fn write_isize(&mut self, data: isize) {
+    self.write_i64(data as i64);
+  }
+  
+  fn write_f32(&mut self, data: f32) {
+    self.write_padded(&data.to_ne_bytes());
+  }
+  
+  fn write_f64(&mut self, data: f64) {
+    self.write_padded(&data.to_ne_bytes());
+  }
+  
+  fn write_bool(&mut self, data: bool) {
+    self.write_u8(data as u8);
+  }
+  
+  // hidl_string, the length doesn't include the nul
+  // but the data does
+  fn write_str(&mut self, data: &str) {
+    let mut bytes = Vec::with_capacity(data.len() + 1);
+    bytes.extend_from_slice(data.as_bytes());
+    bytes.push(0);
+    self.write_embedded(&bytes, data.len());
+  }
+  
+  // Only in place, nul terminated
+  fn write_cstr(&mut self, data: &CStr) {
+    self.write_padded(data.to_bytes_with_nul());
+  }
+  
+  fn write_u8_slice(&mut self, data: &[u8]) {
+    self.write_embedded_slice(data);
+  }
+  
+  fn write_u16_slice(&mut self, data: &[u16]) {
+    self.write_embedded_slice(data);
+  }
+  
+  fn write_u32_slice(&mut self, data: &[u32]) {
+    self.write_embedded_slice(data);
+  }
+  
+  fn write_u64_slice(&mut self, data: &[u64]) {
+    self.write_embedded_slice(data);
+  }
+  
+  fn write_usize_slice(&mut self, data: &[usize]) {
+    self.write_embedded_slice(&data.iter().map(|&x| x as u64).collect::<Vec<_>>());
+  }
+  
+  fn write_i8_slice(&mut self, data: &[i8]) {
+    self.write_embedded_slice(data);
+  }
+  
+  fn write_i16_slice(&mut self, data: &[i16]) {
+    self.write_embedded_slice(data);
+  }
+  
+  fn write_i32_slice(&mut self, data: &[i32]) {
+    self.write_embedded_slice(data);
+  }
+  
+  fn write_i64_slice(&mut self, data: &[i64]) {
+    self.write_embedded_slice(data);
+  }
+  
+  fn write_isize_slice(&mut self, data: &[isize]) {
+    self.write_embedded_slice(&data.iter().map(|&x| x as i64).collect::<Vec<_>>());
+  }
+  
+  fn write_f32_slice(&mut self, data: &[f32]) {
+    self.write_embedded_slice(data);
+  }
+  
+  fn write_f64_slice(&mut self, data: &[f64]) {
+    self.write_embedded_slice(data);
+  }
+  
+  // hidl_vec<hidl_string>, the vec's data is array of hidl_string
+  // and each string's data is embedded into that array
+  fn write_str_slice(&mut self, data: &[&str]) {
+    let Some(sizes) = data.iter()
+      .map(|string| self.hidl_size(string.len()))
+      .collect::<Option<Vec<u32>>>()
+    else {
+      return;
+    };
+    
+    let structs = sizes.into_iter()
+      .flat_map(embedded_struct)
+      .collect::<Vec<u8>>();
+    let Some(array) = self.write_embedded(&structs, data.len()) else {
+      return;
+    };
+    
+    for (i, string) in data.iter().enumerate() {
+      let mut bytes = Vec::with_capacity(string.len() + 1);
+      bytes.extend_from_slice(string.as_bytes());
+      bytes.push(0);
+      self.get_writer_mut().write_buffer(&bytes, Some((array, i * EMBEDDED_STRUCT_SIZE + OFFSET_OF_BUFFER)))
+        .expect("pointer is inside the array");
+    }
+  }
+  
+  // HIDL has no array of C strings, and making one up
+  // would give bytes libhwbinder can't read
+  fn write_cstr_slice(&mut self, _data: &[&CStr]) {
+    let offset = self.get_writer().get_current_offset();
+    self.get_writer_mut().set_error(WriteError::Unsupported { offset });
+  }
+  
+  fn write_bool_slice(&mut self, data: &[bool]) {
+    self.write_embedded_slice(&data.iter().map(|&x| x as u8).collect::<Vec<_>>());
+  }
+}
+
+pub struct HwParcelFormatReader<'reader> {
+  reader: Option<Box<dyn InnerReader<'reader>>>
+}
+
+impl Clone for HwParcelFormatReader<'_> {
+  fn clone(&self) -> Self {
+    Self {
+      reader: self.reader.as_ref().map(|x| x.clone_reader())
+    }
+  }
+}
+
+impl<'reader> HwParcelFormatReader<'reader> {
+  pub fn new() -> Self {
+    Self {
+      reader: None
+    }
+  }
+  
+  // Reads the bytes and skips padding after it
+  fn read_padded(&mut self, len: usize) -> Result<&'reader [u8], ReadError> {
+    Ok(&self.get_reader_mut().read(len.next_multiple_of(ALIGNMENT))?[..len])
+  }
+  
+  // Reads buffer which must be embedded into parent at given offset
+  fn read_child(&mut self, parent: &BufferView<'reader>, offset: usize) -> Result<BufferView<'reader>, ReadError> {
+    let object_offset = self.get_reader().get_current_offset();
+    let child = self.get_reader_mut().read_buffer()?;
+    if child.get_parent() != Some(ObjectBufferParent { index: parent.get_index(), offset }) {
+      return Err(ReadError::MalformedObject { offset: object_offset });
+    }
+    Ok(child)
+  }
+  
+  // Reads the struct and the data embedded in it, gives
+  // length in the struct and the data
+  fn read_embedded(&mut self) -> Result<(usize, BufferView<'reader>), ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    let parent = self.get_reader_mut().read_buffer()?;
+    let size = embedded_size(parent.get_data())
+      .ok_or(ReadError::InvalidValue { offset })?;
+    Ok((size, self.read_child(&parent, OFFSET_OF_BUFFER)?))
+  }
+  
+  fn read_embedded_bytes<T>(&mut self) -> Result<&'reader [u8], ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    let (length, data) = self.read_embedded()?;
+    if length.checked_mul(size_of::<T>()) != Some(data.get_data().len()) {
+      return Err(ReadError::InvalidValue { offset });
+    }
+    Ok(data.get_data())
+  }
+  
+  fn read_embedded_slice<T: Pod>(&mut self) -> Result<SliceReadResult<'reader, T>, ReadError> {
+    self.read_embedded_bytes::<T>()
+      .map(cast_or_copy)
+  }
+  
+  // String must be nul terminated, and length doesn't include it
+  fn check_string(data: &BufferView<'reader>, length: usize, offset: usize) -> Result<&'reader str, ReadError> {
+    let data = data.get_data();
+    if length.checked_add(1) != Some(data.len()) || data[length] != 0 {
+      return Err(ReadError::InvalidValue { offset });
+    }
+    
+    str::from_utf8(&data[..length])
+      .map_err(|_| ReadError::InvalidUtf8 { offset })
+  }
+  
+  pub fn read_interface_token(&mut self) -> Result<&'reader CStr, ReadError> {
+    self.read_cstr()
+  }
+}
+
+macro_rules! impl_slice {
+  ($name:ident, $type:ty) => {
+    fn $name(&mut self) -> Result<SliceReadResult<'reader, $type>, ReadError> {
+      self.read_embedded_slice::<$type>()
+    }
+  };
+}
+
+impl<'reader> ReadFormat<'reader> for HwParcelFormatReader<'reader> {
+  fn get_reader_mut(&mut self) -> &mut Box<dyn InnerReader<'reader>> {
+    self.reader.as_mut().unwrap()
+  }
+  
+  fn set_reader(&mut self, reader: Box<dyn InnerReader<'reader>>) {
+    self.reader = Some(reader);
+  }
+  
+  fn get_reader(&self) -> &Box<dyn InnerReader<'reader>> {
+    self.reader.as_ref().unwrap()
+  }
+  
+  fn read_u8(&mut self) -> Result<u8, ReadError> {
+    self.read_padded(1)
+      .map(|x| x[0])
+  }
+  
+  fn read_u16(&mut self) -> Result<u16, ReadError> {
+    self.read_padded(2)
+      .map(|x| u16::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  fn read_u32(&mut self) -> Result<u32, ReadError> {
+    self.read_padded(4)
+      .map(|x| u32::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  fn read_u64(&mut self) -> Result<u64, ReadError> {
+    self.read_padded(8)
+      .map(|x| u64::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  fn read_usize(&mut self) -> Result<usize, ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    usize::try_from(self.read_u64()?)
+      .map_err(|_| ReadError::InvalidValue { offset })
+  }
+  
+  fn read_i8(&mut self) -> Result<i8, ReadError> {
+    self.read_padded(1)
+      .map(|x| x[0] as i8)
+  }
+  
+  fn read_i16(&mut self) -> Result<i16, ReadError> {
+    self.read_padded(2)
+      .map(|x| i16::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  fn read_i32(&mut self) -> Result<i32, ReadError> {
+    self.read_padded(4)
+      .map(|x| i32::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  fn read_i64(&mut self) -> Result<i64, ReadError> {
+    self.read_padded(8)
+      .map(|x| i64::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  fn read_isize(&mut self) -> Result<isize, ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    isize::try_from(self.read_i64()?)
+      .map_err(|_| ReadError::InvalidValue { offset })
+  }
+  
+  fn read_f32(&mut self) -> Result<f32, ReadError> {
+    self.read_padded(size_of::<f32>())
+      .map(|x| f32::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  fn read_f64(&mut self) -> Result<f64, ReadError> {
+    self.read_padded(size_of::<f64>())
+      .map(|x| f64::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  // libhwbinder treats anything non zero as true
+  fn read_bool(&mut self) -> Result<bool, ReadError> {
+    self.read_u8().map(|x| x != 0)
+  }
+  
+  fn read_cstr(&mut self) -> Result<&'reader CStr, ReadError> {
+    // Find the length of CString
+    let mut length = 0;
+    loop {
+      let current = self.get_reader_mut().peek(1, /* length is also offset */ length)?;
+      if current[0] == 0 {
+        break;
+      }
+      length += 1;
+    }
+    
+    // Include the nul
+    Ok(CStr::from_bytes_with_nul(self.read_padded(length + 1)?).unwrap())
+  }
+  
+  fn read_str(&mut self) -> Result<&'reader str, ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    let (length, data) = self.read_embedded()?;
+    Self::check_string(&data, length, offset)
+  }
+  
+  fn read_u8_slice(&mut self) -> Result<&'reader [u8], ReadError> {
+    self.read_embedded_bytes::<u8>()
+  }
+  impl_slice!(read_u16_slice, u16);
+  impl_slice!(read_u32_slice, u32);
+  impl_slice!(read_u64_slice, u64);
+  
+  fn read_usize_slice(&mut self) -> Result<SliceReadResult<'reader, usize>, ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    self.read_embedded_slice::<u64>()?
+      .iter()
+      .map(|&x| usize::try_from(x))
+      .collect::<Result<Box<[usize]>, _>>()
+      .map(SliceReadResult::Owned)
+      .map_err(|_| ReadError::InvalidValue { offset })
+  }
+  
+  fn read_i8_slice(&mut self) -> Result<&'reader [i8], ReadError> {
+    Ok(bytemuck::cast_slice(self.read_embedded_bytes::<i8>()?))
+  }
+  impl_slice!(read_i16_slice, i16);
+  impl_slice!(read_i32_slice, i32);
+  impl_slice!(read_i64_slice, i64);
+  
+  fn read_isize_slice(&mut self) -> Result<SliceReadResult<'reader, isize>, ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    self.read_embedded_slice::<i64>()?
+      .iter()
+      .map(|&x| isize::try_from(x))
+      .collect::<Result<Box<[isize]>, _>>()
+      .map(SliceReadResult::Owned)
+      .map_err(|_| ReadError::InvalidValue { offset })
+  }
+  
+  impl_slice!(read_f32_slice, f32);
+  impl_slice!(read_f64_slice, f64);
+  
+  fn read_str_slice(&mut self, result: &mut Vec<&'reader str>) -> Result<(), ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    let (length, array) = self.read_embedded()?;
+    if length.checked_mul(EMBEDDED_STRUCT_SIZE) != Some(array.get_data().len()) {
+      return Err(ReadError::InvalidValue { offset });
+    }
+    
+    for (i, raw) in array.get_data().chunks_exact(EMBEDDED_STRUCT_SIZE).enumerate() {
+      let offset = self.get_reader().get_current_offset();
+      let data = self.read_child(&array, i * EMBEDDED_STRUCT_SIZE + OFFSET_OF_BUFFER)?;
+      result.push(Self::check_string(&data, embedded_size(raw).unwrap(), offset)?);
+    }
+    
+    Ok(())
+  }
+  
+  fn read_cstr_slice(&mut self, _result: &mut Vec<&'reader CStr>) -> Result<(), ReadError> {
+    Err(ReadError::Unsupported { offset: self.get_reader().get_current_offset() })
+  }
+  
+  fn read_bool_slice(&mut self) -> Result<SliceReadResult<'reader, bool>, ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    let bytes = self.read_embedded_bytes::<u8>()?;
+    
+    // Can't be borrowed as bool if there invalid bit pattern
+    if bytes.iter().any(|&x| x != 0x00 && x != 0x01) {
+      return Err(ReadError::InvalidValue { offset });
+    }
+    
+    // SAFETY: Checked that it is valid bits
+    Ok(SliceReadResult::Borrowed(unsafe { slice::from_raw_parts(bytes.as_ptr().cast::<bool>(), bytes.len()) }))
+  }
+}
+
+// Tests follow buffer pointers of outgoing packet, which get
+// truncated when 32-bit protocol is used on 64-bit process
+#[cfg(all(test, any(not(feature = "ipc-32bit"), target_pointer_width = "32")))]
+mod tests {
+  use std::slice;
+  
+  use crate::packet::{Packet, test_builder, test_packet};
+  
+  use super::*;
+  
+  type BufferInfo<'a> = (&'a [u8], Option<(usize, usize)>);
+  
+  // Data and parent of each buffer object in the packet
+  fn buffers<'a>(packet: &'a Packet) -> Vec<BufferInfo<'a>> {
+    packet.iter_buffers()
+      .map(|(_, buffer)| {
+        let data = if buffer.length == 0 {
+          &[][..]
+        } else {
+          unsafe { slice::from_raw_parts(buffer.buffer as *const u8, buffer.length) }
+        };
+        (data, buffer.parent.map(|x| (x.index, x.offset)))
+      })
+      .collect()
+  }
+  
+  fn size_field(raw: &[u8]) -> u32 {
+    u32::from_ne_bytes(raw[OFFSET_OF_SIZE..OFFSET_OF_SIZE + 4].try_into().unwrap())
+  }
+  
+  #[test]
+  fn hidl_string() {
+    let packet = test_packet!(HwParcelFormat::new(), |writer| {
+      writer.write_str("abc");
+    });
+    
+    let buffers = buffers(&packet);
+    assert_eq!(buffers.len(), 2);
+    
+    // Size excludes the nul, which the data has
+    let (hidl_string, parent) = buffers[0];
+    assert_eq!(hidl_string.len(), EMBEDDED_STRUCT_SIZE);
+    assert_eq!(size_field(hidl_string), 3);
+    assert_eq!(parent, None);
+    assert_eq!(buffers[1], (b"abc\0".as_slice(), Some((0, OFFSET_OF_BUFFER))));
+    
+    let mut reader = packet.reader(HwParcelFormatReader::new());
+    assert_eq!(reader.read_str(), Ok("abc"));
+  }
+  
+  #[test]
+  fn hidl_vec() {
+    let packet = test_packet!(HwParcelFormat::new(), |writer| {
+      writer.write_u32(7).write_u32_slice(&[1, 2]);
+    });
+    
+    let buffers = buffers(&packet);
+    assert_eq!(buffers.len(), 2);
+    
+    let (hidl_vec, parent) = buffers[0];
+    assert_eq!(hidl_vec.len(), EMBEDDED_STRUCT_SIZE);
+    assert_eq!(size_field(hidl_vec), 2);
+    assert_eq!(parent, None);
+    assert_eq!(buffers[1], (bytemuck::cast_slice(&[1u32, 2]), Some((0, OFFSET_OF_BUFFER))));
+    
+    let mut reader = packet.reader(HwParcelFormatReader::new());
+    assert_eq!(reader.read_u32(), Ok(7));
+    assert_eq!(&*reader.read_u32_slice().unwrap(), [1, 2]);
+  }
+  
+  // Empty hidl_vec still has its data buffer, just empty
+  #[test]
+  fn empty_hidl_vec() {
+    let packet = test_packet!(HwParcelFormat::new(), |writer| {
+      writer.write_u8_slice(&[]);
+    });
+    
+    let buffers = buffers(&packet);
+    assert_eq!(buffers.len(), 2);
+    assert_eq!(size_field(buffers[0].0), 0);
+    assert_eq!(buffers[1], (&[][..], Some((0, OFFSET_OF_BUFFER))));
+    
+    let mut reader = packet.reader(HwParcelFormatReader::new());
+    assert_eq!(reader.read_u8_slice(), Ok(&[][..]));
+  }
+  
+  // Each string's data points into its hidl_string inside the array
+  #[test]
+  fn hidl_vec_of_strings() {
+    let packet = test_packet!(HwParcelFormat::new(), |writer| {
+      writer.write_str_slice(&["a", "bc"]);
+    });
+    
+    let buffers = buffers(&packet);
+    assert_eq!(buffers.len(), 4);
+    assert_eq!(size_field(buffers[0].0), 2);
+    
+    let (array, parent) = buffers[1];
+    assert_eq!(parent, Some((0, OFFSET_OF_BUFFER)));
+    assert_eq!(array.len(), 2 * EMBEDDED_STRUCT_SIZE);
+    assert_eq!(size_field(&array[..EMBEDDED_STRUCT_SIZE]), 1);
+    assert_eq!(size_field(&array[EMBEDDED_STRUCT_SIZE..]), 2);
+    
+    assert_eq!(buffers[2], (b"a\0".as_slice(), Some((1, OFFSET_OF_BUFFER))));
+    assert_eq!(buffers[3], (b"bc\0".as_slice(), Some((1, EMBEDDED_STRUCT_SIZE + OFFSET_OF_BUFFER))));
+    
+    let mut reader = packet.reader(HwParcelFormatReader::new());
+    assert_eq!(reader.read_str_slice(), Ok(vec!["a", "bc"]));
+  }
+  
+  #[test]
+  fn cstr_slice_unsupported() {
+    let mut builder = test_builder();
+    let mut writer = builder.writer(HwParcelFormat::new());
+    writer.write_u32(0).write_cstr_slice(&[c"a"]);
+    drop(writer);
+    assert_eq!(builder.build().err(), Some(WriteError::Unsupported { offset: 4 }));
+    
+    let packet = test_packet!(HwParcelFormat::new(), |writer| {
+      writer.write_u32(0);
+    });
+    
+    let mut reader = packet.reader(HwParcelFormatReader::new());
+    assert_eq!(reader.read_cstr_slice(), Err(ReadError::Unsupported { offset: 0 }));
+  }
+}
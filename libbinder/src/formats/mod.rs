@@ -1,24 +1,174 @@
 // Contains various different format for data inside the packet's data buffer
 // each write, must be independent that mean do not write header/footer
 
-use std::ffi::CStr;
+use std::{error::Error, ffi::CStr, fmt::Display, io, ops::Deref, sync::Arc};
+
+use bytemuck::Pod;
+use libbinder_raw::{BinderUsize, types::Type};
+use nix::errno::Errno;
+
+use crate::packet::{reader::BufferView, writer::BufferHandle};
 
 pub mod dead_simple;
+pub mod parcel;
+pub mod hw_parcel;
+
+// The offset is where in data buffer the failing read
+// starts. Reader rolls back to before the read on error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadError {
+  // Not enough data left
+  OutOfBounds { offset: usize, needed: usize },
+  
+  // Length read from packet is too large to even
+  // compute how many bytes it needs
+  LengthOverflow { offset: usize },
+  
+  // Primitive read would read part of binder object
+  OverlapsObject { offset: usize },
+  
+  InvalidUtf8 { offset: usize },
+  
+  // Bit pattern is not valid for the type (e.g. bool)
+  InvalidValue { offset: usize },
+  
+  // Expected binder object of other type there
+  UnexpectedObject { offset: usize, found: Type },
+  
+  // Binder object is not valid, or not a binder object
+  // at all when one is expected
+  MalformedObject { offset: usize },
+  
+  // Caller of read_reference rejected the reference
+  Rejected { offset: usize },
+  
+  // Format has no way to encode what is being read
+  Unsupported { offset: usize },
+  
+  // Could not duplicate fd inside the packet
+  Fd { offset: usize, errno: Errno }
+}
+
+impl ReadError {
+  pub fn get_offset(&self) -> usize {
+    match *self {
+      ReadError::OutOfBounds { offset, .. } |
+      ReadError::LengthOverflow { offset } |
+      ReadError::OverlapsObject { offset } |
+      ReadError::InvalidUtf8 { offset } |
+      ReadError::InvalidValue { offset } |
+      ReadError::UnexpectedObject { offset, .. } |
+      ReadError::MalformedObject { offset } |
+      ReadError::Rejected { offset } |
+      ReadError::Unsupported { offset } |
+      ReadError::Fd { offset, .. } => offset
+    }
+  }
+}
+
+impl Display for ReadError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ReadError::OutOfBounds { offset, needed } => write!(f, "out of bounds read of {needed} bytes at offset {offset}"),
+      ReadError::LengthOverflow { offset } => write!(f, "length at offset {offset} overflows"),
+      ReadError::OverlapsObject { offset } => write!(f, "read at offset {offset} overlaps binder object"),
+      ReadError::InvalidUtf8 { offset } => write!(f, "invalid UTF-8 string at offset {offset}"),
+      ReadError::InvalidValue { offset } => write!(f, "invalid value at offset {offset}"),
+      ReadError::UnexpectedObject { offset, found } => write!(f, "unexpected binder object {found:?} at offset {offset}"),
+      ReadError::MalformedObject { offset } => write!(f, "malformed binder object at offset {offset}"),
+      ReadError::Rejected { offset } => write!(f, "reference at offset {offset} rejected"),
+      ReadError::Unsupported { offset } => write!(f, "read at offset {offset} is not supported by the format"),
+      ReadError::Fd { offset, errno } => write!(f, "cannot duplicate fd at offset {offset}: {errno}")
+    }
+  }
+}
+
+impl Error for ReadError {}
+
+// Writes don't fail right away, first error is kept and given
+// by PacketBuilder::build. The offset is where the write started
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteError {
+  // Length doesn't fit into what format writes it as
+  LengthTooLarge { offset: usize },
+  
+  // Format has no way to encode what is being written
+  Unsupported { offset: usize }
+}
+
+impl WriteError {
+  pub fn get_offset(&self) -> usize {
+    match *self {
+      WriteError::LengthTooLarge { offset } |
+      WriteError::Unsupported { offset } => offset
+    }
+  }
+}
+
+impl Display for WriteError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      WriteError::LengthTooLarge { offset } => write!(f, "length written at offset {offset} is too large"),
+      WriteError::Unsupported { offset } => write!(f, "write at offset {offset} is not supported by the format")
+    }
+  }
+}
+
+impl Error for WriteError {}
 
 pub enum SliceReadResult<'reader, T> {
   // Incase the data is aligned
-  Borrowed(&'reader T),
+  Borrowed(&'reader [T]),
   
   // Incase the data is not aligned
   // so copy is needed
   Owned(Box<[T]>)
 }
 
+impl<T> Deref for SliceReadResult<'_, T> {
+  type Target = [T];
+  
+  fn deref(&self) -> &[T] {
+    match self {
+      SliceReadResult::Borrowed(x) => x,
+      SliceReadResult::Owned(x) => x
+    }
+  }
+}
+
+// Borrows if the bytes are aligned for T,
+// if not copy into aligned space
+pub(crate) fn cast_or_copy<T: Pod>(bytes: &[u8]) -> SliceReadResult<'_, T> {
+  bytemuck::try_cast_slice(bytes)
+    .map(SliceReadResult::Borrowed)
+    .unwrap_or_else(|_| {
+      SliceReadResult::Owned(
+        bytes.chunks_exact(size_of::<T>())
+          .map(bytemuck::pod_read_unaligned)
+          .collect()
+      )
+    })
+}
+
 pub trait InnerReader<'reader>: 'reader {
   fn get_current_offset(&self) -> usize;
   fn clone_reader(&self) -> Box<dyn InnerReader<'reader>>;
-  fn peek(&self, size: usize, offset: usize) -> Result<&'reader [u8], ()>;
-  fn read(&mut self, size: usize) -> Result<&'reader [u8], ()>;
+  fn peek(&self, size: usize, offset: usize) -> Result<&'reader [u8], ReadError>;
+  fn read(&mut self, size: usize) -> Result<&'reader [u8], ReadError>;
+  
+  // Binder objects, which primitive reads refuse to touch
+  fn peek_object(&self, size: usize) -> Result<&'reader [u8], ReadError>;
+  fn read_object(&mut self, size: usize) -> Result<&'reader [u8], ReadError>;
+  
+  // Reads buffer object, same as Reader::read_buffer. For
+  // formats which keep data outside of the data buffer
+  fn read_buffer(&mut self) -> Result<BufferView<'reader>, ReadError>;
+  
+  // Skips bytes until offset is multiple of alignment
+  fn skip_to_alignment(&mut self, alignment: usize) -> Result<(), ReadError> {
+    let offset = self.get_current_offset();
+    self.read(offset.next_multiple_of(alignment) - offset).map(|_| ())
+  }
 }
 
 pub trait ReadFormat<'reader>: Clone {
@@ -32,54 +182,81 @@ pub trait ReadFormat<'reader>: Clone {
   fn get_reader_mut(&mut self) -> &mut Box<dyn InnerReader<'reader>>;
   fn get_reader(&self) -> &Box<dyn InnerReader<'reader>>;
   
-  fn read_u8(&mut self) -> Result<u8, ()>;
-  fn read_u16(&mut self) -> Result<u16, ()>;
-  fn read_u32(&mut self) -> Result<u32, ()>;
-  fn read_u64(&mut self) -> Result<u64, ()>;
-  fn read_usize(&mut self) -> Result<usize, ()>;
-  
-  fn read_i8(&mut self) -> Result<i8, ()>;
-  fn read_i16(&mut self) -> Result<i16, ()>;
-  fn read_i32(&mut self) -> Result<i32, ()>;
-  fn read_i64(&mut self) -> Result<i64, ()>;
-  fn read_isize(&mut self) -> Result<isize, ()>;
-  
-  fn read_f32(&mut self) -> Result<f32, ()>;
-  fn read_f64(&mut self) -> Result<f64, ()>;
-  fn read_str(&mut self) -> Result<&'reader str, ()>;
-  fn read_cstr(&mut self) -> Result<&'reader CStr, ()>;
-  fn read_bool(&mut self) -> Result<bool, ()>;
-  
-  fn read_u8_slice(&mut self) -> Result<&'reader [u8], ()>;
-  fn read_u16_slice(&mut self) -> Result<SliceReadResult<'reader, u16>, ()>;
-  fn read_u32_slice(&mut self) -> Result<SliceReadResult<'reader, u32>, ()>;
-  fn read_u64_slice(&mut self) -> Result<SliceReadResult<'reader, u64>, ()>;
-  fn read_usize_slice(&mut self) -> Result<SliceReadResult<'reader, usize>, ()>;
-  
-  fn read_i8_slice(&mut self) -> Result<&'reader [i8], ()>;
-  fn read_i16_slice(&mut self) -> Result<SliceReadResult<'reader, i16>, ()>;
-  fn read_i32_slice(&mut self) -> Result<SliceReadResult<'reader, i32>, ()>;
-  fn read_i64_slice(&mut self) -> Result<SliceReadResult<'reader, i64>, ()>;
-  fn read_isize_slice(&mut self) -> Result<SliceReadResult<'reader, isize>, ()>;
-  
-  fn read_f32_slice(&mut self) -> Result<SliceReadResult<'reader, f32>, ()>;
-  fn read_f64_slice(&mut self) -> Result<SliceReadResult<'reader, f64>, ()>;
+  // Consumes what align_for_object of the writer wrote
+  fn align_for_object(&mut self) -> Result<(), ReadError> {
+    self.get_reader_mut().skip_to_alignment(Type::alignment_in_buffer_needed())
+  }
+  
+  fn read_u8(&mut self) -> Result<u8, ReadError>;
+  fn read_u16(&mut self) -> Result<u16, ReadError>;
+  fn read_u32(&mut self) -> Result<u32, ReadError>;
+  fn read_u64(&mut self) -> Result<u64, ReadError>;
+  fn read_usize(&mut self) -> Result<usize, ReadError>;
+  
+  fn read_i8(&mut self) -> Result<i8, ReadError>;
+  fn read_i16(&mut self) -> Result<i16, ReadError>;
+  fn read_i32(&mut self) -> Result<i32, ReadError>;
+  fn read_i64(&mut self) -> Result<i64, ReadError>;
+  fn read_isize(&mut self) -> Result<isize, ReadError>;
+  
+  fn read_f32(&mut self) -> Result<f32, ReadError>;
+  fn read_f64(&mut self) -> Result<f64, ReadError>;
+  fn read_str(&mut self) -> Result<&'reader str, ReadError>;
+  fn read_cstr(&mut self) -> Result<&'reader CStr, ReadError>;
+  fn read_bool(&mut self) -> Result<bool, ReadError>;
+  
+  fn read_u8_slice(&mut self) -> Result<&'reader [u8], ReadError>;
+  fn read_u16_slice(&mut self) -> Result<SliceReadResult<'reader, u16>, ReadError>;
+  fn read_u32_slice(&mut self) -> Result<SliceReadResult<'reader, u32>, ReadError>;
+  fn read_u64_slice(&mut self) -> Result<SliceReadResult<'reader, u64>, ReadError>;
+  fn read_usize_slice(&mut self) -> Result<SliceReadResult<'reader, usize>, ReadError>;
+  
+  fn read_i8_slice(&mut self) -> Result<&'reader [i8], ReadError>;
+  fn read_i16_slice(&mut self) -> Result<SliceReadResult<'reader, i16>, ReadError>;
+  fn read_i32_slice(&mut self) -> Result<SliceReadResult<'reader, i32>, ReadError>;
+  fn read_i64_slice(&mut self) -> Result<SliceReadResult<'reader, i64>, ReadError>;
+  fn read_isize_slice(&mut self) -> Result<SliceReadResult<'reader, isize>, ReadError>;
+  
+  fn read_f32_slice(&mut self) -> Result<SliceReadResult<'reader, f32>, ReadError>;
+  fn read_f64_slice(&mut self) -> Result<SliceReadResult<'reader, f64>, ReadError>;
   
   // Result Vec for these two will not be cleared, it is appened to it
   // BUT: on error, only partially is pushed into the result
-  fn read_str_slice(&mut self, result: &mut Vec<&'reader str>) -> Result<(), ()>;
-  fn read_cstr_slice(&mut self, result: &mut Vec<&'reader CStr>) -> Result<(), ()>;
-  fn read_bool_slice(&mut self) -> Result<&'reader [bool], ()>;
+  fn read_str_slice(&mut self, result: &mut Vec<&'reader str>) -> Result<(), ReadError>;
+  fn read_cstr_slice(&mut self, result: &mut Vec<&'reader CStr>) -> Result<(), ReadError>;
+  fn read_bool_slice(&mut self) -> Result<SliceReadResult<'reader, bool>, ReadError>;
 }
 
 pub trait InnerWriter<'writer> {
   fn write(&mut self, bytes: &[u8]);
   fn get_current_offset(&self) -> usize;
   
-  // The implementation of WriteFormat MUST NOT use this,
-  // this exists so Writer can extract the underlying data
-  // buffer once done using
+  // Writes buffer object, same as Writer::write_buffer. For
+  // formats which keep data outside of the data buffer
+  fn write_buffer(&mut self, data: &[u8], parent: Option<(BufferHandle, usize)>) -> io::Result<BufferHandle>;
+  
+  // For writes the format can't do, only first error is kept
+  fn set_error(&mut self, error: WriteError);
+  
+  // Writes zeros until offset is multiple of alignment
+  fn pad_to_alignment(&mut self, alignment: usize) {
+    const ZEROS: [u8; 8] = [0; 8];
+    let offset = self.get_current_offset();
+    let mut padding = offset.next_multiple_of(alignment) - offset;
+    while padding > 0 {
+      let len = padding.min(ZEROS.len());
+      self.write(&ZEROS[..len]);
+      padding -= len;
+    }
+  }
+  
+  // The implementation of WriteFormat MUST NOT use these,
+  // these exists so Writer can extract the underlying data
+  // buffer, offsets and buffers once done using
   fn get_data_buffer_mut(&mut self) -> &mut Vec<u8>;
+  fn get_offsets_mut(&mut self) -> &mut Vec<BinderUsize>;
+  fn get_buffers_mut(&mut self) -> &mut Vec<Arc<[u8]>>;
+  fn get_error_mut(&mut self) -> &mut Option<WriteError>;
 }
 
 pub trait WriteFormat<'writer> {
@@ -88,6 +265,13 @@ pub trait WriteFormat<'writer> {
   fn get_writer_mut(&mut self) -> &mut Box<dyn InnerWriter<'writer> + 'writer>;
   fn get_writer(&self) -> &Box<dyn InnerWriter<'writer> + 'writer>;
   
+  // Called before every binder object, so the object is at
+  // offset kernel accepts. Formats which keep the data aligned
+  // enough don't need to override it
+  fn align_for_object(&mut self) {
+    self.get_writer_mut().pad_to_alignment(Type::alignment_in_buffer_needed());
+  }
+  
   fn write_u8(&mut self, data: u8);
   fn write_u16(&mut self, data: u16);
   fn write_u32(&mut self, data: u32);
@@ -0,0 +1,687 @@
+// Android's Parcel format, as AOSP's libbinder and Java's
+// android.os.Parcel writes it. Everything is padded to multiple
+// of 4 bytes, types smaller than that are written as i32 and
+// lengths are i32 where -1 mean null
+//
+// The str is AOSP's String8 (UTF-8) so it can be borrowed from
+// the packet, String16 which is what AIDL's String uses is done
+// through write_string16/read_string16
+
+use std::ffi::CStr;
+
+use crate::formats::{InnerReader, InnerWriter, ReadError, ReadFormat, SliceReadResult, WriteError, WriteFormat, cast_or_copy};
+
+const ALIGNMENT: usize = size_of::<u32>();
+const NULL_LENGTH: i32 = -1;
+
+// Headers of interface token, as B_PACK_CHARS packs it
+pub const HEADER_SYSTEM: i32 = i32::from_be_bytes(*b"SYST");
+pub const HEADER_VENDOR: i32 = i32::from_be_bytes(*b"VNDR");
+pub const HEADER_RECOVERY: i32 = i32::from_be_bytes(*b"RECO");
+
+// Always set by AOSP when writing strict mode policy
+pub const STRICT_MODE_PENALTY_GATHER: i32 = 1 << 31;
+pub const UNSET_WORK_SOURCE: i32 = -1;
+
+// Written at start of every transaction to AOSP services, for
+// example with descriptor "a" on little endian it is
+//
+// 00 00 00 80  ff ff ff ff  54 53 59 53  01 00 00 00  61 00 00 00
+// strict mode  work source  'SYST'       length       'a' nul
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterfaceToken {
+  // Written as is, AOSP ORs STRICT_MODE_PENALTY_GATHER into it
+  pub strict_mode_policy: i32,
+  pub work_source_uid: i32,
+  pub header: i32,
+  pub descriptor: String
+}
+
+impl InterfaceToken {
+  // Same as what AOSP writes for system process without
+  // any strict mode policy and work source
+  pub fn new(descriptor: &str) -> Self {
+    Self {
+      strict_mode_policy: STRICT_MODE_PENALTY_GATHER,
+      work_source_uid: UNSET_WORK_SOURCE,
+      header: HEADER_SYSTEM,
+      descriptor: descriptor.to_string()
+    }
+  }
+}
+
+fn padding_for(len: usize) -> usize {
+  len.next_multiple_of(ALIGNMENT) - len
+}
+
+pub struct ParcelFormat<'writer> {
+  writer: Option<Box<dyn InnerWriter<'writer> + 'writer>>
+}
+
+impl<'writer> ParcelFormat<'writer> {
+  pub fn new() -> Self {
+    Self {
+      writer: None
+    }
+  }
+  
+  fn write_padded(&mut self, bytes: &[u8]) {
+    let writer = self.get_writer_mut();
+    writer.write(bytes);
+    writer.write(&[0; ALIGNMENT][..padding_for(bytes.len())]);
+  }
+  
+  // Parcel lengths are i32, longer data fails the packet
+  fn write_length(&mut self, length: usize) {
+    let Ok(length) = i32::try_from(length) else {
+      let offset = self.get_writer().get_current_offset();
+      self.get_writer_mut().set_error(WriteError::LengthTooLarge { offset });
+      self.write_null();
+      return;
+    };
+    self.write_i32(length);
+  }
+  
+  // Written in place of any length prefixed data, like
+  // strings and slices to mark it as null
+  pub fn write_null(&mut self) {
+    self.write_i32(NULL_LENGTH);
+  }
+  
+  // Length is in UTF-16 code units, and nul terminated
+  pub fn write_string16(&mut self, data: Option<&str>) {
+    let Some(data) = data else {
+      self.write_null();
+      return;
+    };
+    
+    let mut units = data.encode_utf16().collect::<Vec<u16>>();
+    self.write_length(units.len());
+    units.push(0);
+    self.write_padded(bytemuck::cast_slice(&units));
+  }
+  
+  pub fn write_interface_token(&mut self, token: &InterfaceToken) {
+    self.write_i32(token.strict_mode_policy);
+    self.write_i32(token.work_source_uid);
+    self.write_i32(token.header);
+    self.write_string16(Some(&token.descriptor));
+  }
+}
+
+impl<'writer> WriteFormat<'writer> for ParcelFormat<'writer> {
+  fn set_writer(&mut self, writer: Box<dyn InnerWriter<'writer> + 'writer>) {
+    self.writer = Some(writer);
+  }
+  
+  fn get_writer_mut(&mut self) -> &mut Box<dyn InnerWriter<'writer> + 'writer> {
+    self.writer.as_mut().unwrap()
+  }
+  
+  fn get_writer(&self) -> &Box<dyn InnerWriter<'writer> + 'writer> {
+    self.writer.as_ref().unwrap()
+  }
+  
+  // Types smaller than 4 bytes are widened to i32
+  fn write_u8(&mut self, data: u8) {
+    self.write_u32(data as u32);
+  }
+  
+  fn write_u16(&mut self, data: u16) {
+    self.write_u32(data as u32);
+  }
+  
+  fn write_u32(&mut self, data: u32) {
+    self.get_writer_mut().write(&data.to_ne_bytes());
+  }
+  
+  fn write_u64(&mut self, data: u64) {
+    self.get_writer_mut().write(&data.to_ne_bytes());
+  }
+  
+  fn write_usize(&mut self, data: usize) {
+    self.write_u64(data as u64);
+  }
+  
+  fn write_i8(&mut self, data: i8) {
+    self.write_i32(data as i32);
+  }
+  
+  fn write_i16(&mut self, data: i16) {
+    self.write_i32(data as i32);
+  }
+  
+  fn write_i32(&mut self, data: i32) {
+    self.get_writer_mut().write(&data.to_ne_bytes());
+  }
+  
+  fn write_i64(&mut self, data: i64) {
+    self.get_writer_mut().write(&data.to_ne_bytes());
+  }
+  
+  fn write_isize(&mut self, data: isize) {
+    self.write_i64(data as i64);
+  }
+  
+  fn write_f32(&mut self, data: f32) {
+    self.get_writer_mut().write(&data.to_ne_bytes());
+  }
+  
+  fn write_f64(&mut self, data: f64) {
+    self.get_writer_mut().write(&data.to_ne_bytes());
+  }
+  
+  fn write_bool(&mut self, data: bool) {
+    self.write_i32(data as i32);
+  }
+  
+  // String8, length is in bytes and nul terminated
+  fn write_str(&mut self, data: &str) {
+    self.write_length(data.len());
+    let writer = self.get_writer_mut();
+    writer.write(data.as_bytes());
+    writer.write(&[0; ALIGNMENT][..1 + padding_for(data.len() + 1)]);
+  }
+  
+  // No length, just nul terminated
+  fn write_cstr(&mut self, data: &CStr) {
+    self.write_padded(data.to_bytes_with_nul());
+  }
+  
+  // Bytes are packed, unlike single byte
+  fn write_u8_slice(&mut self, data: &[u8]) {
+    self.write_length(data.len());
+    self.write_padded(data);
+  }
+  
+  fn write_u16_slice(&mut self, data: &[u16]) {
+    self.write_length(data.len());
+    for &x in data {
+      self.write_u16(x);
+    }
+  }
+  
+  fn write_u32_slice(&mut self, data: &[u32]) {
+    self.write_length(data.len());
+    self.get_writer_mut().write(bytemuck::cast_slice(data));
+  }
+  
+  fn write_u64_slice(&mut self, data: &[u64]) {
+    self.write_length(data.len());
+    self.get_writer_mut().write(bytemuck::cast_slice(data));
+  }
+  
+  fn write_usize_slice(&mut self, data: &[usize]) {
+    self.write_length(data.len());
+    for &x in data {
+      self.write_usize(x);
+    }
+  }
+  
+  fn write_i8_slice(&mut self, data: &[i8]) {
+    self.write_length(data.len());
+    self.write_padded(bytemuck::cast_slice(data));
+  }
+  
+  fn write_i16_slice(&mut self, data: &[i16]) {
+    self.write_length(data.len());
+    for &x in data {
+      self.write_i16(x);
+    }
+  }
+  
+  fn write_i32_slice(&mut self, data: &[i32]) {
+    self.write_length(data.len());
+    self.get_writer_mut().write(bytemuck::cast_slice(data));
+  }
+  
+  fn write_i64_slice(&mut self, data: &[i64]) {
+    self.write_length(data.len());
+    self.get_writer_mut().write(bytemuck::cast_slice(data));
+  }
+  
+  fn write_isize_slice(&mut self, data: &[isize]) {
+    self.write_length(data.len());
+    for &x in data {
+      self.write_isize(x);
+    }
+  }
+  
+  fn write_f32_slice(&mut self, data: &[f32]) {
+    self.write_length(data.len());
+    self.get_writer_mut().write(bytemuck::cast_slice(data));
+  }
+  
+  fn write_f64_slice(&mut self, data: &[f64]) {
+    self.write_length(data.len());
+    self.get_writer_mut().write(bytemuck::cast_slice(data));
+  }
+  
+  fn write_str_slice(&mut self, data: &[&str]) {
+    self.write_length(data.len());
+    for string in data {
+      self.write_str(string);
+    }
+  }
+  
+  fn write_cstr_slice(&mut self, data: &[&CStr]) {
+    self.write_length(data.len());
+    for string in data {
+      self.write_cstr(string);
+    }
+  }
+  
+  // Each bool is i32, not packed like bytes
+  fn write_bool_slice(&mut self, data: &[bool]) {
+    self.write_length(data.len());
+    for &x in data {
+      self.write_bool(x);
+    }
+  }
+}
+
+pub struct ParcelFormatReader<'reader> {
+  reader: Option<Box<dyn InnerReader<'reader>>>
+}
+
+impl Clone for ParcelFormatReader<'_> {
+  fn clone(&self) -> Self {
+    Self {
+      reader: self.reader.as_ref().map(|x| x.clone_reader())
+    }
+  }
+}
+
+impl<'reader> ParcelFormatReader<'reader> {
+  pub fn new() -> Self {
+    Self {
+      reader: None
+    }
+  }
+  
+  // Reads the bytes and skips padding after it
+  fn read_padded(&mut self, len: usize) -> Result<&'reader [u8], ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    let padded_len = len.checked_next_multiple_of(ALIGNMENT)
+      .ok_or(ReadError::LengthOverflow { offset })?;
+    Ok(&self.get_reader_mut().read(padded_len)?[..len])
+  }
+  
+  fn read_length(&mut self) -> Result<Option<usize>, ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    match self.read_i32()? {
+      NULL_LENGTH => Ok(None),
+      length => usize::try_from(length)
+        .map(Some)
+        .map_err(|_| ReadError::InvalidValue { offset })
+    }
+  }
+  
+  // For things which can't be null
+  fn read_nonnull_length(&mut self) -> Result<usize, ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    self.read_length()?
+      .ok_or(ReadError::InvalidValue { offset })
+  }
+  
+  fn read_size_of<T>(&mut self, length: usize) -> Result<&'reader [u8], ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    let size = length.checked_mul(size_of::<T>())
+      .ok_or(ReadError::LengthOverflow { offset })?;
+    self.read_padded(size)
+  }
+  
+  // Consumes the null marker if there is one, if not
+  // nothing is consumed and the value can be read
+  pub fn read_null(&mut self) -> Result<bool, ReadError> {
+    let raw = self.get_reader().peek(size_of::<i32>(), 0)?;
+    if i32::from_ne_bytes(raw.try_into().unwrap()) != NULL_LENGTH {
+      return Ok(false);
+    }
+    
+    self.read_i32()?;
+    Ok(true)
+  }
+  
+  pub fn read_string16(&mut self) -> Result<Option<String>, ReadError> {
+    let Some(length) = self.read_length()? else {
+      return Ok(None);
+    };
+    
+    let offset = self.get_reader().get_current_offset();
+    let with_nul = length.checked_add(1)
+      .ok_or(ReadError::InvalidValue { offset })?;
+    let bytes = self.read_size_of::<u16>(with_nul)?;
+    let (string, nul) = bytes.split_at(length * size_of::<u16>());
+    
+    if nul != [0, 0] {
+      return Err(ReadError::InvalidValue { offset: offset + string.len() });
+    }
+    
+    let units = string.chunks_exact(size_of::<u16>())
+      .map(|x| u16::from_ne_bytes(x.try_into().unwrap()));
+    char::decode_utf16(units)
+      .collect::<Result<String, _>>()
+      .map(Some)
+      .map_err(|_| ReadError::InvalidValue { offset })
+  }
+  
+  pub fn read_interface_token(&mut self) -> Result<InterfaceToken, ReadError> {
+    let strict_mode_policy = self.read_i32()?;
+    let work_source_uid = self.read_i32()?;
+    let header = self.read_i32()?;
+    
+    let offset = self.get_reader().get_current_offset();
+    let descriptor = self.read_string16()?
+      .ok_or(ReadError::InvalidValue { offset })?;
+    
+    Ok(InterfaceToken {
+      strict_mode_policy,
+      work_source_uid,
+      header,
+      descriptor
+    })
+  }
+}
+
+// Each element read one by one, for types which aren't
+// written as is
+macro_rules! impl_slice_each {
+  ($name:ident, $read:ident, $type:ty) => {
+    fn $name(&mut self) -> Result<SliceReadResult<'reader, $type>, ReadError> {
+      let length = self.read_nonnull_length()?;
+      (0..length)
+        .map(|_| self.$read())
+        .collect::<Result<Box<[$type]>, _>>()
+        .map(SliceReadResult::Owned)
+    }
+  };
+}
+
+macro_rules! impl_slice {
+  ($name:ident, $type:ty) => {
+    fn $name(&mut self) -> Result<SliceReadResult<'reader, $type>, ReadError> {
+      let length = self.read_nonnull_length()?;
+      // Parcel only aligns to 4 bytes, so 8 bytes types
+      // might not be aligned and need a copy
+      Ok(cast_or_copy(self.read_size_of::<$type>(length)?))
+    }
+  };
+}
+
+impl<'reader> ReadFormat<'reader> for ParcelFormatReader<'reader> {
+  fn get_reader_mut(&mut self) -> &mut Box<dyn InnerReader<'reader>> {
+    self.reader.as_mut().unwrap()
+  }
+  
+  fn set_reader(&mut self, reader: Box<dyn InnerReader<'reader>>) {
+    self.reader = Some(reader);
+  }
+  
+  fn get_reader(&self) -> &Box<dyn InnerReader<'reader>> {
+    self.reader.as_ref().unwrap()
+  }
+  
+  // Like AOSP, widened types are truncated back
+  fn read_u8(&mut self) -> Result<u8, ReadError> {
+    self.read_u32().map(|x| x as u8)
+  }
+  
+  fn read_u16(&mut self) -> Result<u16, ReadError> {
+    self.read_u32().map(|x| x as u16)
+  }
+  
+  fn read_u32(&mut self) -> Result<u32, ReadError> {
+    self.get_reader_mut().read(4)
+      .map(|x| u32::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  fn read_u64(&mut self) -> Result<u64, ReadError> {
+    self.get_reader_mut().read(8)
+      .map(|x| u64::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  fn read_usize(&mut self) -> Result<usize, ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    usize::try_from(self.read_u64()?)
+      .map_err(|_| ReadError::InvalidValue { offset })
+  }
+  
+  fn read_i8(&mut self) -> Result<i8, ReadError> {
+    self.read_i32().map(|x| x as i8)
+  }
+  
+  fn read_i16(&mut self) -> Result<i16, ReadError> {
+    self.read_i32().map(|x| x as i16)
+  }
+  
+  fn read_i32(&mut self) -> Result<i32, ReadError> {
+    self.get_reader_mut().read(4)
+      .map(|x| i32::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  fn read_i64(&mut self) -> Result<i64, ReadError> {
+    self.get_reader_mut().read(8)
+      .map(|x| i64::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  fn read_isize(&mut self) -> Result<isize, ReadError> {
+    let offset = self.get_reader().get_current_offset();
+    isize::try_from(self.read_i64()?)
+      .map_err(|_| ReadError::InvalidValue { offset })
+  }
+  
+  fn read_f32(&mut self) -> Result<f32, ReadError> {
+    self.get_reader_mut().read(size_of::<f32>())
+      .map(|x| f32::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  fn read_f64(&mut self) -> Result<f64, ReadError> {
+    self.get_reader_mut().read(size_of::<f64>())
+      .map(|x| f64::from_ne_bytes(x.try_into().unwrap()))
+  }
+  
+  // AOSP treats anything non zero as true
+  fn read_bool(&mut self) -> Result<bool, ReadError> {
+    self.read_i32().map(|x| x != 0)
+  }
+  
+  fn read_cstr(&mut self) -> Result<&'reader CStr, ReadError> {
+    // Find the length of CString
+    let mut length = 0;
+    loop {
+      let current = self.get_reader_mut().peek(1, /* length is also offset */ length)?;
+      if current[0] == 0 {
+        break;
+      }
+      length += 1;
+    }
+    
+    // Include the nul
+    Ok(CStr::from_bytes_with_nul(self.read_padded(length + 1)?).unwrap())
+  }
+  
+  fn read_str(&mut self) -> Result<&'reader str, ReadError> {
+    let length = self.read_nonnull_length()?;
+    let offset = self.get_reader().get_current_offset();
+    let with_nul = length.checked_add(1)
+      .ok_or(ReadError::InvalidValue { offset })?;
+    let bytes = self.read_padded(with_nul)?;
+    
+    if bytes[length] != 0 {
+      return Err(ReadError::InvalidValue { offset: offset + length });
+    }
+    
+    str::from_utf8(&bytes[..length])
+      .map_err(|_| ReadError::InvalidUtf8 { offset })
+  }
+  
+  fn read_u8_slice(&mut self) -> Result<&'reader [u8], ReadError> {
+    let length = self.read_nonnull_length()?;
+    self.read_padded(length)
+  }
+  impl_slice_each!(read_u16_slice, read_u16, u16);
+  impl_slice!(read_u32_slice, u32);
+  impl_slice!(read_u64_slice, u64);
+  impl_slice_each!(read_usize_slice, read_usize, usize);
+  
+  fn read_i8_slice(&mut self) -> Result<&'reader [i8], ReadError> {
+    Ok(bytemuck::cast_slice(self.read_u8_slice()?))
+  }
+  impl_slice_each!(read_i16_slice, read_i16, i16);
+  impl_slice!(read_i32_slice, i32);
+  impl_slice!(read_i64_slice, i64);
+  impl_slice_each!(read_isize_slice, read_isize, isize);
+  
+  impl_slice!(read_f32_slice, f32);
+  impl_slice!(read_f64_slice, f64);
+  
+  fn read_str_slice(&mut self, result: &mut Vec<&'reader str>) -> Result<(), ReadError> {
+    let length = self.read_nonnull_length()?;
+    for _ in 0..length {
+      result.push(self.read_str()?);
+    }
+    
+    Ok(())
+  }
+  
+  fn read_cstr_slice(&mut self, result: &mut Vec<&'reader CStr>) -> Result<(), ReadError> {
+    let length = self.read_nonnull_length()?;
+    for _ in 0..length {
+      result.push(self.read_cstr()?);
+    }
+    
+    Ok(())
+  }
+  
+  impl_slice_each!(read_bool_slice, read_bool, bool);
+}
+
+// Expected bytes are what AOSP's Parcel writes on little endian
+#[cfg(all(test, target_endian = "little"))]
+mod tests {
+  use crate::{formats::ReadError, packet::{test_builder, test_packet}};
+  
+  use super::*;
+  
+  // Writes then checks data of the packet is exactly the expected
+  macro_rules! write_expect {
+    ($expected:expr, |$writer:ident| $body:expr) => {{
+      let packet = test_packet!(ParcelFormat::new(), |$writer| $body);
+      assert_eq!(packet.get_transaction().get_common().data_slice, $expected);
+      packet
+    }};
+  }
+  
+  #[test]
+  fn interface_token() {
+    let expected = [
+      0x00, 0x00, 0x00, 0x80,
+      0xff, 0xff, 0xff, 0xff,
+      0x54, 0x53, 0x59, 0x53,
+      0x03, 0x00, 0x00, 0x00,
+      0x66, 0x00, 0x6f, 0x00, 0x6f, 0x00, 0x00, 0x00
+    ];
+    let token = InterfaceToken::new("foo");
+    let packet = write_expect!(&expected, |writer| writer.get_format_mut().write_interface_token(&token));
+    
+    let mut reader = packet.reader(ParcelFormatReader::new());
+    assert_eq!(reader.read_with(|x| x.read_interface_token()), Ok(token));
+  }
+  
+  #[test]
+  fn string16() {
+    // Length excludes the nul, which then needs padding
+    let expected = [
+      0x02, 0x00, 0x00, 0x00,
+      0x61, 0x00, 0x62, 0x00, 0x00, 0x00, 0x00, 0x00,
+      0xff, 0xff, 0xff, 0xff
+    ];
+    let packet = write_expect!(&expected, |writer| {
+      writer.get_format_mut().write_string16(Some("ab"));
+      writer.get_format_mut().write_string16(None);
+    });
+    
+    let mut reader = packet.reader(ParcelFormatReader::new());
+    assert_eq!(reader.read_with(|x| x.read_string16()), Ok(Some("ab".to_string())));
+    assert_eq!(reader.read_with(|x| x.read_string16()), Ok(None));
+  }
+  
+  #[test]
+  fn string8() {
+    let expected = [
+      0x03, 0x00, 0x00, 0x00,
+      0x61, 0x62, 0x63, 0x00,
+      0x04, 0x00, 0x00, 0x00,
+      0x61, 0x62, 0x63, 0x64, 0x00, 0x00, 0x00, 0x00
+    ];
+    let packet = write_expect!(&expected, |writer| {
+      writer.write_str("abc").write_str("abcd");
+    });
+    
+    let mut reader = packet.reader(ParcelFormatReader::new());
+    assert_eq!(reader.read_str(), Ok("abc"));
+    assert_eq!(reader.read_str(), Ok("abcd"));
+  }
+  
+  #[test]
+  fn null() {
+    let expected = [0xff, 0xff, 0xff, 0xff];
+    let packet = write_expect!(&expected, |writer| writer.get_format_mut().write_null());
+    
+    // Nullable reads take it, others reject it
+    let mut reader = packet.reader(ParcelFormatReader::new());
+    assert_eq!(reader.read_u8_slice(), Err(ReadError::InvalidValue { offset: 0 }));
+    assert_eq!(reader.read_str(), Err(ReadError::InvalidValue { offset: 0 }));
+    assert_eq!(reader.read_with(|x| x.read_null()), Ok(true));
+    assert_eq!(reader.get_current_offset(), 4);
+  }
+  
+  #[test]
+  fn byte_vector() {
+    // Bytes are packed then padded, unlike single byte
+    let expected = [
+      0x05, 0x00, 0x00, 0x00,
+      0x01, 0x02, 0x03, 0x04, 0x05, 0x00, 0x00, 0x00,
+      0xab, 0x00, 0x00, 0x00
+    ];
+    let packet = write_expect!(&expected, |writer| {
+      writer.write_u8_slice(&[1, 2, 3, 4, 5]).write_u8(0xab);
+    });
+    
+    let mut reader = packet.reader(ParcelFormatReader::new());
+    assert_eq!(reader.read_u8_slice(), Ok([1, 2, 3, 4, 5].as_slice()));
+    assert_eq!(reader.read_u8(), Ok(0xab));
+  }
+  
+  #[test]
+  fn padding() {
+    let expected = [
+      0x61, 0x62, 0x63, 0x64, 0x65, 0x00, 0x00, 0x00,
+      0x01, 0x00, 0x00, 0x00
+    ];
+    let packet = write_expect!(&expected, |writer| {
+      writer.write_cstr(c"abcde").write_bool(true);
+    });
+    
+    let mut reader = packet.reader(ParcelFormatReader::new());
+    assert_eq!(reader.read_cstr(), Ok(c"abcde"));
+    assert_eq!(reader.read_bool(), Ok(true));
+  }
+  
+  // Fails the packet instead of writing a wrong length
+  #[test]
+  fn length_too_large() {
+    let mut builder = test_builder();
+    let mut writer = builder.writer(ParcelFormat::new());
+    writer.write_u32(1);
+    writer.get_format_mut().write_length(usize::MAX);
+    drop(writer);
+    
+    assert_eq!(builder.build().err(), Some(WriteError::LengthTooLarge { offset: 4 }));
+    
+    // Builder is reset, so it can be used again
+    builder.set_code(1);
+    assert!(builder.build().is_ok());
+  }
+}
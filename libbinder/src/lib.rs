@@ -5,3 +5,5 @@ pub mod command_buffer;
 pub mod return_buffer;
 pub mod formats;
 
+#[cfg(feature = "serde")]
+pub mod serialize;
\ No newline at end of file
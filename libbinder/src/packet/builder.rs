@@ -1,9 +1,9 @@
-use std::{mem, os::fd::BorrowedFd, slice};
+use std::{mem, os::fd::{BorrowedFd, OwnedFd}, slice, sync::Arc};
 
 use enumflags2::BitFlags;
-use libbinder_raw::{object::reference::{ObjectRef, ObjectRefRemote}, transaction::{Transaction, TransactionDataCommon, TransactionFlag, TransactionNotKernelMananged}};
+use libbinder_raw::{BinderUsize, object::reference::{ObjectRef, ObjectRefRemote}, transaction::{Transaction, TransactionDataCommon, TransactionFlag, TransactionNotKernelMananged}};
 
-use crate::{formats::WriteFormat, packet::{Packet, writer::Writer}};
+use crate::{formats::{WriteError, WriteFormat}, packet::{Packet, writer::Writer}};
 
 #[derive(Clone)]
 pub struct PacketBuilder<'binder> {
@@ -11,7 +11,19 @@ pub struct PacketBuilder<'binder> {
   pub(super) binder_dev: BorrowedFd<'binder>,
   pub(super) flags: Option<BitFlags<TransactionFlag>>,
   pub(super) data_buffer: Vec<u8>,
-  pub(super) offsets_buffer: Vec<usize>
+  pub(super) offsets_buffer: Vec<BinderUsize>,
+  
+  // Keeps the fds written into the packet alive
+  // until kernel copies them
+  pub(super) fds: Vec<Arc<OwnedFd>>,
+  
+  // Out of line buffers, their addresses are
+  // written inside the buffer objects so they
+  // must not move
+  pub(super) buffers: Vec<Arc<[u8]>>,
+  
+  // First write which couldn't be done
+  pub(super) error: Option<WriteError>
 }
 
 impl<'binder> PacketBuilder<'binder> {
@@ -21,6 +33,9 @@ impl<'binder> PacketBuilder<'binder> {
       flags: None,
       data_buffer: Vec::new(),
       offsets_buffer: Vec::new(),
+      fds: Vec::new(),
+      buffers: Vec::new(),
+      error: None,
       binder_dev: binder_dev,
     }
   }
@@ -38,6 +53,9 @@ impl<'binder> PacketBuilder<'binder> {
   pub fn clear(&mut self) {
     self.data_buffer.clear();
     self.offsets_buffer.clear();
+    self.fds.clear();
+    self.buffers.clear();
+    self.error = None;
     self.flags = None;
     self.code = None;
   }
@@ -53,9 +71,15 @@ impl<'binder> PacketBuilder<'binder> {
   }
   
   // After build the builder is 'reset'
-  // to state where it starts
-  pub fn build(&mut self) -> Packet<'binder> {
-    Packet {
+  // to state where it starts, also when
+  // a write failed
+  pub fn build(&mut self) -> Result<Packet<'binder>, WriteError> {
+    if let Some(error) = self.error.take() {
+      self.clear();
+      return Err(error);
+    }
+    
+    Ok(Packet {
       binder_dev: self.binder_dev,
       transaction: Transaction::NotKernelManaged(TransactionNotKernelMananged {
         data: TransactionDataCommon {
@@ -63,12 +87,16 @@ impl<'binder> PacketBuilder<'binder> {
           flags: self.flags.take().unwrap_or(BitFlags::empty()),
           target: ObjectRef::Remote(ObjectRefRemote { data_handle: 0, extra_local_data: 0 }),
           data_slice: unsafe { slice::from_raw_parts(self.data_buffer.as_ptr(), self.data_buffer.len()) },
-          offsets: unsafe { slice::from_raw_parts(self.offsets_buffer.as_ptr(), self.offsets_buffer.len()) }
+          offsets: unsafe { slice::from_raw_parts(self.offsets_buffer.as_ptr(), self.offsets_buffer.len()) },
+          sender_pid: 0,
+          sender_uid: 0
         }
       }),
       offset_buffer: mem::replace(&mut self.offsets_buffer, Vec::new()),
+      fds: mem::replace(&mut self.fds, Vec::new()),
+      buffers: mem::replace(&mut self.buffers, Vec::new()),
       data_buffer: mem::replace(&mut self.data_buffer, Vec::new())
-    }
+    })
   }
 }
 
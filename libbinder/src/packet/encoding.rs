@@ -0,0 +1,216 @@
+// Types which can be written into and read from packet, what
+// #[derive(BinderWrite, BinderRead)] implements. PacketWriter and
+// PacketReader are implemented by Writer and Reader of this crate
+// and libbinder-runtime's so same type works with both
+//
+// Things the formats don't have are done as
+//
+// Vec: u32 length then each element in place
+// Option: bool then the value if there is
+// enum: u32 discriminant then the variant's fields
+// struct: each field in order
+//
+// While &[u8] uses the format's own byte slice
+//
+// Vec doesn't use format's slice length because formats only
+// have it as part of their own slices, HwParcel's isn't even
+// in place (it's in hidl_vec buffer object). Fixed u32 keeps
+// elements right after it in every format, like Option's bool
+
+use std::ffi::{CStr, CString};
+
+use crate::{formats::{ReadFormat, WriteError, WriteFormat}, packet::{reader::Reader, writer::Writer}};
+
+// Derived code refers to it from here
+pub use crate::formats::ReadError;
+
+#[cfg(feature = "derive")]
+pub use libbinder_derive::{BinderRead, BinderWrite};
+
+macro_rules! packet_writer {
+  ($($name:ident: $type:ty),*) => {
+    pub trait PacketWriter {
+      fn get_current_offset(&mut self) -> usize;
+      fn set_error(&mut self, error: WriteError);
+      $(fn $name(&mut self, data: $type);)*
+    }
+    
+    impl<'packet, Format: WriteFormat<'packet>> PacketWriter for Writer<'packet, '_, Format> {
+      fn get_current_offset(&mut self) -> usize {
+        Writer::get_current_offset(self)
+      }
+      
+      fn set_error(&mut self, error: WriteError) {
+        Writer::set_error(self, error);
+      }
+      
+      $(
+        fn $name(&mut self, data: $type) {
+          Writer::$name(self, data);
+        }
+      )*
+    }
+  };
+}
+
+macro_rules! packet_reader {
+  ($($name:ident: $type:ty),*) => {
+    pub trait PacketReader<'packet> {
+      fn get_current_offset(&self) -> usize;
+      $(fn $name(&mut self) -> Result<$type, ReadError>;)*
+    }
+    
+    impl<'packet, Format: ReadFormat<'packet>> PacketReader<'packet> for Reader<'packet, '_, Format> {
+      fn get_current_offset(&self) -> usize {
+        Reader::get_current_offset(self)
+      }
+      
+      $(
+        fn $name(&mut self) -> Result<$type, ReadError> {
+          Reader::$name(self)
+        }
+      )*
+    }
+  };
+}
+
+packet_writer!(
+  write_u8: u8, write_u16: u16, write_u32: u32, write_u64: u64, write_usize: usize,
+  write_i8: i8, write_i16: i16, write_i32: i32, write_i64: i64, write_isize: isize,
+  write_f32: f32, write_f64: f64, write_bool: bool,
+  write_str: &str, write_cstr: &CStr, write_u8_slice: &[u8]
+);
+
+packet_reader!(
+  read_u8: u8, read_u16: u16, read_u32: u32, read_u64: u64, read_usize: usize,
+  read_i8: i8, read_i16: i16, read_i32: i32, read_i64: i64, read_isize: isize,
+  read_f32: f32, read_f64: f64, read_bool: bool,
+  read_str: &'packet str, read_cstr: &'packet CStr, read_u8_slice: &'packet [u8]
+);
+
+// The 'packet on self is there so binder object references
+// can be written, which must outlive the packet
+pub trait BinderWrite<'packet, W: PacketWriter + ?Sized> {
+  fn write_to(&'packet self, writer: &mut W);
+}
+
+// Reader only rolls back the failing read, so on error
+// part of the value might already been consumed
+pub trait BinderRead<'packet, R: PacketReader<'packet> + ?Sized>: Sized {
+  fn read_from(reader: &mut R) -> Result<Self, ReadError>;
+}
+
+macro_rules! impl_primitive {
+  ($type:ty, $write:ident, $read:ident) => {
+    impl<'packet, W: PacketWriter + ?Sized> BinderWrite<'packet, W> for $type {
+      fn write_to(&'packet self, writer: &mut W) {
+        writer.$write(*self);
+      }
+    }
+    
+    impl<'packet, R: PacketReader<'packet> + ?Sized> BinderRead<'packet, R> for $type {
+      fn read_from(reader: &mut R) -> Result<Self, ReadError> {
+        reader.$read()
+      }
+    }
+  };
+}
+
+impl_primitive!(u8, write_u8, read_u8);
+impl_primitive!(u16, write_u16, read_u16);
+impl_primitive!(u32, write_u32, read_u32);
+impl_primitive!(u64, write_u64, read_u64);
+impl_primitive!(usize, write_usize, read_usize);
+
+impl_primitive!(i8, write_i8, read_i8);
+impl_primitive!(i16, write_i16, read_i16);
+impl_primitive!(i32, write_i32, read_i32);
+impl_primitive!(i64, write_i64, read_i64);
+impl_primitive!(isize, write_isize, read_isize);
+
+impl_primitive!(f32, write_f32, read_f32);
+impl_primitive!(f64, write_f64, read_f64);
+impl_primitive!(bool, write_bool, read_bool);
+
+impl_primitive!(&'packet str, write_str, read_str);
+impl_primitive!(&'packet CStr, write_cstr, read_cstr);
+impl_primitive!(&'packet [u8], write_u8_slice, read_u8_slice);
+
+impl<'packet, W: PacketWriter + ?Sized> BinderWrite<'packet, W> for String {
+  fn write_to(&'packet self, writer: &mut W) {
+    writer.write_str(self);
+  }
+}
+
+impl<'packet, R: PacketReader<'packet> + ?Sized> BinderRead<'packet, R> for String {
+  fn read_from(reader: &mut R) -> Result<Self, ReadError> {
+    reader.read_str().map(str::to_string)
+  }
+}
+
+impl<'packet, W: PacketWriter + ?Sized> BinderWrite<'packet, W> for CString {
+  fn write_to(&'packet self, writer: &mut W) {
+    writer.write_cstr(self);
+  }
+}
+
+impl<'packet, R: PacketReader<'packet> + ?Sized> BinderRead<'packet, R> for CString {
+  fn read_from(reader: &mut R) -> Result<Self, ReadError> {
+    reader.read_cstr().map(CStr::to_owned)
+  }
+}
+
+impl<'packet, W: PacketWriter + ?Sized, T: BinderWrite<'packet, W>> BinderWrite<'packet, W> for Option<T> {
+  fn write_to(&'packet self, writer: &mut W) {
+    writer.write_bool(self.is_some());
+    if let Some(x) = self {
+      x.write_to(writer);
+    }
+  }
+}
+
+impl<'packet, R: PacketReader<'packet> + ?Sized, T: BinderRead<'packet, R>> BinderRead<'packet, R> for Option<T> {
+  fn read_from(reader: &mut R) -> Result<Self, ReadError> {
+    if !reader.read_bool()? {
+      return Ok(None);
+    }
+    
+    T::read_from(reader).map(Some)
+  }
+}
+
+impl<'packet, W: PacketWriter + ?Sized, T: BinderWrite<'packet, W>> BinderWrite<'packet, W> for Vec<T> {
+  fn write_to(&'packet self, writer: &mut W) {
+    let Ok(len) = u32::try_from(self.len()) else {
+      let offset = writer.get_current_offset();
+      writer.set_error(WriteError::LengthTooLarge { offset });
+      return;
+    };
+    
+    writer.write_u32(len);
+    for x in self {
+      x.write_to(writer);
+    }
+  }
+}
+
+impl<'packet, R: PacketReader<'packet> + ?Sized, T: BinderRead<'packet, R>> BinderRead<'packet, R> for Vec<T> {
+  fn read_from(reader: &mut R) -> Result<Self, ReadError> {
+    let length = reader.read_u32()?;
+    (0..length)
+      .map(|_| T::read_from(reader))
+      .collect()
+  }
+}
+
+impl<'packet, W: PacketWriter + ?Sized, T: BinderWrite<'packet, W>> BinderWrite<'packet, W> for Box<T> {
+  fn write_to(&'packet self, writer: &mut W) {
+    T::write_to(self, writer);
+  }
+}
+
+impl<'packet, R: PacketReader<'packet> + ?Sized, T: BinderRead<'packet, R>> BinderRead<'packet, R> for Box<T> {
+  fn read_from(reader: &mut R) -> Result<Self, ReadError> {
+    T::read_from(reader).map(Box::new)
+  }
+}
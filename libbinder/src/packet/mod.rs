@@ -1,13 +1,15 @@
-use std::{io, os::fd::BorrowedFd};
+use std::{ffi::CStr, io, os::fd::{BorrowedFd, FromRawFd, OwnedFd}, sync::Arc};
 
 use enumflags2::BitFlags;
-use libbinder_raw::{object::reference::{ObjectRef, ObjectRefLocal}, transaction::{Transaction, TransactionFlag, TransactionKernelManaged}, types::Type};
+use libbinder_raw::{BinderUsize, object::{buffer::ObjectBuffer, file_descriptor::ObjectFd, reference::{ObjectRef, ObjectRefLocal}}, transaction::{Transaction, TransactionFlag, TransactionKernelManaged}, types::Type};
+use nix::libc;
 
 use crate::{formats::ReadFormat, packet::{builder::PacketBuilder, reader::Reader}};
 
 pub mod builder;
 pub mod reader;
 pub mod writer;
+pub mod encoding;
 
 // A friendly wrapper over transaction data for both incoming/outgoing
 // and perform parsing too
@@ -27,7 +29,16 @@ pub struct Packet<'binder> {
   transaction: Transaction<'binder, 'static, 'static>,
   
   pub(self) data_buffer: Vec<u8>,
-  pub(self) offset_buffer: Vec<usize>
+  pub(self) offset_buffer: Vec<BinderUsize>,
+  
+  // For outgoing packet, these are fds written into it
+  // and for incoming packet, these are fds kernel installed
+  // into this process which packet owns
+  pub(self) fds: Vec<Arc<OwnedFd>>,
+  
+  // Out of line buffers for outgoing packet, for incoming
+  // packet the buffers live in kernel's buffer instead
+  pub(self) buffers: Vec<Arc<[u8]>>
 }
 
 impl<'binder> Into<PacketBuilder<'binder>> for Packet<'binder> {
@@ -53,6 +64,9 @@ impl<'binder> Into<PacketBuilder<'binder>> for Packet<'binder> {
       code: Some(common.code),
       data_buffer: self.data_buffer,
       offsets_buffer: self.offset_buffer,
+      fds: self.fds,
+      buffers: self.buffers,
+      error: None,
       flags: Some(common.flags)
     }
   }
@@ -83,7 +97,34 @@ impl<'binder> Packet<'binder> {
   // acted on
   pub(crate) unsafe fn from_bytes(binder_dev: BorrowedFd<'binder>, bytes: &[u8], is_reply: bool) -> (Option<ObjectRefLocal>, Self) {
     // SAFETY: Caller met the requirement
-    let transaction = Transaction::KernelManaged(unsafe { TransactionKernelManaged::from_bytes(binder_dev, bytes, is_reply) });
+    let transaction = unsafe { TransactionKernelManaged::from_bytes(binder_dev, bytes, is_reply) };
+    Self::from_kernel_managed(binder_dev, transaction, is_reply)
+  }
+  
+  // SAFETY: Same as from_bytes, but the 'bytes' assumed to be from
+  // BR_TRANSACTION_SEC_CTX
+  pub(crate) unsafe fn from_bytes_with_security_context(binder_dev: BorrowedFd<'binder>, bytes: &[u8]) -> (ObjectRefLocal, Self) {
+    // SAFETY: Caller met the requirement
+    let transaction = unsafe { TransactionKernelManaged::from_bytes_with_security_context(binder_dev, bytes) };
+    let (target, packet) = Self::from_kernel_managed(binder_dev, transaction, false);
+    (target.unwrap(), packet)
+  }
+  
+  fn from_kernel_managed(binder_dev: BorrowedFd<'binder>, transaction: TransactionKernelManaged<'binder>, is_reply: bool) -> (Option<ObjectRefLocal>, Self) {
+    let transaction = Transaction::KernelManaged(transaction);
+    
+    // Kernel installed new fds for us, take ownership
+    // of them so they closed once the packet is gone
+    let fds = Self::iter_objects_impl(&transaction)
+      .filter(|&(_, obj_ty)| obj_ty == Type::FileDescriptor)
+      .map(|(offset, obj_ty)| {
+        let bytes = &transaction.get_common().data_slice[offset..offset+obj_ty.type_size_with_header()];
+        let fd = ObjectFd::try_from_bytes(bytes).unwrap();
+        
+        // SAFETY: Kernel gave this fd to us and nothing else owns it
+        Arc::new(unsafe { OwnedFd::from_raw_fd(fd.fd) })
+      })
+      .collect();
     
     (
       if is_reply {
@@ -98,7 +139,9 @@ impl<'binder> Packet<'binder> {
         binder_dev,
         data_buffer: Vec::new(),
         offset_buffer: Vec::new(),
-        transaction
+        transaction,
+        buffers: Vec::new(),
+        fds
       }
     )
   }
@@ -127,30 +170,113 @@ impl<'binder> Packet<'binder> {
     self.transaction.get_common().flags
   }
   
+  // Pid of the process which sent this packet, only meaningful
+  // for incoming packet and 0 if it was one way transaction
+  pub fn get_sender_pid(&self) -> libc::pid_t {
+    self.transaction.get_common().sender_pid
+  }
+  
+  // Effective uid of the process which sent this packet, only
+  // meaningful for incoming packet
+  pub fn get_sender_uid(&self) -> libc::uid_t {
+    self.transaction.get_common().sender_uid
+  }
+  
+  // Security context of the sender, only present for incoming
+  // transaction to object which asked for it
+  pub fn get_security_context(&self) -> Option<&CStr> {
+    match &self.transaction {
+      Transaction::KernelManaged(x) => x.get_security_context(),
+      Transaction::NotKernelManaged(_) => None
+    }
+  }
+  
   pub fn get_binder_dev(&self) -> BorrowedFd<'binder> {
     self.binder_dev
   }
   
-  pub fn iter_references(&self) -> impl Iterator<Item = (usize, ObjectRef)> {
-    self.transaction.get_common().offsets
+  fn iter_objects_impl<'a>(transaction: &'a Transaction<'binder, 'a, 'a>) -> impl Iterator<Item = (usize, Type)> + 'a {
+    transaction.get_common().offsets
       .iter()
       .map(|&x| {
-        (x, Type::from_bytes(&self.transaction.get_common().data_slice[x..x+Type::bytes_needed()]))
+        let x = x as usize;
+        (x, Type::from_bytes(&transaction.get_common().data_slice[x..x+Type::bytes_needed()]))
       })
-      .map(|(offset, obj_ty)| {
+  }
+  
+  pub fn iter_references(&self) -> impl Iterator<Item = (usize, ObjectRef)> {
+    Self::iter_objects_impl(&self.transaction)
+      .filter_map(|(offset, obj_ty)| {
         let bytes = &self.transaction.get_common().data_slice[offset..offset+obj_ty.type_size_with_header()];
         match obj_ty {
-          Type::LocalReference | Type::RemoteReference => {
-            (offset, ObjectRef::try_from_bytes(bytes).unwrap())
+          Type::LocalReference | Type::RemoteReference | Type::WeakLocalReference | Type::WeakRemoteReference => {
+            Some((offset, ObjectRef::try_from_bytes(bytes).unwrap()))
           }
-          _ => panic!("unexpected")
+          _ => None
         }
       })
   }
   
+  pub fn iter_fds(&self) -> impl Iterator<Item = (usize, ObjectFd)> {
+    Self::iter_objects_impl(&self.transaction)
+      .filter(|&(_, obj_ty)| obj_ty == Type::FileDescriptor)
+      .map(|(offset, obj_ty)| {
+        let bytes = &self.transaction.get_common().data_slice[offset..offset+obj_ty.type_size_with_header()];
+        (offset, ObjectFd::try_from_bytes(bytes).unwrap())
+      })
+  }
+  
+  pub fn has_fds(&self) -> bool {
+    self.iter_fds().next().is_some()
+  }
+  
+  pub fn iter_buffers(&self) -> impl Iterator<Item = (usize, ObjectBuffer)> {
+    Self::iter_objects_impl(&self.transaction)
+      .filter(|&(_, obj_ty)| obj_ty == Type::ByteBuffer)
+      .map(|(offset, obj_ty)| {
+        let bytes = &self.transaction.get_common().data_slice[offset..offset+obj_ty.type_size_with_header()];
+        (offset, ObjectBuffer::try_from_bytes(bytes).unwrap())
+      })
+  }
+  
+  // Total size needed in scatter-gather area for all
+  // buffer objects, if nonzero the packet has to be sent
+  // with BC_TRANSACTION_SG/BC_REPLY_SG
+  pub fn buffers_size(&self) -> usize {
+    self.iter_buffers()
+      .map(|(_, buffer)| buffer.size_in_sg_area())
+      .sum()
+  }
+  
   pub(crate) fn get_transaction<'a>(&'a self) -> &'a Transaction<'binder, 'a, 'a> {
     &self.transaction
   }
 }
 
+// Packets in tests are only built and read back, never
+// sent, so any fd works as the binder device
+#[cfg(test)]
+pub(crate) fn test_builder() -> PacketBuilder<'static> {
+  use std::{fs::File, os::fd::AsFd, sync::LazyLock};
+  
+  static DEV_NULL: LazyLock<File> = LazyLock::new(|| File::open("/dev/null").unwrap());
+  let mut builder = PacketBuilder::new(DEV_NULL.as_fd());
+  builder.set_code(1);
+  builder
+}
+
+// Writes with the format into a packet from test_builder
+// and builds it, for tests which then read it back
+#[cfg(test)]
+macro_rules! test_packet {
+  ($format:expr, |$writer:ident| $body:expr) => {{
+    let mut builder = $crate::packet::test_builder();
+    let mut $writer = builder.writer($format);
+    $body;
+    drop($writer);
+    builder.build().unwrap()
+  }};
+}
 
+#[cfg(test)]
+pub(crate) use test_packet;
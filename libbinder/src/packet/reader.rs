@@ -1,8 +1,11 @@
-use std::ffi::CStr;
+use std::{ffi::CStr, os::fd::{BorrowedFd, OwnedFd, RawFd}, slice};
 
-use libbinder_raw::types::{Type, reference::ObjectRef};
+use bytemuck::Pod;
+use bytemuck_utils::{FromBytesError, PodData};
+use libbinder_raw::types::{Type, buffer::{ObjectBuffer, ObjectBufferParent, ObjectFdArray}, file_descriptor::ObjectFd, reference::ObjectRef};
+use nix::errno::Errno;
 
-use crate::{formats::{InnerReader, ReadFormat, SliceReadResult}, packet::Packet};
+use crate::{formats::{InnerReader, ReadError, ReadFormat, SliceReadResult}, packet::Packet};
 
 #[derive(Clone)]
 pub struct Reader<'packet, 'binder, Format: ReadFormat<'packet>> {
@@ -17,6 +20,48 @@ pub struct Reader<'packet, 'binder, Format: ReadFormat<'packet>> {
   packet: &'packet Packet<'binder>
 }
 
+// A view to out of line buffer inside the packet
+#[derive(Clone, Copy)]
+pub struct BufferView<'packet> {
+  data: &'packet [u8],
+  parent: Option<ObjectBufferParent>,
+  
+  // Index of the buffer object in packet's offsets
+  index: usize
+}
+
+impl<'packet> BufferView<'packet> {
+  pub fn get_data(&self) -> &'packet [u8] {
+    self.data
+  }
+  
+  pub fn get_parent(&self) -> Option<ObjectBufferParent> {
+    self.parent
+  }
+  
+  // What children of this buffer have as parent's index
+  pub fn get_index(&self) -> usize {
+    self.index
+  }
+  
+  // View the buffer as T, buffer must be exactly as large as T
+  pub fn get<T: Pod>(&self) -> Result<PodData<'packet, T>, FromBytesError> {
+    PodData::try_from_bytes(self.data)
+  }
+}
+
+// Data of buffer object inside the packet
+fn get_buffer_data<'packet>(buffer: &ObjectBuffer) -> &'packet [u8] {
+  if buffer.length == 0 {
+    return &[];
+  }
+  
+  // SAFETY: For incoming packet, kernel made it point inside kernel's
+  // buffer and for outgoing packet, it points to the packet's own
+  // buffers. Both lives as long as the packet
+  unsafe { slice::from_raw_parts(buffer.buffer as *const u8, buffer.length) }
+}
+
 #[derive(Clone)]
 struct ReaderState<'packet> {
   packet: &'packet Packet<'packet>,
@@ -25,51 +70,59 @@ struct ReaderState<'packet> {
 }
 
 impl ReaderState<'_> {
-  // return false if overlaps with binder objects
-  // they're cannot be directly read
-  fn check_for_primitive_read_safety(&self, len: usize, is_peeking: Option<usize>) -> bool {
-    let current_offset = self.get_cur_offset(is_peeking);
+  // Binder objects can't be directly read, they
+  // need translation done by kernel
+  fn check_for_primitive_read_safety(&self, len: usize, peek_offset: usize) -> Result<(), ReadError> {
+    let current_offset = self.get_cur_offset(peek_offset);
     let offset_range_to_check = current_offset..(current_offset + len);
     
-    for &offset in self.packet.offset_buffer.iter() {
-      if offset > offset_range_to_check.start {
-        // there no need to go further
+    for offset in self.packet.get_transaction().get_common().offsets.iter().map(|&x| x as usize) {
+      if offset >= offset_range_to_check.end {
+        // Offsets are sorted, there no need to go further
         break;
       }
       
-      let Some(header) = self.packet.data_buffer.get(offset..Type::bytes_needed())
-        .map(|x: &[u8]| x.try_into().ok())
-        .flatten()
-      else {
+      let Some(header) = self.full_slice.get(offset..offset + Type::bytes_needed()) else {
         // Offset doesn't make sense lets be conservative and assume its not safe
-        return false;
+        return Err(ReadError::OverlapsObject { offset: current_offset });
       };
       
-      let object_type = Type::from_bytes(header);
-      let size_of_object = object_type.type_size_with_header();
+      let size_of_object = Type::try_from_bytes(header)
+        .map(|x| x.type_size_with_header())
+        .unwrap_or(Type::bytes_needed());
       let range_occupied = offset..offset+size_of_object;
       
-      if range_occupied.contains(&offset_range_to_check.start) || range_occupied.contains(&(offset_range_to_check.end - 1)) {
+      if range_occupied.start < offset_range_to_check.end && offset_range_to_check.start < range_occupied.end {
         // Overlaps with binder objects which is 'not safe' to read
-        return false;
+        return Err(ReadError::OverlapsObject { offset: current_offset });
       }
     }
     
-    true
+    Ok(())
+  }
+  
+  fn get_cur_offset(&self, peek_offset: usize) -> usize {
+    self.full_slice.len() - self.current_slice.len() + peek_offset
   }
   
-  fn get_cur_offset(&self, is_peeking: Option<usize>) -> usize {
-    let slice = &self.current_slice[is_peeking.unwrap_or(0)..];
-    if slice.is_empty() {
-      // Because slice is empty, pointer arithmetic might be wrong
-      return 0;
+  // Gives index of the object in offsets
+  fn check_is_object(&self) -> Result<usize, ReadError> {
+    let current_offset = self.get_cur_offset(0);
+    // Kernel only translates objects listed in offsets, anything
+    // else is just bytes which looks like one
+    self.packet.get_transaction().get_common().offsets.iter()
+      .position(|&x| x as usize == current_offset)
+      .ok_or(ReadError::MalformedObject { offset: current_offset })
+  }
+  
+  fn check_bounds(&self, size: usize, peek_offset: usize) -> Result<(), ReadError> {
+    let offset = self.get_cur_offset(0);
+    let end = peek_offset.checked_add(size)
+      .ok_or(ReadError::LengthOverflow { offset })?;
+    if end > self.current_slice.len() {
+      return Err(ReadError::OutOfBounds { offset, needed: end });
     }
-    
-    assert!(!self.full_slice.is_empty());
-    
-    let ret = slice.as_ptr().addr() - self.full_slice.as_ptr().addr();
-    assert!(ret <= self.full_slice.len());
-    return ret;
+    Ok(())
   }
 }
 
@@ -79,30 +132,58 @@ impl<'packet> InnerReader<'packet> for ReaderState<'packet> {
   }
   
   fn get_current_offset(&self) -> usize {
-    self.get_cur_offset(None)
+    self.get_cur_offset(0)
   }
   
-  fn read(&mut self, size: usize) -> Result<&'packet [u8], ()> {
-    if !self.check_for_primitive_read_safety(size, None) {
-      return Err(());
-    }
-    
-    if size > self.current_slice.len() {
-      return Err(());
-    }
+  fn read(&mut self, size: usize) -> Result<&'packet [u8], ReadError> {
+    self.check_bounds(size, 0)?;
+    self.check_for_primitive_read_safety(size, 0)?;
     
     let ret = &self.current_slice[..size];
     self.current_slice = &self.current_slice[size..];
     Ok(ret)
   }
   
-  fn peek(&self, size: usize, offset: usize) -> Result<&'packet [u8], ()> {
-    if !self.check_for_primitive_read_safety(size, Some(offset)) {
-      return Err(());
-    }
+  fn peek(&self, size: usize, offset: usize) -> Result<&'packet [u8], ReadError> {
+    self.check_bounds(size, offset)?;
+    self.check_for_primitive_read_safety(size, offset)?;
     
     Ok(&self.current_slice[offset..(offset + size)])
   }
+  
+  fn peek_object(&self, size: usize) -> Result<&'packet [u8], ReadError> {
+    self.check_is_object()?;
+    self.check_bounds(size, 0)?;
+    Ok(&self.current_slice[..size])
+  }
+  
+  fn read_object(&mut self, size: usize) -> Result<&'packet [u8], ReadError> {
+    let ret = self.peek_object(size)?;
+    self.current_slice = &self.current_slice[size..];
+    Ok(ret)
+  }
+  
+  fn read_buffer(&mut self) -> Result<BufferView<'packet>, ReadError> {
+    let offset = self.get_cur_offset(0);
+    assert!(offset.is_multiple_of(Type::alignment_in_buffer_needed()), "improper read alignment for buffer object");
+    
+    let index = self.check_is_object()?;
+    let obj_type = Type::try_from_bytes(self.peek_object(Type::bytes_needed())?)
+      .map_err(|_| ReadError::MalformedObject { offset })?;
+    if obj_type != Type::ByteBuffer {
+      return Err(ReadError::UnexpectedObject { offset, found: obj_type });
+    }
+    
+    let buffer = ObjectBuffer::try_from_bytes(self.peek_object(obj_type.type_size_with_header())?)
+      .map_err(|_| ReadError::MalformedObject { offset })?;
+    self.read_object(obj_type.type_size_with_header())?;
+    
+    Ok(BufferView {
+      data: get_buffer_data(&buffer),
+      parent: buffer.parent,
+      index
+    })
+  }
 }
 
 impl<'packet, 'binder, Format: ReadFormat<'packet>> Reader<'packet, 'binder, Format> {
@@ -128,11 +209,19 @@ impl<'packet, 'binder, Format: ReadFormat<'packet>> Reader<'packet, 'binder, For
   pub fn get_packet(&self) -> &'packet Packet<'packet> {
     self.packet
   }
+  
+  // For reads specific to the format, rolled back
+  // on error same as other reads
+  pub fn read_with<T, F: FnOnce(&mut Format) -> Result<T, ReadError>>(&mut self, func: F) -> Result<T, ReadError> {
+    func(&mut self.format)
+      .inspect_err(|_| self.format = self.saved_format.clone())
+      .inspect(|_| self.saved_format = self.format.clone())
+  }
 }
 
 macro_rules! forward {
   ($name:ident, $type:ty) => {
-    pub fn $name(&mut self) -> Result<$type, ()> {
+    pub fn $name(&mut self) -> Result<$type, ReadError> {
       self.format.$name()
         .inspect_err(|_| self.format = self.saved_format.clone())
         .inspect(|_| self.saved_format = self.format.clone())
@@ -174,7 +263,7 @@ impl<'packet, 'binder, Format: ReadFormat<'packet>> Reader<'packet, 'binder, For
   forward!(read_f32_slice, SliceReadResult<'packet, f32>);
   forward!(read_f64_slice, SliceReadResult<'packet, f64>);
   
-  pub fn read_cstr_slice(&mut self) -> Result<Vec<&'packet CStr>, ()> {
+  pub fn read_cstr_slice(&mut self) -> Result<Vec<&'packet CStr>, ReadError> {
     let mut res = Vec::new();
     self.format.read_cstr_slice(&mut res)
       .inspect_err(|_| self.format = self.saved_format.clone())
@@ -182,7 +271,7 @@ impl<'packet, 'binder, Format: ReadFormat<'packet>> Reader<'packet, 'binder, For
     Ok(res)
   }
   
-  pub fn read_str_slice(&mut self) -> Result<Vec<&'packet str>, ()> {
+  pub fn read_str_slice(&mut self) -> Result<Vec<&'packet str>, ReadError> {
     let mut res = Vec::new();
     self.format.read_str_slice(&mut res)
       .inspect_err(|_| self.format = self.saved_format.clone())
@@ -190,30 +279,230 @@ impl<'packet, 'binder, Format: ReadFormat<'packet>> Reader<'packet, 'binder, For
     Ok(res)
   }
   
-  forward!(read_bool_slice, &'packet [bool]);
-  
-  pub fn read_reference<F: FnOnce(&ObjectRef) -> bool>(&mut self, checker: F) -> Result<ObjectRef, ()> {
-    let peek_offset = self.format.get_reader().get_current_offset();
-    assert!(peek_offset.is_multiple_of(Type::alignment_in_buffer_needed()), "improper read alignment for object reference");
-    
-    let ref_obj = Type::try_from_bytes(self.format.get_reader().peek(Type::bytes_needed(), peek_offset)?)?;
-    match ref_obj {
-      Type::LocalReference | Type::RemoteReference => {
-        let type_size = ref_obj.type_size_with_header();
-        let bytes = self.format.get_reader().peek(type_size, peek_offset)?;
-        let result = ObjectRef::try_from_bytes(bytes)?;
-        
-        if !checker(&result) {
-          // Outside checker say is failed return error
-          return Err(());
-        }
-        
-        // The data was successfully read, lets just advance the reader state
-        self.format.get_reader_mut().read(type_size + peek_offset).unwrap();
-        Ok(result)
-      }
-      _ => Err(())
+  forward!(read_bool_slice, SliceReadResult<'packet, bool>);
+  
+  // Peeks binder object at current offset, which must be one of given types
+  fn peek_object(&self, expected: &[Type]) -> Result<(Type, &'packet [u8]), ReadError> {
+    let offset = self.format.get_reader().get_current_offset();
+    let obj_type = Type::try_from_bytes(self.format.get_reader().peek_object(Type::bytes_needed())?)
+      .map_err(|_| ReadError::MalformedObject { offset })?;
+    if !expected.contains(&obj_type) {
+      return Err(ReadError::UnexpectedObject { offset, found: obj_type });
+    }
+    
+    let bytes = self.format.get_reader().peek_object(obj_type.type_size_with_header())?;
+    Ok((obj_type, bytes))
+  }
+  
+  // The object was successfully read, lets just advance the reader state
+  fn consume_object(&mut self, obj_type: Type) {
+    self.format.get_reader_mut().read_object(obj_type.type_size_with_header()).unwrap();
+    self.saved_format = self.format.clone();
+  }
+  
+  // Skips the padding format puts before binder objects then
+  // reads the object at given offset, padding is rolled back too
+  // if reading the object fails
+  fn read_object_with<T, F: FnOnce(&mut Self, usize) -> Result<T, ReadError>>(&mut self, func: F) -> Result<T, ReadError> {
+    let result = self.format.align_for_object().and_then(|()| {
+      let offset = self.format.get_reader().get_current_offset();
+      assert!(offset.is_multiple_of(Type::alignment_in_buffer_needed()), "format did not align binder object properly");
+      func(self, offset)
+    });
+    
+    if result.is_err() {
+      self.format = self.saved_format.clone();
     }
+    result
+  }
+  
+  pub fn read_reference<F: FnOnce(&ObjectRef) -> bool>(&mut self, checker: F) -> Result<ObjectRef, ReadError> {
+    self.read_object_with(|this, offset| {
+      let (obj_type, bytes) = this.peek_object(&[Type::LocalReference, Type::RemoteReference, Type::WeakLocalReference, Type::WeakRemoteReference])?;
+      let result = ObjectRef::try_from_bytes(bytes)
+        .map_err(|_| ReadError::MalformedObject { offset })?;
+      
+      if !checker(&result) {
+        // Outside checker say is failed return error
+        return Err(ReadError::Rejected { offset });
+      }
+      
+      this.consume_object(obj_type);
+      Ok(result)
+    })
+  }
+  
+  // The packet owns the fd, the returned one
+  // is a duplicate owned by the caller
+  pub fn read_fd(&mut self) -> Result<OwnedFd, ReadError> {
+    self.read_object_with(|this, offset| {
+      let (obj_type, bytes) = this.peek_object(&[Type::FileDescriptor])?;
+      let fd = ObjectFd::try_from_bytes(bytes)
+        .map_err(|_| ReadError::MalformedObject { offset })?;
+      
+      // SAFETY: The fd is kept alive by the packet which outlives the reader
+      let owned = unsafe { BorrowedFd::borrow_raw(fd.fd) }.try_clone_to_owned()
+        .map_err(|e| ReadError::Fd { offset, errno: Errno::from_raw(e.raw_os_error().unwrap_or(0)) })?;
+      
+      this.consume_object(obj_type);
+      Ok(owned)
+    })
+  }
+  
+  fn get_buffer_object(&self, index: usize) -> Option<ObjectBuffer> {
+    let common = self.packet.get_transaction().get_common();
+    let offset = *common.offsets.get(index)? as usize;
+    let bytes = common.data_slice.get(offset..offset + ObjectBuffer::size_in_bytes_for_raw())?;
+    ObjectBuffer::try_from_bytes(bytes).ok()
+  }
+  
+  pub fn read_buffer(&mut self) -> Result<BufferView<'packet>, ReadError> {
+    self.read_with(|format| {
+      format.align_for_object()?;
+      format.get_reader_mut().read_buffer()
+    })
+  }
+  
+  // Like read_fd, the returned fds are duplicates
+  pub fn read_fd_array(&mut self) -> Result<Vec<OwnedFd>, ReadError> {
+    self.read_object_with(|this, offset| {
+      let (obj_type, bytes) = this.peek_object(&[Type::FileDescriptorArray])?;
+      let fd_array = ObjectFdArray::try_from_bytes(bytes)
+        .map_err(|_| ReadError::MalformedObject { offset })?;
+      let parent = this.get_buffer_object(fd_array.parent.index)
+        .ok_or(ReadError::MalformedObject { offset })?;
+      let fds_bytes = get_buffer_data(&parent)
+        .get(fd_array.parent.offset..fd_array.parent.offset + fd_array.num_fds * size_of::<u32>())
+        .ok_or(ReadError::MalformedObject { offset })?;
+      
+      let fds = fds_bytes.chunks_exact(size_of::<u32>())
+        .map(|x| u32::from_ne_bytes(x.try_into().unwrap()) as RawFd)
+        // SAFETY: The fds are kept alive by the packet which outlives the reader
+        .map(|fd| unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| ReadError::Fd { offset, errno: Errno::from_raw(e.raw_os_error().unwrap_or(0)) })?;
+      
+      this.consume_object(obj_type);
+      Ok(fds)
+    })
   }
 }
 
+#[cfg(test)]
+mod tests {
+  use libbinder_raw::types::reference::{ObjectRef, ObjectRefRemote};
+  
+  use crate::{formats::{ReadError, dead_simple::{DeadSimpleFormat, DeadSimpleFormatReader}}, packet::test_packet};
+  
+  // Reference used to be peeked at twice its offset, so
+  // only one at the start of the packet could be read
+  #[test]
+  fn read_reference_not_at_start() {
+    let remote = ObjectRefRemote { data_handle: 3, extra_local_data: 0 };
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      writer.write_u32(7);
+      writer.write_obj_ref(ObjectRef::Remote(remote));
+      writer.write_u8(1);
+    });
+    
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    assert_eq!(reader.read_u32(), Ok(7));
+    
+    // Rejected one is not consumed
+    let offset = reader.get_current_offset();
+    assert!(reader.read_reference(|_| false).is_err());
+    assert_eq!(reader.get_current_offset(), offset);
+    
+    let result = reader.read_reference(|_| true).unwrap();
+    assert!(matches!(result, ObjectRef::Remote(x) if x == remote));
+    assert_eq!(reader.get_current_offset(), offset + ObjectRef::size_in_bytes_for_raw());
+    
+    // Failing read after it rolls back to after the reference
+    assert!(reader.read_u64().is_err());
+    assert_eq!(reader.get_current_offset(), offset + ObjectRef::size_in_bytes_for_raw());
+    assert_eq!(reader.read_u8(), Ok(1));
+  }
+  
+  #[test]
+  fn read_bool() {
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      writer.write_bool(true).write_bool(false).write_u8(2);
+    });
+    
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    assert_eq!(reader.read_bool(), Ok(true));
+    assert_eq!(reader.read_bool(), Ok(false));
+    assert_eq!(reader.read_bool(), Err(ReadError::InvalidValue { offset: 2 }));
+  }
+  
+  // The nul is part of the string, next read starts after it
+  #[test]
+  fn read_cstr_consumes_nul() {
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      writer.write_cstr(c"hi").write_u8(5);
+    });
+    
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    assert_eq!(reader.read_cstr(), Ok(c"hi"));
+    assert_eq!(reader.get_current_offset(), 3);
+    assert_eq!(reader.read_u8(), Ok(5));
+  }
+  
+  #[test]
+  fn primitive_read_overlapping_object() {
+    let remote = ObjectRef::Remote(ObjectRefRemote { data_handle: 3, extra_local_data: 0 });
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      writer.write_u32(7);
+      writer.write_obj_ref(remote);
+    });
+    
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    assert_eq!(reader.read_u32(), Ok(7));
+    
+    // Object starts after the read does, still overlaps
+    assert_eq!(reader.read_u64(), Err(ReadError::OverlapsObject { offset: 4 }));
+    assert_eq!(reader.get_current_offset(), 4);
+  }
+  
+  // Objects not at the start of packet used to be treated as
+  // malformed, making reads after them fail
+  #[test]
+  fn primitive_read_after_objects() {
+    let remote = ObjectRef::Remote(ObjectRefRemote { data_handle: 3, extra_local_data: 0 });
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      writer.write_obj_ref(remote.clone());
+      writer.write_obj_ref(remote);
+      writer.write_u32(7);
+    });
+    
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    reader.read_reference(|_| true).unwrap();
+    reader.read_reference(|_| true).unwrap();
+    assert_eq!(reader.get_current_offset(), 2 * ObjectRef::size_in_bytes_for_raw());
+    assert_eq!(reader.read_u32(), Ok(7));
+  }
+  
+  #[test]
+  fn slice_length_overflow() {
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      writer.write_usize(usize::MAX);
+    });
+    
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    let offset = size_of::<usize>();
+    assert_eq!(reader.read_u32_slice().err(), Some(ReadError::LengthOverflow { offset }));
+    assert_eq!(reader.get_current_offset(), 0);
+  }
+  
+  // Slices with more than one element used to fail to cast
+  #[test]
+  fn dead_simple_slices() {
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      writer.write_u32_slice(&[1, 2, 3]).write_bool_slice(&[true, false]);
+    });
+    
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    assert_eq!(&*reader.read_u32_slice().unwrap(), [1, 2, 3]);
+    assert_eq!(&*reader.read_bool_slice().unwrap(), [true, false]);
+  }
+}
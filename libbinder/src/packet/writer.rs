@@ -1,17 +1,32 @@
-use std::{ffi::CStr, mem};
+use std::{ffi::CStr, io, mem, os::fd::{AsRawFd, BorrowedFd}, sync::Arc};
 
-use libbinder_raw::{object::reference::ObjectRef, types::Type};
+use enumflags2::BitFlags;
+use libbinder_raw::{BinderUsize, object::{buffer::{ObjectBuffer, ObjectBufferParent, ObjectFdArray}, file_descriptor::ObjectFd, reference::{ObjectRef, ObjectRefFlags}}, types::Type};
 
-use crate::{formats::{InnerWriter, WriteFormat}, packet::builder::PacketBuilder};
+use crate::{formats::{InnerWriter, WriteError, WriteFormat}, packet::builder::PacketBuilder};
 
 pub struct Writer<'packet, 'binder, Format: WriteFormat<'packet>> {
   format: Format,
-  result: &'packet mut PacketBuilder<'binder>,
-  offsets: Vec<usize>,
+  result: &'packet mut PacketBuilder<'binder>
+}
+
+// Refers to a buffer object written into the packet
+// used to tell which buffer is parent of another buffer
+// or fd array
+#[derive(Clone, Copy, Debug)]
+pub struct BufferHandle {
+  // Index of the object in packet's offsets
+  object_index: usize,
+  
+  // Index into the builder's out of line buffers
+  buffer_index: usize
 }
 
 struct WriterState {
-  buffer: Vec<u8>
+  buffer: Vec<u8>,
+  offsets: Vec<BinderUsize>,
+  buffers: Vec<Arc<[u8]>>,
+  error: Option<WriteError>
 }
 
 impl InnerWriter<'_> for WriterState {
@@ -23,28 +38,78 @@ impl InnerWriter<'_> for WriterState {
     self.buffer.extend_from_slice(bytes);
   }
   
+  fn write_buffer(&mut self, data: &[u8], parent: Option<(BufferHandle, usize)>) -> io::Result<BufferHandle> {
+    let offset = self.get_current_offset();
+    assert!(offset.is_multiple_of(Type::alignment_in_buffer_needed()), "improper write alignment for buffer object");
+    
+    let parent = parent.map(|(handle, parent_offset)| {
+      let parent_buf = &self.buffers[handle.buffer_index];
+      if parent_offset.checked_add(size_of::<BinderUsize>()).is_none_or(|end| end > parent_buf.len()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "pointer to the buffer is outside of parent"));
+      }
+      
+      Ok(ObjectBufferParent {
+        index: handle.object_index,
+        offset: parent_offset
+      })
+    }).transpose()?;
+    
+    let buffer: Arc<[u8]> = Arc::from(data);
+    let handle = BufferHandle {
+      object_index: self.offsets.len(),
+      buffer_index: self.buffers.len()
+    };
+    
+    self.offsets.push(offset as BinderUsize);
+    ObjectBuffer { buffer: buffer.as_ptr().addr(), length: buffer.len(), parent }.with_raw_bytes(|bytes| {
+      self.write(bytes);
+    });
+    self.buffers.push(buffer);
+    Ok(handle)
+  }
+  
+  fn set_error(&mut self, error: WriteError) {
+    self.error.get_or_insert(error);
+  }
+  
   fn get_data_buffer_mut(&mut self) -> &mut Vec<u8> {
     &mut self.buffer
   }
+  
+  fn get_offsets_mut(&mut self) -> &mut Vec<BinderUsize> {
+    &mut self.offsets
+  }
+  
+  fn get_buffers_mut(&mut self) -> &mut Vec<Arc<[u8]>> {
+    &mut self.buffers
+  }
+  
+  fn get_error_mut(&mut self) -> &mut Option<WriteError> {
+    &mut self.error
+  }
 }
 
 impl<'packet, Format: WriteFormat<'packet>> Drop for Writer<'packet, '_, Format> {
   fn drop(&mut self) {
-    mem::swap(self.format.get_writer_mut().get_data_buffer_mut(), &mut self.result.data_buffer);
-    mem::swap(&mut self.result.offsets_buffer, &mut self.offsets);
+    let writer = self.format.get_writer_mut();
+    mem::swap(writer.get_data_buffer_mut(), &mut self.result.data_buffer);
+    mem::swap(writer.get_offsets_mut(), &mut self.result.offsets_buffer);
+    mem::swap(writer.get_buffers_mut(), &mut self.result.buffers);
+    mem::swap(writer.get_error_mut(), &mut self.result.error);
   }
 }
 
 impl<'packet, 'binder, Format: WriteFormat<'packet>> Writer<'packet, 'binder, Format> {
   pub(crate) fn new(packet: &'packet mut PacketBuilder<'binder>, mut format: Format) -> Self {
-    let offsets = mem::replace(&mut packet.offsets_buffer, Vec::new());
     format.set_writer(Box::new(WriterState {
-      buffer: mem::replace(&mut packet.data_buffer, Vec::new())
+      buffer: mem::replace(&mut packet.data_buffer, Vec::new()),
+      offsets: mem::replace(&mut packet.offsets_buffer, Vec::new()),
+      buffers: mem::replace(&mut packet.buffers, Vec::new()),
+      error: packet.error.take()
     }));
     
     Self {
       result: packet,
-      offsets,
       format
     }
   }
@@ -52,6 +117,26 @@ impl<'packet, 'binder, Format: WriteFormat<'packet>> Writer<'packet, 'binder, Fo
   pub fn get_current_offset(&mut self) -> usize {
     self.format.get_writer_mut().get_current_offset()
   }
+  
+  // For writes which can't be done, PacketBuilder::build
+  // gives the first error instead of the packet
+  pub fn set_error(&mut self, error: WriteError) -> &mut Self {
+    self.format.get_writer_mut().set_error(error);
+    self
+  }
+  
+  // Pads for binder object, gives offset of the object
+  fn align_for_object(&mut self) -> usize {
+    self.format.align_for_object();
+    let offset = self.format.get_writer_mut().get_current_offset();
+    assert!(offset.is_multiple_of(Type::alignment_in_buffer_needed()), "format did not align binder object properly");
+    offset
+  }
+  
+  // For writes specific to the format
+  pub fn get_format_mut(&mut self) -> &mut Format {
+    &mut self.format
+  }
 }
 
 macro_rules! impl_forward {
@@ -94,13 +179,161 @@ impl<'packet, 'binder, Format: WriteFormat<'packet>> Writer<'packet, 'binder, Fo
   impl_forward!(write_bool, write_bool_array, write_bool_slice, bool);
   
   pub fn write_obj_ref(&mut self, obj_ref: ObjectRef) {
-    let offset = self.format.get_writer_mut().get_current_offset();
-    assert!(offset.is_multiple_of(Type::alignment_in_buffer_needed()), "improper write alignment for object reference");
+    self.write_obj_ref_with_flags(obj_ref, ObjectRefFlags::default_for_local());
+  }
+  
+  // The flags are only used if it is local reference and
+  // kernel only cares about it when first time sent out
+  pub fn write_obj_ref_with_flags(&mut self, obj_ref: ObjectRef, flags: BitFlags<ObjectRefFlags>) {
+    let offset = self.align_for_object();
+    let writer = self.format.get_writer_mut();
+    writer.get_offsets_mut().push(offset as BinderUsize);
+    obj_ref.with_raw_bytes_flags(flags, |bytes| {
+      writer.write(bytes);
+    });
+  }
+  
+  // The fd is duplicated and kept alive by the packet
+  // so caller free to close theirs after this
+  pub fn write_fd(&mut self, fd: BorrowedFd) -> io::Result<()> {
+    let offset = self.align_for_object();
+    let owned = Arc::new(fd.try_clone_to_owned()?);
+    let writer = self.format.get_writer_mut();
+    writer.get_offsets_mut().push(offset as BinderUsize);
+    ObjectFd { fd: owned.as_raw_fd(), cookie: 0 }.with_raw_bytes(|bytes| {
+      writer.write(bytes);
+    });
+    self.result.fds.push(owned);
+    Ok(())
+  }
+  
+  // Copies the data into out of line buffer, which kernel copies
+  // into receiver separately from the data buffer. If parent is
+  // given, kernel writes the address of this buffer in receiver
+  // into the parent at the given offset
+  //
+  // Kernel requires parent to be written before its children and
+  // children of same parent to be written in increasing offset
+  //
+  // Fails if the pointer doesn't fit inside the parent
+  pub fn write_buffer(&mut self, data: &[u8], parent: Option<(BufferHandle, usize)>) -> io::Result<BufferHandle> {
+    self.align_for_object();
+    self.format.get_writer_mut().write_buffer(data, parent)
+  }
+  
+  // The fds are duplicated and their numbers are written into
+  // the parent buffer at given offset as array of u32, which
+  // kernel then translates to receiver's fds
+  //
+  // Fails if the array is misaligned or doesn't fit inside the
+  // parent, or the parent buffer is shared with another packet
+  // (e.g. builder made from a clone of a packet)
+  pub fn write_fd_array(&mut self, fds: &[BorrowedFd], parent: BufferHandle, parent_offset: usize) -> io::Result<()> {
+    let offset = self.align_for_object();
+    if !parent_offset.is_multiple_of(size_of::<u32>()) {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput, "improper alignment for fd array inside parent"));
+    }
     
-    self.offsets.push(offset);
-    obj_ref.with_raw_bytes(|bytes| {
-      self.format.get_writer_mut().write(bytes);
+    let writer = self.format.get_writer_mut();
+    let Some(parent_buf) = Arc::get_mut(&mut writer.get_buffers_mut()[parent.buffer_index]) else {
+      return Err(io::Error::new(io::ErrorKind::ResourceBusy, "parent buffer is shared with another packet"));
+    };
+    let Some(slots) = fds.len().checked_mul(size_of::<u32>())
+      .and_then(|size| parent_offset.checked_add(size))
+      .and_then(|end| parent_buf.get_mut(parent_offset..end))
+    else {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput, "fd array is outside of parent"));
+    };
+    
+    let owned = fds.iter()
+      .map(|fd| fd.try_clone_to_owned().map(Arc::new))
+      .collect::<io::Result<Vec<_>>>()?;
+    
+    for (slot, fd) in slots.chunks_exact_mut(size_of::<u32>()).zip(owned.iter()) {
+      slot.copy_from_slice(&(fd.as_raw_fd() as u32).to_ne_bytes());
+    }
+    
+    writer.get_offsets_mut().push(offset as BinderUsize);
+    ObjectFdArray {
+      num_fds: owned.len(),
+      parent: ObjectBufferParent {
+        index: parent.object_index,
+        offset: parent_offset
+      }
+    }.with_raw_bytes(|bytes| {
+      writer.write(bytes);
     });
+    self.result.fds.extend(owned);
+    Ok(())
   }
 }
 
+#[cfg(test)]
+mod tests {
+  use std::{fs::File, io, os::fd::AsFd};
+  
+  use libbinder_raw::{object::Type, types::reference::{ObjectRef, ObjectRefRemote}};
+  
+  use crate::{formats::dead_simple::{DeadSimpleFormat, DeadSimpleFormatReader}, packet::{builder::PacketBuilder, test_builder, test_packet}};
+  
+  // Dead simple format packs data, so objects after
+  // odd sized data need padding before them
+  #[test]
+  fn object_after_unaligned_data() {
+    let remote = ObjectRefRemote { data_handle: 3, extra_local_data: 0 };
+    let file = File::open("/dev/null").unwrap();
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      writer.write_u8(7);
+      writer.write_obj_ref(ObjectRef::Remote(remote));
+      writer.write_cstr(c"ab");
+      writer.write_fd(file.as_fd()).unwrap();
+    });
+    let offsets = packet.iter_references().map(|(offset, _)| offset)
+      .chain(packet.iter_fds().map(|(offset, _)| offset));
+    for offset in offsets {
+      assert_eq!(offset % Type::alignment_in_buffer_needed(), 0);
+    }
+    
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    assert_eq!(reader.read_u8(), Ok(7));
+    assert!(matches!(reader.read_reference(|_| true), Ok(ObjectRef::Remote(x)) if x == remote));
+    assert_eq!(reader.read_cstr(), Ok(c"ab"));
+    reader.read_fd().unwrap();
+  }
+  
+  #[test]
+  fn fd_array_in_shared_parent() {
+    let file = File::open("/dev/null").unwrap();
+    let parent;
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      parent = writer.write_buffer(&[0; 8], None).unwrap();
+    });
+    
+    // Builder made from clone shares buffers with the original
+    let mut builder: PacketBuilder = packet.clone().into();
+    let mut writer = builder.writer(DeadSimpleFormat::new());
+    let err = writer.write_fd_array(&[file.as_fd()], parent, 0).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ResourceBusy);
+    
+    drop(packet);
+    writer.write_fd_array(&[file.as_fd()], parent, 0).unwrap();
+  }
+  
+  #[test]
+  fn invalid_parent_offsets() {
+    let file = File::open("/dev/null").unwrap();
+    let mut builder = test_builder();
+    let mut writer = builder.writer(DeadSimpleFormat::new());
+    let parent = writer.write_buffer(&[0; 8], None).unwrap();
+    
+    let err = writer.write_buffer(&[], Some((parent, 6))).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    
+    let fds = [file.as_fd(), file.as_fd()];
+    let err = writer.write_fd_array(&fds, parent, 2).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let err = writer.write_fd_array(&fds, parent, 4).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    writer.write_fd_array(&fds, parent, 0).unwrap();
+  }
+}
@@ -1,6 +1,6 @@
-use std::os::fd::BorrowedFd;
+use std::{error::Error, fmt::Display, os::fd::{AsFd, BorrowedFd}};
 
-use libbinder_raw::{commands::{PtrCookieRaw, ReturnVal}, object::reference::ObjectRefLocal, transaction::TransactionKernelManaged};
+use libbinder_raw::{BinderUsize, device::BinderDevice, commands::{FrozenStateInfoRaw, PriPtrCookieRaw, PtrCookieRaw, ReturnVal}, object::reference::ObjectRefLocal};
 use yoke::Yokeable;
 
 use crate::packet::Packet;
@@ -18,9 +18,58 @@ pub enum ReturnValue<'binder> {
   SpawnLooper,
   TransactionComplete,
   DeadReply,
-  Noop
+  Noop,
+  
+  // The usize is the cookie given in
+  // BC_REQUEST_DEATH_NOTIFICATION
+  DeadBinder(usize),
+  ClearDeathNotificationDone(usize),
+  
+  // Sync transaction was sent to frozen process
+  FrozenReply,
+  
+  // One way transaction was queued as the target is
+  // frozen, sent instead of TransactionComplete
+  TransactionPendingFrozen,
+  
+  // The usize is the cookie given in BC_REQUEST_FREEZE_NOTIFICATION
+  // and bool is whether the remote object's process is frozen
+  FrozenBinder(usize, bool),
+  ClearFreezeNotificationDone(usize),
+  
+  // Kernel sends these for one way transactions when spam
+  // detection is enabled, in place of TransactionComplete
+  OnewaySpamSuspect,
+  
+  // Not used by current kernel, but are part of protocol
+  AcquireResult(i32),
+  AttemptAcquire(i32, ObjectRefLocal),
+  Finished,
+  
+  // Return code which is not known, its data is skipped
+  Unknown(u32)
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+  // Return value at the offset is cut off
+  Truncated { offset: usize },
+  
+  // Return value at the offset has invalid content
+  Malformed { offset: usize, code: ReturnVal }
+}
+
+impl Display for ParseError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ParseError::Truncated { offset } => write!(f, "return value at offset {offset} is truncated"),
+      ParseError::Malformed { offset, code } => write!(f, "return value {code:?} at offset {offset} is malformed")
+    }
+  }
 }
 
+impl Error for ParseError {}
+
 #[derive(Yokeable)]
 pub struct ReturnBuffer<'binder> {
   binder_dev: BorrowedFd<'binder>,
@@ -29,7 +78,7 @@ pub struct ReturnBuffer<'binder> {
 }
 
 impl<'binder> ReturnBuffer<'binder> {
-  pub fn new(binder_dev: BorrowedFd<'binder>, size: usize) -> Self {
+  pub fn new(binder_dev: &'binder BinderDevice, size: usize) -> Self {
     Self {
       buffer: {
         let mut tmp = Vec::new();
@@ -37,7 +86,7 @@ impl<'binder> ReturnBuffer<'binder> {
         tmp
       },
       parsed: Vec::new(),
-      binder_dev
+      binder_dev: binder_dev.as_fd()
     }
   }
   
@@ -49,76 +98,99 @@ impl<'binder> ReturnBuffer<'binder> {
     self.parsed.clear();
   }
   
-  pub(crate) fn parse(&mut self, read_bytes: usize) {
+  // On error, return values before the malformed one
+  // are still available in get_parsed
+  pub(crate) fn parse(&mut self, read_bytes: usize) -> Result<(), ParseError> {
     let mut current = &self.buffer[..read_bytes];
     const RETVAL_SIZE: usize = size_of::<ReturnVal>();
     while current.len() != 0 {
-      let val_tag = ReturnVal::try_from_bytes(current[..RETVAL_SIZE].try_into().unwrap()).unwrap();
+      let offset = read_bytes - current.len();
+      if current.len() < RETVAL_SIZE {
+        return Err(ParseError::Truncated { offset });
+      }
+      
+      let code = u32::from_ne_bytes(current[..RETVAL_SIZE].try_into().unwrap());
+      let payload_size = ReturnVal::payload_size_of(code);
+      if current.len() < RETVAL_SIZE + payload_size {
+        return Err(ParseError::Truncated { offset });
+      }
+      
+      let payload = &current[RETVAL_SIZE..RETVAL_SIZE+payload_size];
+      
+      // Go forward
+      current = &current[RETVAL_SIZE+payload_size..];
+      
+      let Ok(val_tag) = ReturnVal::try_from_bytes(code.to_ne_bytes()) else {
+        self.parsed.push(ReturnValue::Unknown(code));
+        continue;
+      };
       
-      let transaction_size = TransactionKernelManaged::bytes_needed();
       let val = match val_tag {
         ReturnVal::Noop => ReturnValue::Noop,
         ReturnVal::Reply => {
-          let bytes = &current[RETVAL_SIZE..RETVAL_SIZE+transaction_size];
-          let (_, packet) = unsafe { Packet::from_bytes(self.binder_dev, bytes, true) };
-          current = &current[transaction_size..];
+          let (_, packet) = unsafe { Packet::from_bytes(self.binder_dev, payload, true) };
           ReturnValue::Reply(packet)
         },
         ReturnVal::Transaction => {
-          let bytes = &current[RETVAL_SIZE..RETVAL_SIZE+transaction_size];
-          let packet = unsafe { Packet::from_bytes(self.binder_dev, bytes, false) };
-          current = &current[transaction_size..];
-          ReturnValue::Transaction((packet.0.unwrap(), packet.1))
+          let packet = unsafe { Packet::from_bytes(self.binder_dev, payload, false) };
+          let Some(target) = packet.0 else {
+            return Err(ParseError::Malformed { offset, code: val_tag });
+          };
+          ReturnValue::Transaction((target, packet.1))
         },
-        ReturnVal::Error => {
-          let err = i32::from_ne_bytes(current[..size_of::<i32>()].try_into().unwrap());
-          current = &current[size_of::<i32>()..];
-          ReturnValue::Error(err)
+        ReturnVal::TransactionSecCtx => {
+          let packet = unsafe { Packet::from_bytes_with_security_context(self.binder_dev, payload) };
+          ReturnValue::Transaction(packet)
         },
+        ReturnVal::Error => ReturnValue::Error(i32::from_ne_bytes(payload.try_into().unwrap())),
         ReturnVal::Failed => ReturnValue::TransactionFailed,
         ReturnVal::Ok => ReturnValue::Ok,
         ReturnVal::SpawnLooper => ReturnValue::SpawnLooper,
         ReturnVal::TransactionComplete => ReturnValue::TransactionComplete,
         ReturnVal::DeadReply => ReturnValue::DeadReply,
-        ReturnVal::DeadBinder => unimplemented!(),
-        ReturnVal::Acquire => {
-          let ret = PtrCookieRaw::from_raw_bytes(&current[..size_of::<PtrCookieRaw>()]);
-          current = &current[size_of::<PtrCookieRaw>()..];
-          ReturnValue::Acquire(ObjectRefLocal {
-            data: ret.ptr,
-            extra_data: ret.cookie
-          })
-        }
-        ReturnVal::Release => {
-          let ret = PtrCookieRaw::from_raw_bytes(&current[..size_of::<PtrCookieRaw>()]);
-          current = &current[size_of::<PtrCookieRaw>()..];
-          ReturnValue::Release(ObjectRefLocal {
-            data: ret.ptr,
-            extra_data: ret.cookie
+        ReturnVal::FrozenReply => ReturnValue::FrozenReply,
+        ReturnVal::TransactionPendingFrozen => ReturnValue::TransactionPendingFrozen,
+        ReturnVal::Finished => ReturnValue::Finished,
+        ReturnVal::OnewaySpamSuspect => ReturnValue::OnewaySpamSuspect,
+        ReturnVal::AcquireResult => ReturnValue::AcquireResult(i32::from_ne_bytes(payload.try_into().unwrap())),
+        ReturnVal::AttemptAcquire => {
+          let ret = PriPtrCookieRaw::from_raw_bytes(payload);
+          ReturnValue::AttemptAcquire(ret.priority, ObjectRefLocal {
+            data: ret.ptr as usize,
+            extra_data: ret.cookie as usize
           })
-        }
-        ReturnVal::AcquireWeak => {
-          let ret = PtrCookieRaw::from_raw_bytes(&current[..size_of::<PtrCookieRaw>()]);
-          current = &current[size_of::<PtrCookieRaw>()..];
-          ReturnValue::AcquireWeak(ObjectRefLocal {
-            data: ret.ptr,
-            extra_data: ret.cookie
-          })
-        }
+        },
+        ReturnVal::FrozenBinder => {
+          let info = FrozenStateInfoRaw::from_raw_bytes(payload);
+          ReturnValue::FrozenBinder(info.cookie as usize, info.is_frozen != 0)
+        },
+        ReturnVal::ClearFreezeNotificationDone => ReturnValue::ClearFreezeNotificationDone(BinderUsize::from_ne_bytes(payload.try_into().unwrap()) as usize),
+        ReturnVal::DeadBinder => ReturnValue::DeadBinder(BinderUsize::from_ne_bytes(payload.try_into().unwrap()) as usize),
+        ReturnVal::ClearDeathNotificationDone => ReturnValue::ClearDeathNotificationDone(BinderUsize::from_ne_bytes(payload.try_into().unwrap()) as usize),
+        ReturnVal::Acquire |
+        ReturnVal::Release |
+        ReturnVal::AcquireWeak |
         ReturnVal::ReleaseWeak => {
-          let ret = PtrCookieRaw::from_raw_bytes(&current[..size_of::<PtrCookieRaw>()]);
-          current = &current[size_of::<PtrCookieRaw>()..];
-          ReturnValue::ReleaseWeak(ObjectRefLocal {
-            data: ret.ptr,
-            extra_data: ret.cookie
-          })
+          let ret = PtrCookieRaw::from_raw_bytes(payload);
+          let local_ref = ObjectRefLocal {
+            data: ret.ptr as usize,
+            extra_data: ret.cookie as usize
+          };
+          
+          match val_tag {
+            ReturnVal::Acquire => ReturnValue::Acquire(local_ref),
+            ReturnVal::Release => ReturnValue::Release(local_ref),
+            ReturnVal::AcquireWeak => ReturnValue::AcquireWeak(local_ref),
+            ReturnVal::ReleaseWeak => ReturnValue::ReleaseWeak(local_ref),
+            _ => unreachable!()
+          }
         }
       };
       
-      // Go forward
-      current = &current[RETVAL_SIZE..];
       self.parsed.push(val);
     }
+    
+    Ok(())
   }
   
   // The .0 is cleared and .1 is in unknown state
@@ -136,13 +208,13 @@ impl<'binder> ReturnBuffer<'binder> {
   
   // .0 is cleared
   // while .1 is left as it is but will be overwritten
-  pub fn from_buffers(binder_dev: BorrowedFd<'binder>, mut raw: (Vec<ReturnValue<'static>>, Vec<u8>)) -> Self {
+  pub fn from_buffers(binder_dev: &'binder BinderDevice, mut raw: (Vec<ReturnValue<'static>>, Vec<u8>)) -> Self {
     raw.0.clear();
     
     Self {
       parsed: raw.0,
       buffer: raw.1,
-      binder_dev
+      binder_dev: binder_dev.as_fd()
     }
   }
 }
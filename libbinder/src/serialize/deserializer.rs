@@ -0,0 +1,234 @@
+use serde::{Deserialize, de::{self, DeserializeSeed, IntoDeserializer, Visitor}};
+
+use crate::{formats::{ReadError, ReadFormat}, packet::reader::Reader, serialize::{BINDER_OBJECT_NAME, SerdeError}};
+
+pub struct Deserializer<'reader, 'packet, 'binder, Format: ReadFormat<'packet>> {
+  reader: &'reader mut Reader<'packet, 'binder, Format>
+}
+
+impl<'reader, 'packet, 'binder, Format: ReadFormat<'packet>> Deserializer<'reader, 'packet, 'binder, Format> {
+  pub fn new(reader: &'reader mut Reader<'packet, 'binder, Format>) -> Self {
+    Self {
+      reader
+    }
+  }
+}
+
+// Strings and bytes borrows from the packet
+pub fn from_reader<'packet, Format: ReadFormat<'packet>, T: Deserialize<'packet>>(reader: &mut Reader<'packet, '_, Format>) -> Result<T, SerdeError> {
+  T::deserialize(&mut Deserializer::new(reader))
+}
+
+// Gives fixed number of elements, for sequences,
+// maps, tuples and structs
+struct Counted<'de, 'reader, 'packet, 'binder, Format: ReadFormat<'packet>> {
+  de: &'de mut Deserializer<'reader, 'packet, 'binder, Format>,
+  remaining: usize
+}
+
+impl<'packet, Format: ReadFormat<'packet>> de::SeqAccess<'packet> for Counted<'_, '_, 'packet, '_, Format> {
+  type Error = SerdeError;
+  
+  fn next_element_seed<T: DeserializeSeed<'packet>>(&mut self, seed: T) -> Result<Option<T::Value>, SerdeError> {
+    if self.remaining == 0 {
+      return Ok(None);
+    }
+    
+    self.remaining -= 1;
+    seed.deserialize(&mut *self.de).map(Some)
+  }
+  
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.remaining)
+  }
+}
+
+impl<'packet, Format: ReadFormat<'packet>> de::MapAccess<'packet> for Counted<'_, '_, 'packet, '_, Format> {
+  type Error = SerdeError;
+  
+  fn next_key_seed<K: DeserializeSeed<'packet>>(&mut self, seed: K) -> Result<Option<K::Value>, SerdeError> {
+    if self.remaining == 0 {
+      return Ok(None);
+    }
+    
+    self.remaining -= 1;
+    seed.deserialize(&mut *self.de).map(Some)
+  }
+  
+  fn next_value_seed<V: DeserializeSeed<'packet>>(&mut self, seed: V) -> Result<V::Value, SerdeError> {
+    seed.deserialize(&mut *self.de)
+  }
+  
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.remaining)
+  }
+}
+
+impl<'packet, Format: ReadFormat<'packet>> de::EnumAccess<'packet> for &mut Deserializer<'_, 'packet, '_, Format> {
+  type Error = SerdeError;
+  type Variant = Self;
+  
+  fn variant_seed<V: DeserializeSeed<'packet>>(self, seed: V) -> Result<(V::Value, Self), SerdeError> {
+    let index = self.reader.read_u32()?;
+    let value = seed.deserialize(IntoDeserializer::<SerdeError>::into_deserializer(index))?;
+    Ok((value, self))
+  }
+}
+
+impl<'packet, Format: ReadFormat<'packet>> de::VariantAccess<'packet> for &mut Deserializer<'_, 'packet, '_, Format> {
+  type Error = SerdeError;
+  
+  fn unit_variant(self) -> Result<(), SerdeError> {
+    Ok(())
+  }
+  
+  fn newtype_variant_seed<T: DeserializeSeed<'packet>>(self, seed: T) -> Result<T::Value, SerdeError> {
+    seed.deserialize(self)
+  }
+  
+  fn tuple_variant<V: Visitor<'packet>>(self, len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_seq(Counted { de: self, remaining: len })
+  }
+  
+  fn struct_variant<V: Visitor<'packet>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_seq(Counted { de: self, remaining: fields.len() })
+  }
+}
+
+impl<'packet, Format: ReadFormat<'packet>> de::Deserializer<'packet> for &mut Deserializer<'_, 'packet, '_, Format> {
+  type Error = SerdeError;
+  
+  fn deserialize_any<V: Visitor<'packet>>(self, _visitor: V) -> Result<V::Value, SerdeError> {
+    Err(SerdeError::Unsupported("deserialize_any"))
+  }
+  
+  fn deserialize_ignored_any<V: Visitor<'packet>>(self, _visitor: V) -> Result<V::Value, SerdeError> {
+    Err(SerdeError::Unsupported("deserialize_ignored_any"))
+  }
+  
+  fn deserialize_bool<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_bool(self.reader.read_bool()?)
+  }
+  
+  fn deserialize_i8<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_i8(self.reader.read_i8()?)
+  }
+  
+  fn deserialize_i16<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_i16(self.reader.read_i16()?)
+  }
+  
+  fn deserialize_i32<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_i32(self.reader.read_i32()?)
+  }
+  
+  fn deserialize_i64<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_i64(self.reader.read_i64()?)
+  }
+  
+  fn deserialize_u8<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_u8(self.reader.read_u8()?)
+  }
+  
+  fn deserialize_u16<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_u16(self.reader.read_u16()?)
+  }
+  
+  fn deserialize_u32<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_u32(self.reader.read_u32()?)
+  }
+  
+  fn deserialize_u64<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_u64(self.reader.read_u64()?)
+  }
+  
+  fn deserialize_f32<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_f32(self.reader.read_f32()?)
+  }
+  
+  fn deserialize_f64<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_f64(self.reader.read_f64()?)
+  }
+  
+  fn deserialize_char<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    let offset = self.reader.get_current_offset();
+    let value = char::from_u32(self.reader.read_u32()?)
+      .ok_or(SerdeError::Read(ReadError::InvalidValue { offset }))?;
+    visitor.visit_char(value)
+  }
+  
+  fn deserialize_str<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_borrowed_str(self.reader.read_str()?)
+  }
+  
+  fn deserialize_string<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    self.deserialize_str(visitor)
+  }
+  
+  fn deserialize_bytes<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_borrowed_bytes(self.reader.read_u8_slice()?)
+  }
+  
+  fn deserialize_byte_buf<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    self.deserialize_bytes(visitor)
+  }
+  
+  fn deserialize_option<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    if self.reader.read_bool()? {
+      visitor.visit_some(self)
+    } else {
+      visitor.visit_none()
+    }
+  }
+  
+  fn deserialize_unit<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_unit()
+  }
+  
+  fn deserialize_unit_struct<V: Visitor<'packet>>(self, _name: &'static str, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_unit()
+  }
+  
+  fn deserialize_newtype_struct<V: Visitor<'packet>>(self, name: &'static str, visitor: V) -> Result<V::Value, SerdeError> {
+    if name == BINDER_OBJECT_NAME {
+      let obj_ref = self.reader.read_reference(|_| true)?;
+      return obj_ref.with_raw_bytes(|bytes| visitor.visit_bytes(bytes));
+    }
+    
+    visitor.visit_newtype_struct(self)
+  }
+  
+  fn deserialize_seq<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    let remaining = self.reader.read_u32()? as usize;
+    visitor.visit_seq(Counted { de: self, remaining })
+  }
+  
+  fn deserialize_tuple<V: Visitor<'packet>>(self, len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_seq(Counted { de: self, remaining: len })
+  }
+  
+  fn deserialize_tuple_struct<V: Visitor<'packet>>(self, _name: &'static str, len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_seq(Counted { de: self, remaining: len })
+  }
+  
+  fn deserialize_map<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    let remaining = self.reader.read_u32()? as usize;
+    visitor.visit_map(Counted { de: self, remaining })
+  }
+  
+  fn deserialize_struct<V: Visitor<'packet>>(self, _name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_seq(Counted { de: self, remaining: fields.len() })
+  }
+  
+  fn deserialize_enum<V: Visitor<'packet>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+    visitor.visit_enum(self)
+  }
+  
+  fn deserialize_identifier<V: Visitor<'packet>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+    self.deserialize_u32(visitor)
+  }
+  
+  fn is_human_readable(&self) -> bool {
+    false
+  }
+}
@@ -0,0 +1,225 @@
+// Serde support over the packet's Writer and Reader, the layout is
+// up to the format used. Things the formats don't have are done as
+//
+// sequence, map: u32 length then each element in place
+// option: bool then the value if there is
+// enum: u32 variant index then the variant's data
+// struct, tuple: each field in order
+//
+// Bytes and str uses the format's own, so reading them borrows
+// from the packet. Format is not self describing, so types which
+// needs deserialize_any can't be used
+
+use std::{error::Error, fmt::{self, Display}};
+
+use libbinder_raw::types::reference::ObjectRef;
+use serde::{Deserialize, Serialize, de::{self, Visitor}, ser};
+
+use crate::formats::ReadError;
+
+pub mod serializer;
+pub mod deserializer;
+
+pub use deserializer::{Deserializer, from_reader};
+pub use serializer::{Serializer, to_writer};
+
+// Serializer and Deserializer look for this to
+// write and read binder object instead
+const BINDER_OBJECT_NAME: &str = "$libbinder::BinderObject";
+
+#[derive(Debug)]
+pub enum SerdeError {
+  Read(ReadError),
+  
+  // Length of sequence or map must be known
+  // before serializing it
+  UnknownLength,
+  LengthTooLarge,
+  
+  // Type which the format can't represent
+  Unsupported(&'static str),
+  Custom(String)
+}
+
+impl Display for SerdeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SerdeError::Read(e) => write!(f, "{e}"),
+      SerdeError::UnknownLength => write!(f, "length of sequence must be known"),
+      SerdeError::LengthTooLarge => write!(f, "length too large"),
+      SerdeError::Unsupported(what) => write!(f, "{what} is not supported"),
+      SerdeError::Custom(msg) => write!(f, "{msg}")
+    }
+  }
+}
+
+impl Error for SerdeError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match self {
+      SerdeError::Read(e) => Some(e),
+      _ => None
+    }
+  }
+}
+
+impl From<ReadError> for SerdeError {
+  fn from(value: ReadError) -> Self {
+    SerdeError::Read(value)
+  }
+}
+
+impl ser::Error for SerdeError {
+  fn custom<T: Display>(msg: T) -> Self {
+    SerdeError::Custom(msg.to_string())
+  }
+}
+
+impl de::Error for SerdeError {
+  fn custom<T: Display>(msg: T) -> Self {
+    SerdeError::Custom(msg.to_string())
+  }
+}
+
+// Binder object reference inside serde types, written as binder
+// object with this crate's Serializer. With other serializers its
+// the raw flat_binder_object bytes
+#[derive(Clone)]
+pub struct BinderObject(pub ObjectRef);
+
+struct RawObject<'a>(&'a [u8]);
+
+impl Serialize for RawObject<'_> {
+  fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(self.0)
+  }
+}
+
+impl Serialize for BinderObject {
+  fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    self.0.with_raw_bytes(|bytes| {
+      serializer.serialize_newtype_struct(BINDER_OBJECT_NAME, &RawObject(bytes))
+    })
+  }
+}
+
+struct BinderObjectVisitor;
+
+impl<'de> Visitor<'de> for BinderObjectVisitor {
+  type Value = BinderObject;
+  
+  fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "binder object")
+  }
+  
+  fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+    ObjectRef::try_from_bytes(v)
+      .map(BinderObject)
+      .map_err(|_| E::custom("invalid binder object"))
+  }
+  
+  fn visit_newtype_struct<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+    deserializer.deserialize_bytes(self)
+  }
+}
+
+impl<'de> Deserialize<'de> for BinderObject {
+  fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    deserializer.deserialize_newtype_struct(BINDER_OBJECT_NAME, BinderObjectVisitor)
+  }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+  use std::{collections::BTreeMap, ops::Range};
+  
+  use libbinder_raw::types::reference::{ObjectRef, ObjectRefRemote};
+  use serde::de::IgnoredAny;
+  
+  use crate::{formats::dead_simple::{DeadSimpleFormat, DeadSimpleFormatReader}, packet::{Packet, test_packet}};
+  
+  use super::*;
+  
+  fn packet_range(packet: &Packet) -> Range<*const u8> {
+    packet.get_transaction().get_common().data_slice.as_ptr_range()
+  }
+  
+  #[test]
+  fn borrows_from_packet() {
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      to_writer(&mut writer, &("hello", RawObject(b"world"))).unwrap();
+    });
+    
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    let (text, bytes): (&str, &[u8]) = from_reader(&mut reader).unwrap();
+    assert_eq!(text, "hello");
+    assert_eq!(bytes, b"world");
+    
+    let range = packet_range(&packet);
+    assert!(range.contains(&text.as_ptr()));
+    assert!(range.contains(&bytes.as_ptr()));
+  }
+  
+  #[test]
+  fn round_trip() {
+    let mut map = BTreeMap::new();
+    map.insert(1u32, "one");
+    map.insert(2u32, "two");
+    let value = (Some(5u16), None::<i64>, Ok::<u8, &str>(3), Err::<u8, &str>("bad"), map, 'ż');
+    
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      to_writer(&mut writer, &value).unwrap();
+    });
+    
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    let result = from_reader(&mut reader).unwrap();
+    assert_eq!(value, result);
+  }
+  
+  #[test]
+  fn invalid_char() {
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      writer.write_u32(0xD800);
+    });
+    
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    let result = from_reader::<_, char>(&mut reader);
+    assert!(matches!(result, Err(SerdeError::Read(ReadError::InvalidValue { offset: 0 }))));
+  }
+  
+  #[test]
+  fn binder_object() {
+    let remote = ObjectRefRemote { data_handle: 3, extra_local_data: 0 };
+    
+    // Written as binder object, not as bytes
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      to_writer(&mut writer, &(7u32, BinderObject(ObjectRef::Remote(remote)))).unwrap();
+    });
+    
+    assert_eq!(packet.iter_references().count(), 1);
+    
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    let (value, object): (u32, BinderObject) = from_reader(&mut reader).unwrap();
+    assert_eq!(value, 7);
+    assert!(matches!(object.0, ObjectRef::Remote(x) if x == remote));
+  }
+  
+  // Needs the format to be self describing
+  struct Any;
+  
+  impl<'de> Deserialize<'de> for Any {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+      deserializer.deserialize_any(IgnoredAny).map(|_| Any)
+    }
+  }
+  
+  #[test]
+  fn deserialize_any_unsupported() {
+    let packet = test_packet!(DeadSimpleFormat::new(), |writer| {
+      writer.write_u32(0);
+    });
+    
+    let mut reader = packet.reader(DeadSimpleFormatReader::new());
+    let result = from_reader::<_, Any>(&mut reader);
+    assert!(matches!(result, Err(SerdeError::Unsupported("deserialize_any"))));
+  }
+}
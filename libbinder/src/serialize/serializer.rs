@@ -0,0 +1,255 @@
+use libbinder_raw::types::reference::ObjectRef;
+use serde::{Serialize, ser};
+
+use crate::{formats::WriteFormat, packet::writer::Writer, serialize::{BINDER_OBJECT_NAME, SerdeError}};
+
+pub struct Serializer<'writer, 'packet, 'binder, Format: WriteFormat<'packet>> {
+  writer: &'writer mut Writer<'packet, 'binder, Format>,
+  
+  // Next bytes is binder object not a byte slice
+  in_binder_object: bool
+}
+
+impl<'writer, 'packet, 'binder, Format: WriteFormat<'packet>> Serializer<'writer, 'packet, 'binder, Format> {
+  pub fn new(writer: &'writer mut Writer<'packet, 'binder, Format>) -> Self {
+    Self {
+      writer,
+      in_binder_object: false
+    }
+  }
+  
+  fn write_length(&mut self, length: Option<usize>) -> Result<(), SerdeError> {
+    let length = length.ok_or(SerdeError::UnknownLength)?;
+    self.writer.write_u32(u32::try_from(length).map_err(|_| SerdeError::LengthTooLarge)?);
+    Ok(())
+  }
+}
+
+pub fn to_writer<'packet, Format: WriteFormat<'packet>, T: Serialize + ?Sized>(writer: &mut Writer<'packet, '_, Format>, value: &T) -> Result<(), SerdeError> {
+  value.serialize(&mut Serializer::new(writer))
+}
+
+impl<'packet, Format: WriteFormat<'packet>> ser::Serializer for &mut Serializer<'_, 'packet, '_, Format> {
+  type Ok = ();
+  type Error = SerdeError;
+  
+  type SerializeSeq = Self;
+  type SerializeTuple = Self;
+  type SerializeTupleStruct = Self;
+  type SerializeTupleVariant = Self;
+  type SerializeMap = Self;
+  type SerializeStruct = Self;
+  type SerializeStructVariant = Self;
+  
+  fn serialize_bool(self, v: bool) -> Result<(), SerdeError> {
+    self.writer.write_bool(v);
+    Ok(())
+  }
+  
+  fn serialize_i8(self, v: i8) -> Result<(), SerdeError> {
+    self.writer.write_i8(v);
+    Ok(())
+  }
+  
+  fn serialize_i16(self, v: i16) -> Result<(), SerdeError> {
+    self.writer.write_i16(v);
+    Ok(())
+  }
+  
+  fn serialize_i32(self, v: i32) -> Result<(), SerdeError> {
+    self.writer.write_i32(v);
+    Ok(())
+  }
+  
+  fn serialize_i64(self, v: i64) -> Result<(), SerdeError> {
+    self.writer.write_i64(v);
+    Ok(())
+  }
+  
+  fn serialize_u8(self, v: u8) -> Result<(), SerdeError> {
+    self.writer.write_u8(v);
+    Ok(())
+  }
+  
+  fn serialize_u16(self, v: u16) -> Result<(), SerdeError> {
+    self.writer.write_u16(v);
+    Ok(())
+  }
+  
+  fn serialize_u32(self, v: u32) -> Result<(), SerdeError> {
+    self.writer.write_u32(v);
+    Ok(())
+  }
+  
+  fn serialize_u64(self, v: u64) -> Result<(), SerdeError> {
+    self.writer.write_u64(v);
+    Ok(())
+  }
+  
+  fn serialize_f32(self, v: f32) -> Result<(), SerdeError> {
+    self.writer.write_f32(v);
+    Ok(())
+  }
+  
+  fn serialize_f64(self, v: f64) -> Result<(), SerdeError> {
+    self.writer.write_f64(v);
+    Ok(())
+  }
+  
+  fn serialize_char(self, v: char) -> Result<(), SerdeError> {
+    self.writer.write_u32(v as u32);
+    Ok(())
+  }
+  
+  fn serialize_str(self, v: &str) -> Result<(), SerdeError> {
+    self.writer.write_str(v);
+    Ok(())
+  }
+  
+  fn serialize_bytes(self, v: &[u8]) -> Result<(), SerdeError> {
+    if self.in_binder_object {
+      self.in_binder_object = false;
+      let obj_ref = ObjectRef::try_from_bytes(v)
+        .map_err(|_| SerdeError::Custom("invalid binder object".to_string()))?;
+      self.writer.write_obj_ref(obj_ref);
+      return Ok(());
+    }
+    
+    self.writer.write_u8_slice(v);
+    Ok(())
+  }
+  
+  fn serialize_none(self) -> Result<(), SerdeError> {
+    self.writer.write_bool(false);
+    Ok(())
+  }
+  
+  fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), SerdeError> {
+    self.writer.write_bool(true);
+    value.serialize(self)
+  }
+  
+  fn serialize_unit(self) -> Result<(), SerdeError> {
+    Ok(())
+  }
+  
+  fn serialize_unit_struct(self, _name: &'static str) -> Result<(), SerdeError> {
+    Ok(())
+  }
+  
+  fn serialize_unit_variant(self, _name: &'static str, variant_index: u32, _variant: &'static str) -> Result<(), SerdeError> {
+    self.writer.write_u32(variant_index);
+    Ok(())
+  }
+  
+  fn serialize_newtype_struct<T: Serialize + ?Sized>(self, name: &'static str, value: &T) -> Result<(), SerdeError> {
+    self.in_binder_object = name == BINDER_OBJECT_NAME;
+    value.serialize(self)
+  }
+  
+  fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, variant_index: u32, _variant: &'static str, value: &T) -> Result<(), SerdeError> {
+    self.writer.write_u32(variant_index);
+    value.serialize(self)
+  }
+  
+  fn serialize_seq(self, len: Option<usize>) -> Result<Self, SerdeError> {
+    self.write_length(len)?;
+    Ok(self)
+  }
+  
+  fn serialize_tuple(self, _len: usize) -> Result<Self, SerdeError> {
+    Ok(self)
+  }
+  
+  fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, SerdeError> {
+    Ok(self)
+  }
+  
+  fn serialize_tuple_variant(self, _name: &'static str, variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self, SerdeError> {
+    self.writer.write_u32(variant_index);
+    Ok(self)
+  }
+  
+  fn serialize_map(self, len: Option<usize>) -> Result<Self, SerdeError> {
+    self.write_length(len)?;
+    Ok(self)
+  }
+  
+  fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, SerdeError> {
+    Ok(self)
+  }
+  
+  fn serialize_struct_variant(self, _name: &'static str, variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self, SerdeError> {
+    self.writer.write_u32(variant_index);
+    Ok(self)
+  }
+  
+  fn is_human_readable(&self) -> bool {
+    false
+  }
+}
+
+macro_rules! impl_compound {
+  ($trait:ident, $func:ident) => {
+    impl<'packet, Format: WriteFormat<'packet>> ser::$trait for &mut Serializer<'_, 'packet, '_, Format> {
+      type Ok = ();
+      type Error = SerdeError;
+      
+      fn $func<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        value.serialize(&mut **self)
+      }
+      
+      fn end(self) -> Result<(), SerdeError> {
+        Ok(())
+      }
+    }
+  };
+}
+
+impl_compound!(SerializeSeq, serialize_element);
+impl_compound!(SerializeTuple, serialize_element);
+impl_compound!(SerializeTupleStruct, serialize_field);
+impl_compound!(SerializeTupleVariant, serialize_field);
+
+impl<'packet, Format: WriteFormat<'packet>> ser::SerializeMap for &mut Serializer<'_, 'packet, '_, Format> {
+  type Ok = ();
+  type Error = SerdeError;
+  
+  fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+    key.serialize(&mut **self)
+  }
+  
+  fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+    value.serialize(&mut **self)
+  }
+  
+  fn end(self) -> Result<(), SerdeError> {
+    Ok(())
+  }
+}
+
+impl<'packet, Format: WriteFormat<'packet>> ser::SerializeStruct for &mut Serializer<'_, 'packet, '_, Format> {
+  type Ok = ();
+  type Error = SerdeError;
+  
+  fn serialize_field<T: Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), SerdeError> {
+    value.serialize(&mut **self)
+  }
+  
+  fn end(self) -> Result<(), SerdeError> {
+    Ok(())
+  }
+}
+
+impl<'packet, Format: WriteFormat<'packet>> ser::SerializeStructVariant for &mut Serializer<'_, 'packet, '_, Format> {
+  type Ok = ();
+  type Error = SerdeError;
+  
+  fn serialize_field<T: Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), SerdeError> {
+    value.serialize(&mut **self)
+  }
+  
+  fn end(self) -> Result<(), SerdeError> {
+    Ok(())
+  }
+}